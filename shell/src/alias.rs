@@ -0,0 +1,324 @@
+// alias.rs
+//
+// Aliases: after `alias ll='ls -l -a'`, `ll` stands for `ls -l -a` wherever
+// a command name is expected. They are expanded in the tokens of a command
+// line before it is parsed, the way POSIX describes: the value is split into
+// tokens of its own, so it may hold operators such as `|` or `;`; when it
+// ends in a blank, the word after the alias is checked for an alias too; and
+// an alias is not expanded again inside its own expansion, so that
+// `alias ls='ls -F'` works. Only a name typed without quotes is expanded, so
+// `\ls` or `'ls'` runs the command itself.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::getopt::{self, Args, Opt, Spec};
+use crate::lexer::{self, Operator, Token, Word, WordPart};
+use crate::state::Shell;
+use crate::stream::Io;
+use crate::vars;
+
+/// Characters an alias name may not contain.
+const NOT_IN_NAME: &str = " \t\n'\"\\$`/=;|&()<>";
+
+pub const ALIAS: Spec = Spec::new(
+    "Define aliases, or print them, all of them by default.",
+    "[name[=value] ...]",
+    &[Opt::flag('p', "print", "print every alias")],
+);
+
+pub const UNALIAS: Spec = Spec::new(
+    "Remove aliases.",
+    "[name ...]",
+    &[Opt::flag('a', "all", "remove every alias")],
+);
+
+/// `alias [name[=value]...]`: defines the aliases given with a value and
+/// prints the others. Without names every alias is printed.
+pub fn alias(args: Args, shell: &mut Shell, io: &mut Io) -> Result<(), String> {
+    let print = |io: &mut Io, name: &str, value: &str| {
+        writeln!(io.stdout, "alias {}='{}'", name, value.replace('\'', "'\\''"))
+            .map_err(|e| format!("alias: {}", e))
+    };
+    let names = &args.operands;
+    if names.is_empty() || args.has('p') {
+        for (name, value) in &shell.aliases {
+            print(io, name, value)?;
+        }
+    }
+
+    let mut result = Ok(());
+    for arg in names {
+        match arg.split_once('=') {
+            Some((name, _)) if !is_valid_name(name) => {
+                result = Err(format!("alias: `{}': invalid alias name", name));
+            }
+            Some((name, value)) => {
+                shell.aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match shell.aliases.get(*arg) {
+                Some(value) => print(io, arg, value)?,
+                None => result = Err(format!("alias: {}: not found", arg)),
+            },
+        }
+    }
+    result
+}
+
+/// `unalias name...` removes aliases, and `unalias -a` all of them.
+pub fn unalias(args: Args, shell: &mut Shell) -> Result<(), String> {
+    if args.has('a') {
+        shell.aliases.clear();
+        return Ok(());
+    }
+    if args.operands.is_empty() {
+        return Err(format!("unalias: name expected\n{}", getopt::usage("unalias", &UNALIAS)));
+    }
+    let mut result = Ok(());
+    for name in args.operands {
+        if shell.aliases.remove(name).is_none() {
+            result = Err(format!("unalias: {}: not found", name));
+        }
+    }
+    result
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(|c| NOT_IN_NAME.contains(c))
+}
+
+/// A token waiting to be looked at, with the aliases whose expansion it
+/// came from, which are not expanded again.
+struct Pending {
+    token: Token,
+    expanding: Rc<Vec<String>>,
+    /// Set on the word after an alias whose value ends in a blank.
+    check: bool,
+}
+
+/// Expands the aliases in `tokens`, the command line before it is parsed.
+/// A word is a command name at the start of the line, after an operator
+/// such as `;` or `|`, after a reserved word such as `then`, and after
+/// variable assignments. The patterns of a `case` are never command names.
+pub fn expand(tokens: Vec<Token>, aliases: &BTreeMap<String, String>) -> Vec<Token> {
+    if aliases.is_empty() {
+        return tokens;
+    }
+    let none = Rc::new(Vec::new());
+    let mut pending: VecDeque<Pending> = tokens
+        .into_iter()
+        .map(|token| Pending {
+            token,
+            expanding: Rc::clone(&none),
+            check: false,
+        })
+        .collect();
+
+    let mut out = Vec::new();
+    let mut command_name = true;
+    // Inside `[[ ]]` the words are operands, even after `&&`
+    let mut conditional = false;
+    // The `case` commands open around the word, how many, whether the next
+    // `in` is awaited, and whether the word is in a pattern, which lasts from
+    // `in` or `;;` to the `)` that ends it
+    let mut cases = 0;
+    let mut case_in = false;
+    let mut pattern = false;
+    while let Some(next) = pending.pop_front() {
+        let literal = match &next.token {
+            Token::Word(word) => match word.parts.as_slice() {
+                [WordPart::Literal(text)] => Some(text.as_str()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let value = literal
+            .filter(|_| (command_name || next.check) && !conditional && !case_in && !pattern)
+            .filter(|name| !next.expanding.iter().any(|expanding| expanding == name))
+            .and_then(|name| Some((name, aliases.get(name)?)));
+        if let Some((name, value)) = value {
+            // A value that cannot be split, such as one with an open quote,
+            // leaves the word as it is
+            if let Ok(tokens) = lexer::tokenize(value) {
+                let mut expanding = next.expanding.to_vec();
+                expanding.push(name.to_string());
+                let expanding = Rc::new(expanding);
+                if value.ends_with([' ', '\t']) {
+                    if let Some(after) = pending.front_mut() {
+                        after.check = true;
+                    }
+                }
+                for token in tokens.into_iter().rev() {
+                    pending.push_front(Pending {
+                        token,
+                        expanding: Rc::clone(&expanding),
+                        check: false,
+                    });
+                }
+                continue;
+            }
+        }
+
+        if case_in || pattern {
+            match (&next.token, literal) {
+                (_, Some("in")) if case_in => {
+                    case_in = false;
+                    pattern = true;
+                }
+                (_, Some("esac")) if pattern => {
+                    cases -= 1;
+                    pattern = false;
+                }
+                (Token::Operator(Operator::RParen), _) if pattern => {
+                    pattern = false;
+                    command_name = true;
+                    out.push(next.token);
+                    continue;
+                }
+                _ => {}
+            }
+            command_name = false;
+            out.push(next.token);
+            continue;
+        }
+
+        command_name = match &next.token {
+            Token::Operator(Operator::DSemi) => {
+                pattern = cases > 0;
+                false
+            }
+            Token::Operator(op) => !op.is_redirect(),
+            Token::Word(word) => match literal {
+                Some("case") if command_name => {
+                    cases += 1;
+                    case_in = true;
+                    false
+                }
+                // Right after a branch's commands, without `;;`
+                Some("esac") if command_name && cases > 0 => {
+                    cases -= 1;
+                    false
+                }
+                Some("[[") => {
+                    conditional = true;
+                    false
+                }
+                Some("]]") => {
+                    conditional = false;
+                    false
+                }
+                // Reserved words after which a command comes
+                Some("if" | "then" | "elif" | "else" | "while" | "until" | "do" | "{" | "!") => {
+                    command_name
+                }
+                _ => command_name && is_assignment(word),
+            },
+            // The word after `2>` is its target
+            Token::IoNumber(_) => false,
+            _ => command_name,
+        };
+        out.push(next.token);
+    }
+    out
+}
+
+/// Whether `word` is an assignment such as `NAME=value` or `NAME="$value"`.
+fn is_assignment(word: &Word) -> bool {
+    match word.parts.first() {
+        Some(WordPart::Literal(text)) => text
+            .split_once('=')
+            .is_some_and(|(name, _)| vars::is_valid_name(name)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    /// `input` after alias expansion, as the parser reads it.
+    fn expanded(aliases: &[(&str, &str)], input: &str) -> String {
+        let aliases = aliases
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let list = parser::parse_input_with_aliases(input, &aliases)
+            .unwrap()
+            .unwrap();
+        list.to_string()
+    }
+
+    #[test]
+    fn command_names_only() {
+        let aliases = [("ll", "ls -l")];
+        assert_eq!(expanded(&aliases, "ll src"), "ls -l src");
+        assert_eq!(expanded(&aliases, "echo ll"), "echo ll");
+        assert_eq!(
+            expanded(&aliases, "a; ll | ll && ll"),
+            "a; ls -l | ls -l && ls -l"
+        );
+        assert_eq!(
+            expanded(&aliases, "if ll; then ll; fi"),
+            "if ls -l; then ls -l; fi"
+        );
+        assert_eq!(expanded(&aliases, "X=1 ll"), "X=1 ls -l");
+        assert_eq!(expanded(&aliases, "echo > ll"), "echo > ll");
+        assert_eq!(expanded(&aliases, "[[ a && ll ]]"), "[[ a && ll ]]");
+    }
+
+    #[test]
+    fn case_patterns_are_not_command_names() {
+        let aliases = [("ll", "ls -l")];
+        assert_eq!(
+            expanded(&aliases, "case x in\n(ll) ll;;\nll|x) ll\nesac; ll"),
+            "case x in ll) ls -l;; ll | x) ls -l;; esac; ls -l"
+        );
+        assert_eq!(
+            expanded(
+                &aliases,
+                "case x in ll) case y in ll) ll;; esac;; ll) ll;; esac"
+            ),
+            "case x in ll) case y in ll) ls -l;; esac;; ll) ls -l;; esac"
+        );
+        assert_eq!(expanded(&aliases, "echo case ll"), "echo case ll");
+    }
+
+    #[test]
+    fn quoting_skips_the_alias() {
+        let aliases = [("ls", "ls -F")];
+        assert_eq!(expanded(&aliases, "\\ls"), "'l's");
+        assert_eq!(expanded(&aliases, "'ls'"), "'ls'");
+    }
+
+    #[test]
+    fn values_hold_operators() {
+        let aliases = [("count", "ls | wc -l"), ("both", "a; b")];
+        assert_eq!(expanded(&aliases, "count"), "ls | wc -l");
+        assert_eq!(expanded(&aliases, "both && c"), "a; b && c");
+    }
+
+    #[test]
+    fn no_endless_recursion() {
+        assert_eq!(expanded(&[("ls", "ls -F")], "ls"), "ls -F");
+        assert_eq!(expanded(&[("a", "b"), ("b", "a")], "a"), "a");
+        assert_eq!(expanded(&[("a", "b x"), ("b", "c y")], "a"), "c y x");
+    }
+
+    #[test]
+    fn trailing_blank_checks_the_next_word() {
+        let aliases = [("sudo", "sudo "), ("ll", "ls -l"), ("e", "echo")];
+        assert_eq!(expanded(&aliases, "sudo ll"), "sudo ls -l");
+        assert_eq!(expanded(&aliases, "e ll"), "echo ll");
+    }
+
+    #[test]
+    fn names() {
+        assert!(is_valid_name("ll"));
+        assert!(is_valid_name("g++"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("a/b"));
+        assert!(!is_valid_name("a b"));
+    }
+}
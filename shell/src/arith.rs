@@ -0,0 +1,569 @@
+// arith.rs
+//
+// Integer arithmetic for `$(( ))`, `(( ))` and `let`. Expressions use the C
+// operators with their usual precedence, including assignments such as `+=`
+// and `++`, on 64-bit integers that wrap around on overflow. A variable that
+// is unset or empty counts as 0, and one holding an expression is evaluated
+// in turn. Numbers may be written in hex (`0xff`), octal (`017`) or any base
+// from 2 to 64 (`2#1010`, `16#ff`).
+
+use crate::state::Shell;
+
+/// `let expr...`: evaluates each argument in turn. The status is 0 when the
+/// last one is non-zero and 1 otherwise, so `let` works as a condition.
+pub fn let_command(args: Vec<&str>, shell: &mut Shell) -> Result<i32, String> {
+    if args.is_empty() {
+        return Err("let: expression expected".to_string());
+    }
+    let mut value = 0;
+    for arg in args {
+        value = evaluate(shell, arg).map_err(|e| format!("let: {}", e))?;
+    }
+    Ok(i32::from(value == 0))
+}
+
+/// Evaluates `expr`, assigning to variables as it goes.
+pub fn evaluate(shell: &mut Shell, expr: &str) -> Result<i64, String> {
+    evaluate_nested(shell, expr, 0)
+}
+
+/// How deeply variables may refer to further expressions, so that `a=a`
+/// is an error instead of a crash.
+const MAX_DEPTH: usize = 1024;
+
+fn evaluate_nested(shell: &mut Shell, expr: &str, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err("expression recursion level exceeded".to_string());
+    }
+    // Only the expression the user wrote is named, once, whichever of the
+    // expressions its variables hold went wrong
+    let error = |message: String| {
+        if depth == 0 {
+            format!("{}: {}", expr.trim(), message)
+        } else {
+            message
+        }
+    };
+    let tokens = tokenize(expr).map_err(error)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let tree = parser.comma().map_err(error)?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(error(syntax_error(token)));
+    }
+    tree.eval(shell, depth).map_err(error)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    /// An operator or parenthesis, as written.
+    Op(&'static str),
+}
+
+/// Operators, longest first so that tokenizing can take the first match.
+const OPERATORS: [&str; 42] = [
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "++", "--", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "?", ":", "=", "(", ")", ",", "[", "]", "#",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr;
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#' || c == '@' || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(number(&rest[..end])?));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) else {
+                return Err(format!(
+                    "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                    rest
+                ));
+            };
+            // `[`, `]` and `#` are only listed so they are reported by name
+            if matches!(*op, "[" | "]" | "#") {
+                return Err(format!(
+                    "syntax error: operand expected (error token is \"{}\")",
+                    rest
+                ));
+            }
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        }
+    }
+}
+
+/// Reads an integer literal: decimal, `0x` hex, `0` octal or `base#digits`.
+fn number(text: &str) -> Result<i64, String> {
+    let invalid = || format!("value too great for base (error token is \"{}\")", text);
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| invalid())?;
+        if !(2..=64).contains(&base) {
+            return Err(format!(
+                "invalid arithmetic base (error token is \"{}\")",
+                text
+            ));
+        }
+        (base, digits)
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // Up to base 36 letters are digits in either case; beyond that
+        // lowercase comes first, then uppercase, `@` and `_`
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+fn syntax_error(token: &Token) -> String {
+    let text = match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => op.to_string(),
+    };
+    format!("syntax error in expression (error token is \"{}\")", text)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    /// `-x`, `+x`, `!x` or `~x`.
+    Unary(&'static str, Box<Expr>),
+    /// `++x` or `--x`, with the amount added.
+    PreIncrement(String, i64),
+    /// `x++` or `x--`, with the amount added.
+    PostIncrement(String, i64),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `x = value`, or `x op= value`, with the assignment operator.
+    Assign(String, &'static str, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+}
+
+/// Binary operators from loosest to tightest binding, above `?:`.
+const LEVELS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+const ASSIGNMENTS: [&str; 11] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+/// Recursive descent over the tokens, one method per precedence level.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            return Ok(());
+        }
+        Err(match self.tokens.get(self.pos) {
+            Some(token) => syntax_error(token),
+            None => format!("syntax error: `{}' expected", op),
+        })
+    }
+
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut expr = self.assignment()?;
+        while self.eat(",") {
+            expr = Expr::Comma(Box::new(expr), Box::new(self.assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, String> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if ASSIGNMENTS.contains(op) {
+                let name = name.clone();
+                let op = *op;
+                self.pos += 2;
+                let value = self.assignment()?;
+                return Ok(Expr::Assign(name, op, Box::new(value)));
+            }
+        }
+        let expr = self.conditional()?;
+        // Anything else followed by `=` cannot be assigned to
+        if let Some(op) = self.peek_op() {
+            if ASSIGNMENTS.contains(&op) {
+                return Err(format!(
+                    "attempted assignment to non-variable (error token is \"{}\")",
+                    op
+                ));
+            }
+        }
+        Ok(expr)
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.comma()?;
+        self.expect(":")?;
+        let otherwise = self.assignment()?;
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(ops) = LEVELS.get(level) else {
+            return self.power();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| ops.contains(op)) {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// `**` binds tighter than `*` and groups to the right.
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.unary()?;
+        if self.eat("**") {
+            let exponent = self.power()?;
+            return Ok(Expr::Binary("**", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let Some(op) = self.peek_op() else {
+            return self.postfix();
+        };
+        match op {
+            "++" | "--" => {
+                self.pos += 1;
+                let step = if op == "++" { 1 } else { -1 };
+                if let Some(Token::Name(name)) = self.tokens.get(self.pos) {
+                    let name = name.clone();
+                    self.pos += 1;
+                    return Ok(Expr::PreIncrement(name, step));
+                }
+                // Without a variable, `++5` is just `+(+5)`
+                let sign = if op == "++" { "+" } else { "-" };
+                let operand = Expr::Unary(sign, Box::new(self.unary()?));
+                Ok(Expr::Unary(sign, Box::new(operand)))
+            }
+            "-" | "+" | "!" | "~" => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let expr = self.primary()?;
+        if let Expr::Variable(name) = &expr {
+            for (op, step) in [("++", 1), ("--", -1)] {
+                if self.eat(op) {
+                    return Ok(Expr::PostIncrement(name.clone(), step));
+                }
+            }
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            return Err("syntax error: operand expected".to_string());
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Name(name) => Ok(Expr::Variable(name)),
+            Token::Op("(") => {
+                let expr = self.comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Op(op) => Err(format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                op
+            )),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+        match self {
+            Expr::Number(n) => Ok(*n),
+            Expr::Variable(name) => variable(shell, name, depth),
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(shell, depth)?;
+                Ok(match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => i64::from(value == 0),
+                    "~" => !value,
+                    _ => value,
+                })
+            }
+            Expr::PreIncrement(name, step) => {
+                let value = variable(shell, name, depth)?.wrapping_add(*step);
+                shell.vars.set(name, &value.to_string());
+                Ok(value)
+            }
+            Expr::PostIncrement(name, step) => {
+                let value = variable(shell, name, depth)?;
+                shell.vars.set(name, &value.wrapping_add(*step).to_string());
+                Ok(value)
+            }
+            // `&&` and `||` skip their right side, side effects included
+            Expr::Binary("&&", left, right) => Ok(i64::from(
+                left.eval(shell, depth)? != 0 && right.eval(shell, depth)? != 0,
+            )),
+            Expr::Binary("||", left, right) => Ok(i64::from(
+                left.eval(shell, depth)? != 0 || right.eval(shell, depth)? != 0,
+            )),
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(shell, depth)?;
+                let right = right.eval(shell, depth)?;
+                apply(op, left, right)
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if condition.eval(shell, depth)? != 0 {
+                    then.eval(shell, depth)
+                } else {
+                    otherwise.eval(shell, depth)
+                }
+            }
+            Expr::Assign(name, op, value) => {
+                let value = value.eval(shell, depth)?;
+                let value = match op.strip_suffix('=').filter(|op| !op.is_empty()) {
+                    Some(op) => apply(op, variable(shell, name, depth)?, value)?,
+                    None => value,
+                };
+                shell.vars.set(name, &value.to_string());
+                Ok(value)
+            }
+            Expr::Comma(first, second) => {
+                first.eval(shell, depth)?;
+                second.eval(shell, depth)
+            }
+        }
+    }
+}
+
+/// The value of a variable: 0 if unset or empty, otherwise its text
+/// evaluated as an expression of its own.
+fn variable(shell: &mut Shell, name: &str, depth: usize) -> Result<i64, String> {
+    let value = shell.vars.get(name).unwrap_or_default().to_string();
+    if value.trim().is_empty() {
+        return Ok(0);
+    }
+    evaluate_nested(shell, &value, depth + 1)
+}
+
+fn apply(op: &str, left: i64, right: i64) -> Result<i64, String> {
+    let division_by_zero = || format!("division by 0 (error token is \"{}\")", right);
+    Ok(match op {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" if right == 0 => return Err(division_by_zero()),
+        "/" => left.wrapping_div(right),
+        "%" if right == 0 => return Err(division_by_zero()),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err("exponent less than 0".to_string()),
+        "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+        // Like C on x86, only the low six bits of the shift count are used
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "<" => i64::from(left < right),
+        "<=" => i64::from(left <= right),
+        ">" => i64::from(left > right),
+        ">=" => i64::from(left >= right),
+        "==" => i64::from(left == right),
+        "!=" => i64::from(left != right),
+        "&" => left & right,
+        "^" => left ^ right,
+        "|" => left | right,
+        _ => {
+            return Err(format!(
+                "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                op
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(expr: &str) -> i64 {
+        evaluate(&mut Shell::default(), expr).unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7);
+        assert_eq!(value("(1 + 2) * 3"), 9);
+        assert_eq!(value("2 ** 3 ** 2"), 512);
+        assert_eq!(value("-2 ** 2"), 4);
+        assert_eq!(value("1 << 2 + 1"), 8);
+        assert_eq!(value("1 | 2 ^ 3 & 1"), 3);
+        assert_eq!(value("1 < 2 == 1"), 1);
+        assert_eq!(value("0 || 2 && 3"), 1);
+        assert_eq!(value("1 ? 2 : 0 ? 3 : 4"), 2);
+        assert_eq!(value("7 % 4 * 2"), 6);
+        assert_eq!(value("!0 + ~0"), 0);
+        assert_eq!(value("1, 2, 3"), 3);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(value("0xff"), 255);
+        assert_eq!(value("017"), 15);
+        assert_eq!(value("2#1010"), 10);
+        assert_eq!(value("16#ff"), 255);
+        assert_eq!(value("64#_"), 63);
+        assert!(evaluate(&mut Shell::default(), "08").is_err());
+        assert!(evaluate(&mut Shell::default(), "65#1").is_err());
+    }
+
+    #[test]
+    fn assignments() {
+        let mut shell = Shell::default();
+        assert_eq!(evaluate(&mut shell, "x = 5").unwrap(), 5);
+        assert_eq!(evaluate(&mut shell, "x += 2").unwrap(), 7);
+        assert_eq!(evaluate(&mut shell, "x *= 3").unwrap(), 21);
+        assert_eq!(evaluate(&mut shell, "x <<= 1").unwrap(), 42);
+        assert_eq!(evaluate(&mut shell, "x %= 5").unwrap(), 2);
+        assert_eq!(evaluate(&mut shell, "x++").unwrap(), 2);
+        assert_eq!(evaluate(&mut shell, "++x").unwrap(), 4);
+        assert_eq!(evaluate(&mut shell, "x--").unwrap(), 4);
+        assert_eq!(evaluate(&mut shell, "y = x = 9").unwrap(), 9);
+        assert_eq!(shell.vars.get("x"), Some("9"));
+        assert_eq!(shell.vars.get("y"), Some("9"));
+    }
+
+    #[test]
+    fn variables() {
+        let mut shell = Shell::default();
+        assert_eq!(evaluate(&mut shell, "unset + 1").unwrap(), 1);
+        shell.vars.set("a", "2 + 3");
+        shell.vars.set("b", "a * 2");
+        assert_eq!(evaluate(&mut shell, "b").unwrap(), 10);
+        shell.vars.set("e", "");
+        assert_eq!(evaluate(&mut shell, "e").unwrap(), 0);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let mut shell = Shell::default();
+        let e = evaluate(&mut shell, "1 / 0").unwrap_err();
+        assert_eq!(e, "1 / 0: division by 0 (error token is \"0\")");
+        assert!(evaluate(&mut shell, "5 % 0").is_err());
+        assert!(evaluate(&mut shell, "x = 1, x /= 0").is_err());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let mut shell = Shell::default();
+        assert!(evaluate(&mut shell, "1 +").is_err());
+        assert!(evaluate(&mut shell, "(1").is_err());
+        assert!(evaluate(&mut shell, "1 2").is_err());
+        assert!(evaluate(&mut shell, "1 $ 2").is_err());
+        assert!(evaluate(&mut shell, "3 = 4").is_err());
+        assert_eq!(evaluate(&mut shell, "  ").unwrap(), 0);
+    }
+
+    #[test]
+    fn recursion_limit() {
+        // As deep as the shell's main thread allows, more than a test thread's
+        let test = || {
+            let mut shell = Shell::default();
+            shell.vars.set("a", "a");
+            let e = evaluate(&mut shell, "a").unwrap_err();
+            assert_eq!(e, "a: expression recursion level exceeded");
+
+            // An error deep inside is named after the outer expression, once
+            shell.vars.set("a", "b");
+            shell.vars.set("b", "1 +");
+            let e = evaluate(&mut shell, "a * 2").unwrap_err();
+            assert!(e.starts_with("a * 2: syntax error"), "{}", e);
+            assert_eq!(e.matches(": ").count(), 2, "{}", e);
+        };
+        let thread = std::thread::Builder::new().stack_size(8 * 1024 * 1024).spawn(test);
+        thread.unwrap().join().unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+// builtins.rs
+//
+// Dispatch table for the commands the shell implements itself. Each builtin
+// writes through the `Io` it is given, so it works the same at the terminal
+// and inside a pipeline.
+
+use std::io::Write;
+
+use crate::getopt::{self, Args, Spec};
+use crate::state::Shell;
+use crate::stream::Io;
+use crate::{
+    alias, arith, cat, cd, cp, echo, env, exit, export, functions, history, jobs, loops, ls, mkdir,
+    mv, pwd, rm, set, shift, shopt, signals, source, test, unset,
+};
+
+/// Names handled by `run` instead of being looked up on `PATH`.
+pub const BUILTINS: [&str; 35] = [
+    "echo", "cd", "ls", "pwd", "cat", "cp", "rm", "mv", "mkdir", "exit", "set", "export",
+    "unset", "env", "printenv", "shopt", "jobs", "fg", "bg", "wait", "disown", "shift", "break",
+    "continue", "local", "return", "declare", "let",
+    "test", "[", "history", "source", ".", "alias", "unalias",
+];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+/// How the builtins that take options are called, for `getopt` to parse
+/// their arguments with. The others read their arguments themselves.
+pub fn spec(command: &str) -> Option<&'static Spec> {
+    Some(match command {
+        "cd" => &cd::SPEC,
+        "ls" => &ls::SPEC,
+        "pwd" => &pwd::SPEC,
+        "cat" => &cat::SPEC,
+        "cp" => &cp::SPEC,
+        "rm" => &rm::SPEC,
+        "mv" => &mv::SPEC,
+        "mkdir" => &mkdir::SPEC,
+        "exit" => &exit::SPEC,
+        "export" => &export::SPEC,
+        "unset" => &unset::SPEC,
+        "env" => &env::ENV,
+        "printenv" => &env::PRINTENV,
+        "shopt" => &shopt::SPEC,
+        "jobs" => &jobs::JOBS,
+        "fg" => &jobs::FG,
+        "bg" => &jobs::BG,
+        "wait" => &jobs::WAIT,
+        "disown" => &jobs::DISOWN,
+        "shift" => &shift::SPEC,
+        "break" => &loops::BREAK,
+        "continue" => &loops::CONTINUE,
+        "local" => &functions::LOCAL,
+        "return" => &functions::RETURN,
+        "declare" => &functions::DECLARE,
+        "history" => &history::SPEC,
+        "source" | "." => &source::SPEC,
+        "alias" => &alias::ALIAS,
+        "unalias" => &alias::UNALIAS,
+        _ => return None,
+    })
+}
+
+/// Runs the builtin `command` and returns its exit status. Errors are
+/// reported on the command's stderr, after where the command was read in a
+/// script, and give a status of 1; a usage error gives 2.
+pub fn run(shell: &mut Shell, command: &str, args: Vec<&str>, io: &mut Io) -> i32 {
+    // Options are sorted out here, the same way for every builtin with a spec
+    let parsed = match spec(command).map(|spec| (spec, getopt::parse(command, spec, &args))) {
+        Some((spec, Ok(None))) => {
+            let _ = write!(io.stdout, "{}", getopt::help(command, spec));
+            return 0;
+        }
+        Some((_, Err(e))) => {
+            let _ = writeln!(io.stderr, "{}{}", shell.location, e);
+            return 2;
+        }
+        Some((_, Ok(Some(parsed)))) => parsed,
+        None => Args::default(),
+    };
+    let operands = parsed.operands.clone();
+
+    let result: Result<(), String> = match command {
+        "exit" => match exit::exit_code(&operands, shell.last_status) {
+            Ok(code) => exit::exit(code),
+            Err(e) => {
+                let _ = writeln!(io.stderr, "{}{}", shell.location, e);
+                exit::exit(2);
+            }
+        },
+        "cd" => cd::handle_cd(operands, &mut shell.vars),
+        "ls" => ls::handlels(parsed, io),
+        "pwd" => pwd::pwd(io),
+        "cat" => cat::cat_files(&operands, io),
+        "cp" => cp::handle_cp(&parsed),
+        "rm" => rm::handle_rm(parsed, io),
+        "mkdir" => mkdir::mkdir_with_flag(&parsed),
+        "mv" => mv::handle_mv(&operands),
+        "echo" => echo::echo(&args, io).map_err(|e| format!("Error: {}", e)),
+        "set" => set::handle_set(args, shell, io),
+        "export" => export::export(parsed, &mut shell.vars, io),
+        "unset" => unset::unset(parsed, shell),
+        "env" => match env::env(parsed, &shell.vars, io) {
+            Ok(status) => return status,
+            Err(e) => {
+                let _ = writeln!(io.stderr, "{}{}", shell.location, e);
+                return 127;
+            }
+        },
+        "test" | "[" => match test::test(command, args) {
+            Ok(status) => return status,
+            Err(e) => {
+                let _ = writeln!(io.stderr, "{}{}", shell.location, e);
+                return 2;
+            }
+        },
+        "printenv" => env::printenv(operands, &shell.vars, io),
+        "shopt" => shopt::shopt(parsed, &mut shell.options, io),
+        "jobs" => jobs::jobs(parsed, shell, io),
+        "fg" | "wait" | "return" | "let" | "source" | "." => {
+            let result = match command {
+                "fg" => jobs::fg(operands, shell, io),
+                "wait" => jobs::wait(operands, shell, io),
+                "let" => arith::let_command(args, shell),
+                "source" | "." => source::source(command, operands, shell),
+                _ => functions::return_from(operands, shell),
+            };
+            match result {
+                Ok(status) => return status,
+                Err(e) => Err(e),
+            }
+        }
+        "bg" => jobs::bg(operands, shell, io),
+        "disown" => jobs::disown(parsed, shell),
+        "shift" => shift::shift(operands, shell),
+        "break" => loops::break_loop(operands, shell),
+        "continue" => loops::continue_loop(operands, shell),
+        "local" => functions::local(operands, shell),
+        "declare" => functions::declare(parsed, shell, io),
+        "history" => history::history(parsed, shell, io),
+        "alias" => alias::alias(parsed, shell, io),
+        "unalias" => alias::unalias(parsed, shell),
+        _ => Err(format!("{}: not a builtin", command)),
+    };
+
+    // Ctrl+C stopped the builtin early, so whatever it reported is moot. The
+    // flag stays set so that the rest of the command line is skipped as well
+    if signals::interrupted() {
+        return 130;
+    }
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            // An empty message means there is nothing left to report
+            if !e.is_empty() {
+                let _ = writeln!(io.stderr, "{}{}", shell.location, e);
+            }
+            1
+        }
+    }
+}
@@ -1,35 +1,80 @@
 use std::fs::File;
-use std::io::{ BufRead, BufReader };
+use std::io::{ BufRead, BufReader, Read, Write };
 use std::path::Path;
 
-pub fn cat(filename: &str) {
+use crate::getopt::Spec;
+use crate::signals;
+use crate::stream::Io;
+
+pub const SPEC: Spec = Spec::new(
+    "Print files one after another; standard input without any, or for -.",
+    "[file ...]",
+    &[],
+);
+
+/// Prints each of `files` in turn. A file that cannot be read is reported
+/// after the others have been printed.
+pub fn cat_files(files: &[&str], io: &mut Io) -> Result<(), String> {
+    if files.is_empty() {
+        return cat(None, io);
+    }
+    let mut result = Ok(());
+    for &file in files {
+        let file = if file == "-" { None } else { Some(file) };
+        if let Err(e) = cat(file, io) {
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+    result
+}
+
+pub fn cat(filename: Option<&str>, io: &mut Io) -> Result<(), String> {
+    // Without a filename, copy standard input so cat can sit inside a pipeline
+    let Some(filename) = filename else {
+        let reader = BufReader::new(io.stdin.clone());
+        return copy_lines(reader, "standard input", io);
+    };
+
     let path = Path::new(filename);
-    let file = match File::open(&path) {
+    let file = match File::open(path) {
         Ok(file) => file,
         Err(e) => {
-            match e.kind() {
+            return Err(match e.kind() {
                 std::io::ErrorKind::NotFound => {
-                    eprintln!("File '{}' not found", filename);
+                    format!("File '{}' not found", filename)
                 }
                 std::io::ErrorKind::PermissionDenied => {
-                    eprintln!("Permission denied for file '{}'", filename);
+                    format!("Permission denied for file '{}'", filename)
                 }
                 _ => {
-                    eprintln!("Error opening file '{}': {}", filename, e);
+                    format!("Error opening file '{}': {}", filename, e)
                 }
-            }
-            return;
+            });
         }
     };
     let reader = BufReader::new(file);
+    copy_lines(reader, filename, io)
+}
 
+fn copy_lines<R: Read>(reader: BufReader<R>, name: &str, io: &mut Io) -> Result<(), String> {
     for line in reader.lines() {
+        // Each line is a safe point to stop at when Ctrl+C is pressed
+        if signals::interrupted() {
+            return Ok(());
+        }
         match line {
-            Ok(line) => println!("{}", line),
+            Ok(line) => {
+                // The reader went away (e.g. `cat file | head -1`), so stop quietly
+                if writeln!(io.stdout, "{}", line).is_err() {
+                    return Ok(());
+                }
+            }
             Err(e) => {
-                eprintln!("Error reading file '{}': {}", filename, e);
-                return;
+                return Err(format!("Error reading file '{}': {}", name, e));
             }
         }
     }
+    Ok(())
 }
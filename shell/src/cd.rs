@@ -1,20 +1,36 @@
+// cd.rs
+//
+// The `cd` builtin. Besides changing the working directory it keeps `PWD` up
+// to date, which the prompt falls back to when the directory is removed.
+
 use std::env;
 use std::path::Path;
 
-pub fn handle_cd(args: Vec<&str>) -> Result<(), String> {
-    if let Some(dir) = args.get(0) {
-        // check if the directory exists 
-        if !Path::new(dir).exists() {
-            return Err(format!("cd: {}: No such file or directory", dir));
-        }
+use crate::getopt::Spec;
+use crate::vars::Variables;
 
-        set_current_dir(dir)
-    } else {
+pub const SPEC: Spec = Spec::new(
+    "Change the working directory, to $HOME by default.",
+    "[dir]",
+    &[],
+);
+
+pub fn handle_cd(args: Vec<&str>, vars: &mut Variables) -> Result<(), String> {
+    let dir = match args.first() {
+        Some(dir) => dir,
         // if no directory is given, change to the home directory
-        let home = env::var("HOME").unwrap();
-        set_current_dir(&home)
+        None => vars.get("HOME").ok_or_else(|| "cd: HOME not set".to_string())?,
+    }
+    .to_string();
+    // check if the directory exists
+    if !Path::new(&dir).exists() {
+        return Err(format!("cd: {}: No such file or directory", dir));
     }
 
+    set_current_dir(&dir)?;
+    let current = env::current_dir().map_err(|e| format!("cd: {}", e))?;
+    vars.set("PWD", &current.to_string_lossy());
+    Ok(())
 }
 
 fn set_current_dir(dir: &str) -> Result<(), String> {
@@ -23,5 +39,4 @@ fn set_current_dir(dir: &str) -> Result<(), String> {
     } else {
         Ok(())
     }
-    
-}
\ No newline at end of file
+}
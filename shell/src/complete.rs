@@ -0,0 +1,289 @@
+// complete.rs
+//
+// Tab completion for the line editor. What the word before the cursor
+// completes to depends on where it stands: the first word of a command is an
+// alias, a builtin, a function or a program on `PATH`, a word starting with `-` after
+// a builtin is one of its options, `$NAME` is a variable, `~name` a user's
+// home directory, and anything else a file name, only directories after `cd`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use crate::builtins;
+use crate::expand;
+use crate::state::Shell;
+use crate::vars;
+
+/// Words after which the next one is a command name again.
+const KEYWORDS: [&str; 9] = ["if", "then", "elif", "else", "while", "until", "do", "!", "{"];
+
+/// Characters that are quoted with a backslash when they appear in a file
+/// name put into the line.
+const SPECIAL: &str = " \t\n'\"\\$`&;|()<>*?[]!{}#";
+
+/// What the word before the cursor may become.
+pub struct Completion {
+    /// Byte offset in the text where the word starts.
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+pub struct Candidate {
+    /// What replaces the word.
+    pub text: String,
+    /// How the candidate is listed, such as a file name without its directory.
+    pub display: String,
+}
+
+/// Completes the last word of `text`, the line up to the cursor.
+pub fn complete(shell: &Shell, text: &str) -> Completion {
+    let (start, command, redirect) = current_word(text);
+    let word = &text[start..];
+    let candidates = if let Some(name) = variable_start(word) {
+        variables(shell, &word[..name], &word[name..])
+    } else if word.starts_with('~') && !word.contains('/') {
+        user_names(&word[1..])
+    } else if command.is_none() && !redirect && !word.contains('/') {
+        commands(shell, word)
+    } else if word.starts_with('-') && !redirect {
+        options(command.as_deref().unwrap_or_default(), word)
+    } else {
+        paths(shell, word, command.as_deref() == Some("cd") && !redirect)
+    };
+    Completion { start, candidates }
+}
+
+/// Finds the word being typed at the end of `text`. Returns where it starts,
+/// the name of the command it belongs to, if it is not that name itself, and
+/// whether it is the target of a redirection.
+fn current_word(text: &str) -> (usize, Option<String>, bool) {
+    let mut start = 0;
+    // The words of the command typed so far, and whether a `<` or `>` is
+    // waiting for its target
+    let mut words: Vec<&str> = Vec::new();
+    let mut redirect = false;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '\'' | '"' => quote = Some(c),
+            c if c.is_whitespace() || ";|&()<>".contains(c) => {
+                if start < i {
+                    if redirect {
+                        redirect = false;
+                    } else {
+                        words.push(&text[start..i]);
+                    }
+                }
+                match c {
+                    ';' | '|' | '&' | '(' => {
+                        words.clear();
+                        redirect = false;
+                    }
+                    '<' | '>' => redirect = true,
+                    _ => {}
+                }
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    // Assignments and keywords such as `then` come before the command name
+    let command = words
+        .into_iter()
+        .find(|word| !KEYWORDS.contains(word) && !is_assignment(word))
+        .map(unquote);
+    (start, command, redirect)
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=')
+        .is_some_and(|(name, _)| vars::is_valid_name(name))
+}
+
+/// Where the name starts if `word` ends in `$name` or `${name`.
+fn variable_start(word: &str) -> Option<usize> {
+    let dollar = word.rfind('$')?;
+    let brace = usize::from(word[dollar + 1..].starts_with('{'));
+    let start = dollar + 1 + brace;
+    let name = &word[start..];
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    valid.then_some(start)
+}
+
+/// Variables whose names start with `name`, after `prefix` (which ends in
+/// `$` or `${`).
+fn variables(shell: &Shell, prefix: &str, name: &str) -> Vec<Candidate> {
+    let close = if prefix.ends_with('{') { "}" } else { "" };
+    shell
+        .vars
+        .iter()
+        .filter(|(var, _)| var.starts_with(name))
+        .map(|(var, _)| Candidate {
+            text: format!("{}{}{}", prefix, var, close),
+            display: var.to_string(),
+        })
+        .collect()
+}
+
+/// `~name/` for the users whose names start with `prefix`.
+fn user_names(prefix: &str) -> Vec<Candidate> {
+    // SAFETY: the user database is only read here, on the shell's own thread
+    let users = unsafe { users::all_users() };
+    let names: BTreeSet<String> = users
+        .map(|user| user.name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names
+        .into_iter()
+        .map(|name| Candidate {
+            text: format!("~{}/", name),
+            display: format!("~{}", name),
+        })
+        .collect()
+}
+
+/// Aliases, builtins, functions and programs on `PATH` whose names start
+/// with `word`.
+fn commands(shell: &Shell, word: &str) -> Vec<Candidate> {
+    let prefix = unquote(word);
+    let mut names: BTreeSet<String> = builtins::BUILTINS
+        .iter()
+        .map(|name| name.to_string())
+        .chain(shell.aliases.keys().cloned())
+        .chain(shell.functions.keys().cloned())
+        .filter(|name| name.starts_with(&prefix))
+        .collect();
+    for dir in shell.vars.get("PATH").unwrap_or_default().split(':') {
+        let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(&prefix) || names.contains(&name) {
+                continue;
+            }
+            let executable = fs::metadata(entry.path())
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+            if executable {
+                names.insert(name);
+            }
+        }
+    }
+    names
+        .into_iter()
+        .map(|name| Candidate {
+            text: escape(&name),
+            display: name,
+        })
+        .collect()
+}
+
+/// The options of builtin `command` that start with `word`, short ones
+/// first, as its spec gives them.
+fn options(command: &str, word: &str) -> Vec<Candidate> {
+    let Some(spec) = builtins::spec(command) else {
+        return Vec::new();
+    };
+    let shorts = spec.options.iter().map(|option| format!("-{}", option.short));
+    let longs = spec
+        .options
+        .iter()
+        .filter(|option| !option.long.is_empty())
+        .map(|option| format!("--{}", option.long))
+        .chain(std::iter::once("--help".to_string()));
+    shorts
+        .chain(longs)
+        .filter(|option| option.starts_with(word))
+        .map(|option| Candidate {
+            text: option.clone(),
+            display: option,
+        })
+        .collect()
+}
+
+/// Files in the directory part of `word` whose names start with the rest of
+/// it. Directories end in `/`; hidden files only show up once a `.` is typed.
+fn paths(shell: &Shell, word: &str, directories_only: bool) -> Vec<Candidate> {
+    let typed = unquote(word);
+    let (dir, prefix) = match typed.rfind('/') {
+        Some(slash) => typed.split_at(slash + 1),
+        None => ("", typed.as_str()),
+    };
+    // `~/` and `~user/` are looked up in the home directory but stay as typed
+    let lookup = match dir.strip_prefix('~') {
+        Some(rest) => {
+            let end = rest.find('/').unwrap_or(rest.len());
+            match expand::home_dir(shell, &rest[..end]) {
+                Some(home) => format!("{}{}", home, &rest[end..]),
+                None => dir.to_string(),
+            }
+        }
+        None => dir.to_string(),
+    };
+    let Ok(entries) = fs::read_dir(if lookup.is_empty() { "." } else { &lookup }) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<Candidate> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // Following links, so that a link to a directory counts as one
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+            if directories_only && !is_dir {
+                return None;
+            }
+            let slash = if is_dir { "/" } else { "" };
+            Some(Candidate {
+                text: format!("{}{}{}", escape(dir), escape(&name), slash),
+                display: format!("{}{}", name, slash),
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.text.cmp(&b.text));
+    candidates
+}
+
+/// The word as the shell will see it, without its quotes and backslashes.
+fn unquote(word: &str) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\\') => out.extend(chars.next()),
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (_, c) => out.push(c),
+        }
+    }
+    out
+}
+
+/// Quotes the characters of a name that the shell would otherwise act on.
+fn escape(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if SPECIAL.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -2,6 +2,42 @@ use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
+use crate::getopt::{self, Args, Opt, Spec};
+use crate::signals;
+
+pub const SPEC: Spec = Spec::new(
+    "Copy a file or directory, or several into a directory.",
+    "source ... destination",
+    &[
+        Opt::flag('r', "recursive", "copy directories and what is in them"),
+        Opt::flag('R', "", "the same as -r"),
+    ],
+);
+
+pub fn handle_cp(args: &Args) -> Result<(), String> {
+    let Some((destination, sources)) = args.operands.split_last() else {
+        return Err(format!("cp: missing operand\n{}", getopt::usage("cp", &SPEC)));
+    };
+    if sources.is_empty() {
+        let message = format!("missing destination file operand after '{}'", destination);
+        return Err(format!("cp: {}\n{}", message, getopt::usage("cp", &SPEC)));
+    }
+    let recursive = args.has('r') || args.has('R');
+    if sources.len() > 1 {
+        return cp_many(sources, destination, recursive);
+    }
+    let source = sources[0];
+    if !recursive && Path::new(source).is_dir() {
+        return Err(omitted(source));
+    }
+    cp(source, destination).map_err(|e| format!("cp: {}", e))
+}
+
+/// Why directory `source` was not copied without `-r`.
+fn omitted(source: &str) -> String {
+    format!("cp: -r not specified; omitting directory '{}'", source)
+}
+
 pub fn cp(source: &str, destination: &str) -> io::Result<()> {
     let source_path = Path::new(source);
     let destination_path = Path::new(destination);
@@ -14,13 +50,15 @@ pub fn cp(source: &str, destination: &str) -> io::Result<()> {
 
         // Iterate through the entries in the source directory and copy them to the destination
         for entry in fs::read_dir(source_path)? {
+            // Stop between entries if Ctrl+C was pressed
+            signals::check()?;
             let entry = entry?;
             let entry_path = entry.path();
             let dest_entry_path = destination_path.join(entry_path.file_name().unwrap());
 
             if entry_path.is_dir() {
                 // Recursively copy subdirectories
-                cp(&entry_path.to_str().unwrap(), &dest_entry_path.to_str().unwrap())?;
+                cp(entry_path.to_str().unwrap(), dest_entry_path.to_str().unwrap())?;
             } else {
                 // Copy files directly
                 fs::copy(&entry_path, &dest_entry_path)?;
@@ -39,3 +77,38 @@ pub fn cp(source: &str, destination: &str) -> io::Result<()> {
 
     Ok(())
 }
+
+/// Copies several sources into the existing directory `destination`, as in
+/// `cp src/* dest/`. Each source keeps its own name inside the directory,
+/// and directories among them are only copied when `recursive`.
+pub fn cp_many(sources: &[&str], destination: &str, recursive: bool) -> Result<(), String> {
+    let destination_path = Path::new(destination);
+    if !destination_path.is_dir() {
+        return Err(format!("cp: target '{}' is not a directory", destination));
+    }
+
+    // Copy what we can and report the first failure at the end
+    let mut result = Ok(());
+    for source in sources {
+        let source_path = Path::new(source);
+        if !recursive && source_path.is_dir() {
+            if result.is_ok() {
+                result = Err(omitted(source));
+            }
+            continue;
+        }
+        let copied = match source_path.file_name() {
+            Some(name) if source_path.is_dir() => {
+                let target = destination_path.join(name);
+                cp(source, &target.to_string_lossy())
+            }
+            _ => cp(source, destination),
+        };
+        if let Err(e) = copied {
+            if result.is_ok() {
+                result = Err(format!("cp: {}: {}", source, e));
+            }
+        }
+    }
+    result
+}
@@ -1,45 +1,15 @@
-use std::fs::File;
 use std::io::Write;
 
-pub fn echo(args: &[&str]) -> Result<(), std::io::Error> {
-    if let Some(redirect_index) = args.iter().position(|&x| x == ">") {
-        // If redirection symbol found
-        if let Some(filename) = args.get(redirect_index + 1) {
-            if let Ok(mut file) = File::create(filename) {
-                let content = args[..redirect_index].join(" ");
-                if let Err(e) = writeln!(file, "{}", content) {
-                    return Err(e);
-                }
-                return Ok(());
-            } else {
-                return Err(
-                    std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Error creating file '{}'", filename)
-                    )
-                );
-            }
-        } else {
-            return Err(
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "No filename provided for redirection"
-                )
-            );
-        }
-    }
+use crate::stream::Io;
 
-    // If no redirection, echo to console
+pub fn echo(args: &[&str], io: &mut Io) -> Result<(), std::io::Error> {
+    // Redirections such as `echo hi > file` are applied by the executor, so the
+    // output stream may already point at a file
     for (i, arg) in args.iter().enumerate() {
         if i != 0 {
-            print!(" ");
+            write!(io.stdout, " ")?;
         }
-        print!("{}", remove_quotes(arg));
+        write!(io.stdout, "{}", arg)?;
     }
-    println!();
-    Ok(())
-}
-
-fn remove_quotes(s: &str) -> &str {
-    s.trim_matches(['"', '\''].as_ref())
+    writeln!(io.stdout)
 }
@@ -0,0 +1,1112 @@
+// editor.rs
+//
+// The line editor behind the interactive prompt. While a line is typed the
+// terminal is in raw mode, so the editor sees every key instead of the
+// escape codes the terminal would echo: the cursor moves anywhere in the line
+// and by words, text can be killed and yanked back, the arrow keys browse the
+// history, Ctrl+R searches it and Tab completes the word before the cursor,
+// listing the choices in columns when there are several. A command recalled
+// from the history may span several lines, and Up and Down move between them
+// before moving on to other commands. Keys follow emacs by default, or vi
+// after `set -o vi`.
+//
+// The screen is redrawn from the prompt on after every key. Positions are
+// worked out in terminal columns rather than characters, so that wide
+// characters such as CJK or emoji, which take two columns, and lines that
+// wrap at the edge of the terminal keep the cursor in the right place. A
+// right prompt is drawn at the edge of the first row while the line leaves
+// room for it.
+//
+// Without a terminal that can be put in raw mode, or with `TERM=dumb`, the
+// prompt is printed and the line read as it is.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::mem;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::complete::{Candidate, Completion};
+use crate::history::Entry;
+use crate::input;
+
+const TAB_WIDTH: usize = 8;
+
+/// Beyond this many completions the user is asked before they are listed.
+const LIST_WITHOUT_ASKING: usize = 100;
+
+/// How long to wait for the rest of an escape sequence before taking ESC
+/// as a key of its own, in milliseconds.
+const ESCAPE_TIMEOUT: i32 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Char(char),
+    /// A control character by its letter: `Ctrl('a')` is Ctrl+A.
+    Ctrl(char),
+    /// A key pressed with Alt, or after ESC. Alt+Enter is `Alt('\r')` and
+    /// Alt+Backspace `Alt('\x7f')`.
+    Alt(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Escape,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// Ctrl+Left or Alt+Left
+    WordLeft,
+    /// Ctrl+Right or Alt+Right
+    WordRight,
+    Unknown,
+}
+
+/// What the editor does after a key.
+enum Action {
+    Continue,
+    /// Enter: the line is done.
+    Accept,
+    /// Ctrl+D on an empty line.
+    Eof,
+    /// Ctrl+C: the line is thrown away.
+    Interrupt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Insert,
+    /// vi command mode, entered with ESC.
+    Normal,
+}
+
+/// The part of the editor that outlives a line: the text last killed, which
+/// Ctrl+Y or vi's `p` can put back into a later line too.
+#[derive(Debug, Default)]
+pub struct Editor {
+    killed: String,
+}
+
+impl Editor {
+    /// Shows `prompt`, and `right_prompt` at the right edge of the terminal,
+    /// and lets the user edit a line, with `history` to browse,
+    /// `complete` to find what the word before the cursor may become, and vi
+    /// keys if `vi` is set. Appends the line and a newline to `line`
+    /// and returns the number of bytes added, or 0 at Ctrl+D on an empty
+    /// line. Ctrl+C gives an `Interrupted` error.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        right_prompt: &str,
+        history: &[Entry],
+        complete: &dyn Fn(&str) -> Completion,
+        vi: bool,
+        line: &mut String,
+    ) -> io::Result<usize> {
+        let Some(raw) = RawMode::enable() else {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+            return input::read_line(&mut io::stdin().lock(), line);
+        };
+        let mut editing = Line {
+            prompt,
+            right_prompt,
+            buffer: Vec::new(),
+            cursor: 0,
+            row: 0,
+            history,
+            index: history.len(),
+            draft: Vec::new(),
+            killed: &mut self.killed,
+            killing: false,
+            complete,
+            completing: false,
+            undo: Vec::new(),
+            vi,
+            mode: Mode::Insert,
+            pending: None,
+        };
+        let result = editing.edit();
+        drop(raw);
+        match result? {
+            Some(text) => {
+                line.push_str(&text);
+                line.push('\n');
+                Ok(text.len() + 1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// A line being edited.
+struct Line<'a> {
+    prompt: &'a str,
+    right_prompt: &'a str,
+    buffer: Vec<char>,
+    /// Index in `buffer` of the character the cursor is on.
+    cursor: usize,
+    /// Screen row the cursor was left on, counted from the prompt's first row.
+    row: usize,
+    history: &'a [Entry],
+    /// The history entry being shown; `history.len()` for the line being typed.
+    index: usize,
+    /// The line being typed, kept while other entries are shown.
+    draft: Vec<char>,
+    killed: &'a mut String,
+    /// Whether the last key killed text, so that the next kill adds to it.
+    killing: bool,
+    complete: &'a dyn Fn(&str) -> Completion,
+    /// Whether the last key was a Tab with several completions, so that
+    /// another one lists them.
+    completing: bool,
+    /// Earlier states of the line, for Ctrl+_ or vi's `u` to go back to.
+    undo: Vec<(Vec<char>, usize)>,
+    vi: bool,
+    mode: Mode,
+    /// A vi operator (`d`, `c` or `y`) or `r` waiting for its second key.
+    pending: Option<char>,
+}
+
+impl Line<'_> {
+    fn edit(&mut self) -> io::Result<Option<String>> {
+        self.refresh(None)?;
+        loop {
+            let Some(key) = read_key()? else {
+                return Ok(None);
+            };
+            match self.dispatch(key)? {
+                Action::Continue => self.refresh(None)?,
+                Action::Accept => {
+                    self.cursor = self.buffer.len();
+                    self.refresh(None)?;
+                    write_screen("\r\n")?;
+                    return Ok(Some(self.buffer.iter().collect()));
+                }
+                Action::Eof => return Ok(None),
+                Action::Interrupt => {
+                    self.cursor = self.buffer.len();
+                    self.refresh(None)?;
+                    write_screen("^C")?;
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+            }
+        }
+    }
+
+    fn dispatch(&mut self, key: Key) -> io::Result<Action> {
+        let killing = mem::take(&mut self.killing);
+        let completing = mem::take(&mut self.completing);
+        if key == Key::Tab && self.mode == Mode::Insert {
+            self.complete(completing)?;
+            return Ok(Action::Continue);
+        }
+        if self.mode == Mode::Normal {
+            let action = self.normal(key, killing)?;
+            if self.mode == Mode::Normal {
+                self.clamp();
+            }
+            return Ok(action);
+        }
+        self.insert(key, killing)
+    }
+
+    /// Keys in emacs mode and in vi's insert mode.
+    fn insert(&mut self, key: Key, killing: bool) -> io::Result<Action> {
+        match key {
+            Key::Char(c) => {
+                self.save();
+                self.buffer.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Enter => return Ok(Action::Accept),
+            // A line break inside the command, for editing several lines at once
+            Key::Alt('\r') => {
+                self.save();
+                self.buffer.insert(self.cursor, '\n');
+                self.cursor += 1;
+            }
+            Key::Ctrl('c') => return Ok(Action::Interrupt),
+            Key::Ctrl('d') if self.buffer.is_empty() => return Ok(Action::Eof),
+            Key::Ctrl('d') | Key::Delete if self.cursor < self.buffer.len() => {
+                self.save();
+                self.buffer.remove(self.cursor);
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.save();
+                self.cursor -= 1;
+                self.buffer.remove(self.cursor);
+            }
+            Key::Ctrl('r') => return self.search(),
+            Key::Ctrl('l') => self.clear_screen()?,
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = self.line_start(),
+            Key::End | Key::Ctrl('e') => self.cursor = self.line_end(),
+            Key::WordLeft | Key::Alt('b') => self.cursor = self.word_back(is_alphanumeric),
+            Key::WordRight | Key::Alt('f') => self.cursor = self.word_forward(is_alphanumeric),
+            Key::Up | Key::Ctrl('p') => self.up(),
+            Key::Down | Key::Ctrl('n') => self.down(),
+            Key::Ctrl('w') => {
+                let start = self.word_back(|c| !c.is_whitespace());
+                self.kill(start, self.cursor, killing);
+            }
+            Key::Ctrl('u') => self.kill(self.line_start(), self.cursor, killing),
+            Key::Ctrl('_') => self.undo(),
+            Key::Escape if self.vi => {
+                self.mode = Mode::Normal;
+                self.cursor = self.cursor.saturating_sub(1).max(self.line_start());
+            }
+            // The rest are emacs keys
+            _ if self.vi => {}
+            Key::Ctrl('k') => self.kill(self.cursor, self.line_end(), killing),
+            Key::Alt('d') => {
+                let end = self.word_forward(is_alphanumeric);
+                self.kill(self.cursor, end, killing);
+            }
+            Key::Alt('\x7f') => {
+                let start = self.word_back(is_alphanumeric);
+                self.kill(start, self.cursor, killing);
+            }
+            Key::Ctrl('y') => self.put(self.cursor),
+            Key::Ctrl('t') => self.transpose(),
+            _ => {}
+        }
+        Ok(Action::Continue)
+    }
+
+    /// Keys in vi's command mode.
+    fn normal(&mut self, key: Key, killing: bool) -> io::Result<Action> {
+        if let Some(operator) = self.pending.take() {
+            self.operator(operator, key);
+            return Ok(Action::Continue);
+        }
+        let (start, end) = (self.line_start(), self.line_end());
+        match key {
+            Key::Enter => return Ok(Action::Accept),
+            Key::Ctrl('c') => return Ok(Action::Interrupt),
+            Key::Ctrl('d') if self.buffer.is_empty() => return Ok(Action::Eof),
+            Key::Ctrl('r') | Key::Char('/') => return self.search(),
+            Key::Ctrl('l') => self.clear_screen()?,
+            Key::Char('i') => self.insert_mode(),
+            Key::Char('a') => {
+                self.cursor = (self.cursor + 1).min(end);
+                self.insert_mode();
+            }
+            Key::Char('I') => {
+                self.cursor = start;
+                self.insert_mode();
+            }
+            Key::Char('A') => {
+                self.cursor = end;
+                self.insert_mode();
+            }
+            Key::Char('x') | Key::Delete => self.kill(self.cursor, (self.cursor + 1).min(end), false),
+            Key::Char('X') if self.cursor > start => self.kill(self.cursor - 1, self.cursor, false),
+            Key::Char('D') => self.kill(self.cursor, end, false),
+            Key::Char('C') => {
+                self.kill(self.cursor, end, false);
+                self.mode = Mode::Insert;
+            }
+            Key::Char('S') => {
+                self.kill(start, end, false);
+                self.mode = Mode::Insert;
+            }
+            Key::Char(c @ ('d' | 'c' | 'y' | 'r')) => self.pending = Some(c),
+            Key::Char('p') => {
+                let at = (self.cursor + 1).min(end);
+                self.put(at);
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            Key::Char('P') => {
+                self.put(self.cursor);
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            Key::Char('~') => {
+                if let Some(&c) = self.buffer.get(self.cursor).filter(|_| self.cursor < end) {
+                    self.save();
+                    let toggled: Vec<char> = if c.is_uppercase() {
+                        c.to_lowercase().collect()
+                    } else {
+                        c.to_uppercase().collect()
+                    };
+                    self.buffer.splice(self.cursor..=self.cursor, toggled.iter().copied());
+                    self.cursor += toggled.len();
+                }
+            }
+            Key::Char('u') | Key::Ctrl('_') => self.undo(),
+            Key::Char('j') | Key::Down | Key::Ctrl('n') => self.down(),
+            Key::Char('k') | Key::Up | Key::Ctrl('p') => self.up(),
+            key => {
+                if let Some(target) = self.motion(key) {
+                    self.cursor = target;
+                }
+                // Keeps adding to the killed text, as in emacs mode
+                self.killing = killing;
+            }
+        }
+        Ok(Action::Continue)
+    }
+
+    /// Where a vi motion key moves the cursor.
+    fn motion(&self, key: Key) -> Option<usize> {
+        let (start, end) = (self.line_start(), self.line_end());
+        Some(match key {
+            Key::Char('h') | Key::Left | Key::Backspace => self.cursor.saturating_sub(1).max(start),
+            Key::Char('l') | Key::Right | Key::Char(' ') => (self.cursor + 1).min(end),
+            Key::Char('0') | Key::Home => start,
+            Key::Char('^') => (start..end)
+                .find(|&i| !self.buffer[i].is_whitespace())
+                .unwrap_or(end),
+            Key::Char('$') | Key::End => end,
+            Key::Char('w') | Key::WordRight => self.vi_word_forward(),
+            Key::Char('b') | Key::WordLeft => self.vi_word_back(),
+            Key::Char('e') => self.vi_word_end(),
+            _ => return None,
+        })
+    }
+
+    /// The second key of `d`, `c`, `y` or `r`. Doubling an operator, as in
+    /// `dd`, applies it to the whole line.
+    fn operator(&mut self, operator: char, key: Key) {
+        let (start, end) = (self.line_start(), self.line_end());
+        if operator == 'r' {
+            if let Key::Char(c) = key {
+                if self.cursor < end {
+                    self.save();
+                    self.buffer[self.cursor] = c;
+                }
+            }
+            return;
+        }
+        let (from, to) = if key == Key::Char(operator) {
+            (start, end)
+        } else {
+            // `cw` on a word changes just the word, like `ce`
+            let on_word = self.buffer.get(self.cursor).is_some_and(|c| !c.is_whitespace());
+            let key = if operator == 'c' && on_word && key == Key::Char('w') {
+                Key::Char('e')
+            } else {
+                key
+            };
+            let Some(mut target) = self.motion(key) else {
+                return;
+            };
+            // `e` includes the character it lands on
+            if key == Key::Char('e') {
+                target = (target + 1).min(self.buffer.len());
+            }
+            (self.cursor.min(target), self.cursor.max(target))
+        };
+        match operator {
+            'y' => {
+                *self.killed = self.buffer[from..to].iter().collect();
+                self.cursor = from;
+            }
+            'c' => {
+                self.kill(from, to, false);
+                self.mode = Mode::Insert;
+            }
+            _ => self.kill(from, to, false),
+        }
+    }
+
+    fn insert_mode(&mut self) {
+        self.save();
+        self.mode = Mode::Insert;
+    }
+
+    /// In vi's command mode the cursor stays on a character of the line.
+    fn clamp(&mut self) {
+        let (start, end) = (self.line_start(), self.line_end());
+        if self.cursor >= end && end > start {
+            self.cursor = end - 1;
+        }
+    }
+
+    /// Remembers the line as it is, before a change. In vi the text typed
+    /// in insert mode is undone as a whole, from where insert mode began.
+    fn save(&mut self) {
+        if self.vi && self.mode == Mode::Insert {
+            return;
+        }
+        self.undo.push((self.buffer.clone(), self.cursor));
+    }
+
+    fn undo(&mut self) {
+        if let Some((buffer, cursor)) = self.undo.pop() {
+            self.buffer = buffer;
+            self.cursor = cursor.min(self.buffer.len());
+        }
+    }
+
+    /// Removes `buffer[from..to]` and keeps it for yanking. Right after
+    /// another kill the text is added to what that one removed instead.
+    fn kill(&mut self, from: usize, to: usize, killing: bool) {
+        if from >= to {
+            self.killing = killing;
+            return;
+        }
+        self.save();
+        let text: String = self.buffer.drain(from..to).collect();
+        if !killing {
+            *self.killed = text;
+        } else if to <= self.cursor {
+            self.killed.insert_str(0, &text);
+        } else {
+            self.killed.push_str(&text);
+        }
+        self.cursor = from;
+        self.killing = true;
+    }
+
+    /// Inserts the killed text at `at` and leaves the cursor after it.
+    fn put(&mut self, at: usize) {
+        if self.killed.is_empty() {
+            return;
+        }
+        self.save();
+        let text: Vec<char> = self.killed.chars().collect();
+        self.buffer.splice(at..at, text.iter().copied());
+        self.cursor = at + text.len();
+    }
+
+    /// Ctrl+T: swaps the character before the cursor with the one under it,
+    /// or the last two at the end of the line.
+    fn transpose(&mut self) {
+        let at = self.cursor.min(self.line_end());
+        if at == 0 || self.buffer.len() < 2 {
+            return;
+        }
+        let at = if at == self.buffer.len() { at - 1 } else { at };
+        self.save();
+        self.buffer.swap(at - 1, at);
+        self.cursor = at + 1;
+    }
+
+    /// Up: the line above in a command of several lines, otherwise the
+    /// previous history entry.
+    fn up(&mut self) {
+        let start = self.line_start();
+        if start > 0 {
+            let column = self.cursor - start;
+            let above = line_start(&self.buffer, start - 1);
+            self.cursor = (above + column).min(start - 1);
+        } else if self.index > 0 {
+            self.show(self.index - 1);
+        }
+    }
+
+    fn down(&mut self) {
+        let end = self.line_end();
+        if end < self.buffer.len() {
+            let column = self.cursor - self.line_start();
+            let below = end + 1;
+            self.cursor = (below + column).min(line_end(&self.buffer, below));
+        } else if self.index < self.history.len() {
+            self.show(self.index + 1);
+        }
+    }
+
+    /// Shows history entry `index`, or the line being typed.
+    fn show(&mut self, index: usize) {
+        if self.index == self.history.len() {
+            self.draft = mem::take(&mut self.buffer);
+        }
+        self.index = index;
+        self.buffer = match self.history.get(index) {
+            Some(entry) => entry.line.chars().collect(),
+            None => self.draft.clone(),
+        };
+        self.undo.clear();
+        self.cursor = if self.mode == Mode::Normal { 0 } else { self.buffer.len() };
+    }
+
+    /// Ctrl+R: searches the history backwards as the query is typed. Ctrl+R
+    /// again finds an older match, Ctrl+G gives up, and any other key keeps
+    /// the match and then does what it normally does.
+    fn search(&mut self) -> io::Result<Action> {
+        let original = (self.buffer.clone(), self.cursor, self.index);
+        let mut query = String::new();
+        // The entry matched, and where in it
+        let mut found: Option<usize> = None;
+        let mut failed = false;
+        loop {
+            let prompt = format!(
+                "({}reverse-i-search)`{}': ",
+                if failed { "failed " } else { "" },
+                query
+            );
+            self.refresh(Some(&prompt))?;
+            let Some(key) = read_key()? else {
+                return Ok(Action::Eof);
+            };
+            // Entries before this one are searched
+            let before = match key {
+                Key::Ctrl('r') => found.unwrap_or(original.2),
+                Key::Char(c) => {
+                    query.push(c);
+                    found.map_or(original.2, |index| index + 1)
+                }
+                Key::Backspace => {
+                    query.pop();
+                    original.2
+                }
+                Key::Ctrl('g') => {
+                    (self.buffer, self.cursor, self.index) = original;
+                    return Ok(Action::Continue);
+                }
+                Key::Escape => return Ok(Action::Continue),
+                key => return self.dispatch(key),
+            };
+            if query.is_empty() {
+                failed = false;
+                continue;
+            }
+            let matched = self.history[..before].iter().enumerate().rev().find_map(|(i, entry)| {
+                let at = entry.line.rfind(&query)?;
+                Some((i, entry.line[..at].chars().count()))
+            });
+            failed = matched.is_none();
+            if let Some((index, cursor)) = matched {
+                if self.index == self.history.len() {
+                    self.draft = self.buffer.clone();
+                }
+                self.index = index;
+                self.buffer = self.history[index].line.chars().collect();
+                self.cursor = cursor;
+                found = Some(index);
+            }
+        }
+    }
+
+    /// Tab: completes the word before the cursor. A single completion is
+    /// put in full, followed by a space unless it is a directory; several
+    /// are completed as far as they agree, and listed at the next Tab.
+    fn complete(&mut self, completing: bool) -> io::Result<()> {
+        let text: String = self.buffer[..self.cursor].iter().collect();
+        let completion = (self.complete)(&text);
+        let start = text[..completion.start].chars().count();
+        let word = &text[completion.start..];
+        match &completion.candidates[..] {
+            [] => write_screen("\x07")?,
+            [only] => {
+                let mut text = only.text.clone();
+                if !text.ends_with('/') {
+                    text.push(' ');
+                }
+                self.replace(start, &text);
+            }
+            candidates => {
+                let prefix = common_prefix(candidates);
+                if prefix != word && prefix.len() >= word.len() {
+                    self.replace(start, &prefix);
+                } else if completing {
+                    self.list(candidates)?;
+                } else {
+                    write_screen("\x07")?;
+                }
+                self.completing = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the text from `start` to the cursor.
+    fn replace(&mut self, start: usize, text: &str) {
+        self.save();
+        self.buffer.splice(start..self.cursor, text.chars());
+        self.cursor = start + text.chars().count();
+    }
+
+    /// Lists `candidates` in columns below the line, down first like `ls`,
+    /// then starts the prompt again underneath.
+    fn list(&mut self, candidates: &[Candidate]) -> io::Result<()> {
+        let cursor = mem::replace(&mut self.cursor, self.buffer.len());
+        self.refresh(None)?;
+        self.cursor = cursor;
+        self.row = 0;
+        if candidates.len() > LIST_WITHOUT_ASKING {
+            let question = format!("\r\nDisplay all {} possibilities? (y or n)", candidates.len());
+            write_screen(&question)?;
+            if read_key()? != Some(Key::Char('y')) {
+                return write_screen("\r\n");
+            }
+        }
+
+        let width = terminal_width();
+        let column_width = candidates.iter().map(|c| c.display.width()).max().unwrap_or(0) + 2;
+        let columns = (width / column_width).max(1);
+        let rows = candidates.len().div_ceil(columns);
+        let mut out = String::from("\r\n");
+        for row in 0..rows {
+            let mut line = String::new();
+            for column in 0..columns {
+                let Some(candidate) = candidates.get(column * rows + row) else {
+                    break;
+                };
+                line.push_str(&candidate.display);
+                let padding = column_width - candidate.display.width();
+                line.extend(std::iter::repeat_n(' ', padding));
+            }
+            out.push_str(line.trim_end());
+            out.push_str("\r\n");
+        }
+        write_screen(&out)
+    }
+
+    fn clear_screen(&mut self) -> io::Result<()> {
+        self.row = 0;
+        write_screen("\x1b[H\x1b[2J")
+    }
+
+    fn line_start(&self) -> usize {
+        line_start(&self.buffer, self.cursor)
+    }
+
+    fn line_end(&self) -> usize {
+        line_end(&self.buffer, self.cursor)
+    }
+
+    /// The start of the word before the cursor, where words are made of
+    /// characters for which `is_word` holds.
+    fn word_back(&self, is_word: fn(char) -> bool) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !is_word(self.buffer[i - 1]) {
+            i -= 1;
+        }
+        while i > 0 && is_word(self.buffer[i - 1]) {
+            i -= 1;
+        }
+        i
+    }
+
+    /// The end of the word under or after the cursor.
+    fn word_forward(&self, is_word: fn(char) -> bool) -> usize {
+        let mut i = self.cursor;
+        while i < self.buffer.len() && !is_word(self.buffer[i]) {
+            i += 1;
+        }
+        while i < self.buffer.len() && is_word(self.buffer[i]) {
+            i += 1;
+        }
+        i
+    }
+
+    /// vi's `w`: the start of the next word. vi words are runs of letters,
+    /// digits and `_`, or runs of other characters that are not blanks.
+    fn vi_word_forward(&self) -> usize {
+        let mut i = self.cursor;
+        if let Some(&c) = self.buffer.get(i) {
+            let class = vi_class(c);
+            while class != 0 && i < self.buffer.len() && vi_class(self.buffer[i]) == class {
+                i += 1;
+            }
+        }
+        while i < self.buffer.len() && vi_class(self.buffer[i]) == 0 {
+            i += 1;
+        }
+        i
+    }
+
+    /// vi's `b`: the start of this word, or of the one before.
+    fn vi_word_back(&self) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && vi_class(self.buffer[i - 1]) == 0 {
+            i -= 1;
+        }
+        if i > 0 {
+            let class = vi_class(self.buffer[i - 1]);
+            while i > 0 && vi_class(self.buffer[i - 1]) == class {
+                i -= 1;
+            }
+        }
+        i
+    }
+
+    /// vi's `e`: the last character of this word, or of the next one.
+    fn vi_word_end(&self) -> usize {
+        let mut i = self.cursor + 1;
+        while i < self.buffer.len() && vi_class(self.buffer[i]) == 0 {
+            i += 1;
+        }
+        if i >= self.buffer.len() {
+            return self.buffer.len().saturating_sub(1).max(self.cursor);
+        }
+        let class = vi_class(self.buffer[i]);
+        while i + 1 < self.buffer.len() && vi_class(self.buffer[i + 1]) == class {
+            i += 1;
+        }
+        i
+    }
+
+    /// Redraws the prompt, or `prompt` in its place, and the line, and puts
+    /// the cursor where it belongs.
+    fn refresh(&mut self, prompt: Option<&str>) -> io::Result<()> {
+        let mut screen = Screen {
+            width: terminal_width(),
+            ..Screen::default()
+        };
+        screen.prompt(prompt.unwrap_or(self.prompt));
+        let mut cursor = None;
+        for (i, &c) in self.buffer.iter().enumerate() {
+            if i == self.cursor {
+                cursor = Some(screen.position(c));
+            }
+            screen.put(c);
+        }
+        let end = screen.finish();
+        let (row, column) = cursor.unwrap_or(end);
+
+        let mut out = String::new();
+        // Back to the start of the prompt, then draw everything again
+        if self.row > 0 {
+            let _ = write!(out, "\x1b[{}A", self.row);
+        }
+        out.push_str("\r\x1b[J");
+        out.push_str(&screen.text);
+        if prompt.is_none() && end.0 == 0 {
+            let mut right = Screen {
+                width: screen.width,
+                ..Screen::default()
+            };
+            right.prompt(self.right_prompt);
+            // At least one blank column between the line and the right prompt
+            if right.row == 0 && right.column > 0 && end.1 + right.column < screen.width {
+                let _ = write!(out, "\r\x1b[{}C{}", screen.width - right.column, right.text);
+            }
+        }
+        if end.0 > row {
+            let _ = write!(out, "\x1b[{}A", end.0 - row);
+        }
+        out.push('\r');
+        if column > 0 {
+            let _ = write!(out, "\x1b[{}C", column);
+        }
+        self.row = row;
+        write_screen(&out)
+    }
+}
+
+/// The longest text all the candidates start with.
+fn common_prefix(candidates: &[Candidate]) -> String {
+    let first = &candidates[0].text;
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.text.chars())
+            .take_while(|((i, a), b)| *i < len && a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8());
+    }
+    first[..len].to_string()
+}
+
+fn is_alphanumeric(c: char) -> bool {
+    c.is_alphanumeric()
+}
+
+/// 0 for blanks, 1 for letters, digits and `_`, 2 for anything else.
+fn vi_class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// The start of the line `at` is on, in a buffer that may hold several.
+fn line_start(buffer: &[char], at: usize) -> usize {
+    buffer[..at].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1)
+}
+
+fn line_end(buffer: &[char], at: usize) -> usize {
+    buffer[at..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(buffer.len(), |i| at + i)
+}
+
+/// Text laid out on the screen, with the row and column it got to.
+#[derive(Default)]
+struct Screen {
+    width: usize,
+    row: usize,
+    column: usize,
+    text: String,
+}
+
+impl Screen {
+    /// Lays out the prompt. Escape sequences, such as colors, take no room,
+    /// and neither do the `\x01` and `\x02` that may mark them.
+    fn prompt(&mut self, prompt: &str) {
+        let mut chars = prompt.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    self.text.push(c);
+                    if chars.peek() == Some(&'[') {
+                        self.text.push(chars.next().unwrap_or('['));
+                        for c in chars.by_ref() {
+                            self.text.push(c);
+                            if ('\x40'..='\x7e').contains(&c) {
+                                break;
+                            }
+                        }
+                    } else if let Some(c) = chars.next() {
+                        self.text.push(c);
+                    }
+                }
+                '\x01' | '\x02' => {}
+                c => self.put(c),
+            }
+        }
+    }
+
+    /// Columns `c` takes when written at the current column.
+    fn columns(&self, c: char) -> usize {
+        match c {
+            '\t' => TAB_WIDTH - self.column % TAB_WIDTH,
+            // Shown as `^X`
+            c if c.is_ascii_control() => 2,
+            c if c.is_control() => 1,
+            c => c.width().unwrap_or(0),
+        }
+    }
+
+    /// Where `c` will be drawn: wrapped to the next row if it does not fit.
+    fn position(&self, c: char) -> (usize, usize) {
+        if c != '\n' && self.column + self.columns(c) > self.width {
+            (self.row + 1, 0)
+        } else {
+            (self.row, self.column.min(self.width.saturating_sub(1)))
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        if c == '\n' {
+            self.text.push_str("\r\n");
+            self.row += 1;
+            self.column = 0;
+            return;
+        }
+        let mut columns = self.columns(c);
+        // The terminal moves on to the next row by itself
+        if self.column + columns > self.width {
+            self.row += 1;
+            self.column = 0;
+            columns = self.columns(c);
+        }
+        match c {
+            '\t' => {
+                let spaces = columns.min(self.width - self.column);
+                self.text.extend(std::iter::repeat_n(' ', spaces));
+                columns = spaces;
+            }
+            c if c.is_ascii_control() => {
+                self.text.push('^');
+                self.text.push(((c as u8) ^ 0x40) as char);
+            }
+            c if c.is_control() => self.text.push('?'),
+            c => self.text.push(c),
+        }
+        self.column += columns;
+    }
+
+    /// Ends the text. A line that exactly fills its last row leaves the
+    /// terminal's cursor there, so it is moved to the next row explicitly.
+    fn finish(&mut self) -> (usize, usize) {
+        if self.column >= self.width {
+            self.text.push_str("\r\n");
+            self.row += 1;
+            self.column = 0;
+        }
+        (self.row, self.column)
+    }
+}
+
+fn write_screen(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(text.as_bytes())?;
+    stdout.flush()
+}
+
+/// Width of the terminal in columns.
+fn terminal_width() -> usize {
+    for fd in [libc::STDOUT_FILENO, libc::STDIN_FILENO] {
+        // SAFETY: TIOCGWINSZ fills in the winsize it is given
+        unsafe {
+            let mut size: libc::winsize = mem::zeroed();
+            if libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+                return usize::from(size.ws_col);
+            }
+        }
+    }
+    80
+}
+
+/// The terminal in raw mode, switched back to how it was when dropped.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Puts the terminal on stdin in raw mode: no echo, keys delivered one
+    /// at a time, and Ctrl+C or Ctrl+Z read as keys rather than signals.
+    fn enable() -> Option<RawMode> {
+        if std::env::var("TERM").is_ok_and(|term| term == "dumb") {
+            return None;
+        }
+        // SAFETY: tcgetattr and tcsetattr fill in and read the termios given
+        unsafe {
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) < 0 {
+                return None;
+            }
+            let mut raw = original;
+            raw.c_iflag &= !(libc::ICRNL | libc::IXON | libc::BRKINT | libc::ISTRIP);
+            raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::ISIG | libc::IEXTEN);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) < 0 {
+                return None;
+            }
+            Some(RawMode { original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in `enable`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
+
+/// Reads one byte from the terminal. Returns `None` when it is gone.
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = 0u8;
+    loop {
+        // SAFETY: reads at most one byte into `byte`
+        let count = unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) };
+        match count {
+            1 => return Ok(Some(byte)),
+            0 => return Ok(None),
+            _ => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// Whether another byte arrives within `timeout` milliseconds.
+fn byte_ready(timeout: i32) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: polls the one descriptor given
+    unsafe { libc::poll(&mut fd, 1, timeout) > 0 }
+}
+
+/// Reads the next key. Returns `None` when the terminal is gone.
+fn read_key() -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte()? else {
+        return Ok(None);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => read_escape()?,
+        0x1f => Key::Ctrl('_'),
+        1..=26 => Key::Ctrl(char::from(b'a' + byte - 1)),
+        0..=0x1f => Key::Unknown,
+        _ => read_char(byte)?.map_or(Key::Unknown, Key::Char),
+    };
+    Ok(Some(key))
+}
+
+/// Reads the rest of a UTF-8 character that starts with `first`.
+fn read_char(first: u8) -> io::Result<Option<char>> {
+    let len = match first {
+        0..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Ok(None),
+    };
+    let mut bytes = vec![first];
+    while bytes.len() < len {
+        match read_byte()? {
+            Some(byte) => bytes.push(byte),
+            None => return Ok(None),
+        }
+    }
+    Ok(std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()))
+}
+
+/// Reads what follows ESC: an escape sequence for a special key, a key
+/// pressed with Alt, or nothing for the ESC key itself.
+fn read_escape() -> io::Result<Key> {
+    if !byte_ready(ESCAPE_TIMEOUT) {
+        return Ok(Key::Escape);
+    }
+    let Some(byte) = read_byte()? else {
+        return Ok(Key::Escape);
+    };
+    Ok(match byte {
+        b'[' => {
+            let mut params = String::new();
+            loop {
+                match read_byte()? {
+                    Some(end @ 0x40..=0x7e) => break csi_key(&params, end),
+                    Some(byte) => params.push(char::from(byte)),
+                    None => break Key::Unknown,
+                }
+            }
+        }
+        b'O' => match read_byte()? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Unknown,
+        },
+        b'\r' | b'\n' => Key::Alt('\r'),
+        0x7f | 0x08 => Key::Alt('\x7f'),
+        byte => read_char(byte)?.map_or(Key::Unknown, Key::Alt),
+    })
+}
+
+/// The key sent as `ESC [ params end`.
+fn csi_key(params: &str, end: u8) -> Key {
+    // `1;5C` is Ctrl+Right and `1;3C` Alt+Right
+    let by_word = params.ends_with(";5") || params.ends_with(";3");
+    match (end, params) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) if by_word => Key::WordRight,
+        (b'C', _) => Key::Right,
+        (b'D', _) if by_word => Key::WordLeft,
+        (b'D', _) => Key::Left,
+        (b'H', _) | (b'~', "1" | "7") => Key::Home,
+        (b'F', _) | (b'~', "4" | "8") => Key::End,
+        (b'~', "3") => Key::Delete,
+        _ => Key::Unknown,
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::Write;
+use std::process::Command;
+
+use crate::getopt::{Args, Opt, Spec};
+use crate::stream::Io;
+use crate::vars::Variables;
+
+pub const ENV: Spec = Spec::new(
+    "Print the environment, or run a command with it changed.",
+    "[name=value ...] [command [arg ...]]",
+    &[Opt::flag('i', "ignore-environment", "start with an empty environment")],
+)
+.in_order();
+
+pub const PRINTENV: Spec = Spec::new(
+    "Print the values of exported variables, all of them by default.",
+    "[name ...]",
+    &[],
+);
+
+/// `env [-i] [NAME=value]... [command [arg]...]`: prints the environment, or
+/// runs `command` with the environment changed. Returns the exit status.
+pub fn env(args: Args, vars: &Variables, io: &mut Io) -> Result<i32, String> {
+    let mut environment: Vec<(String, String)> = vars
+        .exported()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    if args.has('i') {
+        environment.clear();
+    }
+    let mut args = args.operands.into_iter().peekable();
+
+    while let Some(arg) = args.peek() {
+        let Some((name, value)) = arg.split_once('=') else {
+            break;
+        };
+        environment.retain(|(existing, _)| existing != name);
+        environment.push((name.to_string(), value.to_string()));
+        args.next();
+    }
+
+    let Some(program) = args.next() else {
+        for (name, value) in &environment {
+            writeln!(io.stdout, "{}={}", name, value).map_err(|e| format!("env: {}", e))?;
+        }
+        return Ok(0);
+    };
+
+    let stdio_error = |e: std::io::Error| format!("env: {}", e);
+    let status = Command::new(program)
+        .args(args)
+        .env_clear()
+        .envs(environment)
+        .stdin(io.stdin.to_stdio().map_err(stdio_error)?)
+        .stdout(io.stdout.to_stdio().map_err(stdio_error)?)
+        .stderr(io.stderr.to_stdio().map_err(stdio_error)?)
+        .status()
+        .map_err(|e| format!("env: {}: {}", program, e))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// `printenv [NAME]...`: prints the value of each exported variable named, or
+/// all of them. Fails if any name is not exported.
+pub fn printenv(args: Vec<&str>, vars: &Variables, io: &mut Io) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("printenv: {}", e);
+    if args.is_empty() {
+        for (name, value) in vars.exported() {
+            writeln!(io.stdout, "{}={}", name, value).map_err(write_error)?;
+        }
+        return Ok(());
+    }
+
+    let mut missing = false;
+    for name in args {
+        match vars.exported().find(|(exported, _)| *exported == name) {
+            Some((_, value)) => writeln!(io.stdout, "{}", value).map_err(write_error)?,
+            None => missing = true,
+        }
+    }
+    if missing {
+        // Like the coreutils version, a missing name only changes the status
+        return Err(String::new());
+    }
+    Ok(())
+}
@@ -0,0 +1,701 @@
+// exec.rs
+//
+// Runs parsed command lists and the pipelines inside them. External commands
+// are spawned with their stdio wired to the pipeline's pipes. Builtins,
+// functions and compound commands run in the shell itself when they are the
+// whole pipeline; as one stage of several they run in a forked copy of the
+// shell, so that every stage makes progress at the same time and gets Ctrl+C
+// and Ctrl+Z from the terminal, and the pipeline waits for it like an
+// external command. A `( list )` subshell is always such a copy, and a list
+// item ending in `&` runs in one that becomes a background job.
+//
+// The shell never starts a thread, which is what makes forking it safe: the
+// child is a consistent copy that can go on running shell code, `$(...)` and
+// `&` included, without finding a lock held by a thread that is not there.
+//
+// Output is never buffered by the shell. A command writing to the terminal
+// writes to it directly, and `capture` collects output through a pipe only
+// when the caller asks for it.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::mem;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+use std::sync::Arc;
+
+use crate::arith;
+use crate::builtins;
+use crate::exit;
+use crate::expand;
+use crate::jobs::{self, Jobs, ProcessState};
+use crate::parser::{
+    AndOr, Command as ParsedCommand, CommandList, CompoundCommand, Connector, Pipeline,
+    SimpleCommand,
+};
+use crate::pattern;
+use crate::redirect;
+use crate::signals;
+use crate::state::{Flow, Shell};
+use crate::stream::{self, Io, Stream};
+use crate::terminal;
+use crate::test;
+
+/// Runs every item of `list` in turn and returns the status of the last one.
+/// Stops early for `break` or `continue`, or when Ctrl+C interrupted a command.
+pub fn run_list(shell: &mut Shell, list: &CommandList, io: &Io) -> i32 {
+    let mut status = 0;
+    for item in &list.items {
+        status = if item.background {
+            run_background(shell, item, io)
+        } else {
+            run_and_or(shell, item, io)
+        };
+        if shell.flow.is_some() || signals::interrupted() {
+            break;
+        }
+    }
+    status
+}
+
+/// Starts `and_or` as a background job in a forked copy of the shell and
+/// returns at once.
+fn run_background(shell: &mut Shell, and_or: &AndOr, io: &Io) -> i32 {
+    let interactive = shell.terminal.is_some();
+    // Anything still buffered would otherwise be written by both processes
+    let _ = std::io::stdout().flush();
+    // SAFETY: the shell runs on a single thread, so the child gets a
+    // consistent copy of it, locks included
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        let _ = writeln!(io.stderr.clone(), "Error: {}", std::io::Error::last_os_error());
+        return 1;
+    }
+
+    if pid == 0 {
+        let mut io = io.clone();
+        if interactive {
+            // SAFETY: plain libc call in our own process
+            unsafe {
+                libc::setpgid(0, 0);
+            }
+            terminal::reset_signals();
+        } else if let Ok(null) = File::open("/dev/null") {
+            // Without job control a background job must not eat the shell's input
+            io.stdin = Stream::File(Arc::new(null));
+        }
+        shell.terminal = None;
+        shell.jobs = Jobs::default();
+        io.close_others();
+        let status = run_and_or(shell, and_or, &io);
+        exit::exit(status);
+    }
+
+    if interactive {
+        // SAFETY: `pid` is our own child; mirrors the call it makes itself
+        unsafe {
+            libc::setpgid(pid, pid);
+        }
+    }
+    let id = shell
+        .jobs
+        .add(pid, vec![(pid, ProcessState::Running)], and_or.to_string());
+    if interactive {
+        let _ = writeln!(io.stderr.clone(), "[{}] {}", id, pid);
+    }
+    shell.last_background = Some(pid);
+    0
+}
+
+/// Runs an `&&`/`||` chain, skipping pipelines whose condition is not met.
+pub fn run_and_or(shell: &mut Shell, and_or: &AndOr, io: &Io) -> i32 {
+    let mut status = run_pipeline(shell, &and_or.first, io);
+    shell.last_status = status;
+    for (connector, pipeline) in &and_or.rest {
+        let run = match connector {
+            Connector::And => status == 0,
+            Connector::Or => status != 0,
+        };
+        if shell.flow.is_some() || signals::interrupted() {
+            break;
+        }
+        if run {
+            status = run_pipeline(shell, pipeline, io);
+            shell.last_status = status;
+        }
+    }
+    status
+}
+
+/// Runs `pipeline` with its first stage reading `io.stdin`, its last stage
+/// writing `io.stdout` and every stage writing errors to `io.stderr`.
+/// Returns the exit status of the last stage.
+pub fn run_pipeline(shell: &mut Shell, pipeline: &Pipeline, io: &Io) -> i32 {
+    let count = pipeline.commands.len();
+    let mut processes: Vec<(libc::pid_t, ProcessState)> = Vec::new();
+    let mut status = 0;
+    // The last stage's process, whose exit status becomes the pipeline's
+    let mut last_pid: Option<libc::pid_t> = None;
+    // The read end of the previous stage's pipe
+    let mut stdin = io.stdin.clone();
+    // Process group shared by the pipeline's external commands
+    let mut pgid: Option<libc::pid_t> = None;
+
+    for (i, command) in pipeline.commands.iter().enumerate() {
+        let last = i + 1 == count;
+        // A stage that is the whole pipeline runs in the shell itself
+        let alone = count == 1;
+
+        let (next_stdin, stdout) = if last {
+            (io.stdin.clone(), io.stdout.clone())
+        } else {
+            match stream::pipe() {
+                Ok(ends) => ends,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    break;
+                }
+            }
+        };
+        let mut io = Io {
+            stdin: mem::replace(&mut stdin, next_stdin),
+            stdout,
+            stderr: io.stderr.clone(),
+        };
+
+        // A stage of several that the shell runs itself, in a forked copy of
+        // the shell that the pipeline waits for like an external command
+        let mut fork_stage = |shell: &mut Shell, io: &Io, run: &dyn Fn(&mut Shell, &Io) -> i32| {
+            match spawn_stage(shell, io, pgid, run) {
+                Ok(pid) => {
+                    join_pipeline(shell, pid, &mut pgid);
+                    if last {
+                        last_pid = Some(pid);
+                    }
+                    processes.push((pid, ProcessState::Running));
+                    true
+                }
+                Err(e) => {
+                    let _ = writeln!(io.stderr.clone(), "shell: fork: {}", describe(&e));
+                    false
+                }
+            }
+        };
+
+        status = 0;
+        let command = match command {
+            ParsedCommand::Simple(command) => command,
+            ParsedCommand::Function(name, body) => {
+                shell.functions.insert(name.clone(), body.clone());
+                continue;
+            }
+            ParsedCommand::Compound(compound, redirects) => {
+                if let Err(e) = redirect::apply(shell, redirects, &mut io) {
+                    let _ = writeln!(io.stderr, "Error: {}", e);
+                    status = 1;
+                    continue;
+                }
+                let started = match compound {
+                    // A separate process even when it is the whole pipeline,
+                    // so that Ctrl+C and Ctrl+Z reach it
+                    CompoundCommand::Subshell(body) => {
+                        fork_stage(shell, &io, &|shell, io| run_list(shell, body, io))
+                    }
+                    _ if alone => {
+                        status = run_compound(shell, compound, &io);
+                        true
+                    }
+                    _ => fork_stage(shell, &io, &|shell, io| run_compound(shell, compound, io)),
+                };
+                if !started {
+                    status = 1;
+                    break;
+                }
+                continue;
+            }
+        };
+        shell.substitution_status = None;
+        let (assignments, words) = match expand_command(shell, command) {
+            Ok(expanded) => expanded,
+            Err(e) => {
+                let _ = writeln!(io.stderr, "{}", e);
+                status = 1;
+                continue;
+            }
+        };
+        // Ctrl+C during a command substitution drops the whole command
+        if signals::interrupted() {
+            status = 130;
+            continue;
+        }
+        if let Err(e) = redirect::apply(shell, &command.redirects, &mut io) {
+            let _ = writeln!(io.stderr, "Error: {}", e);
+            status = 1;
+            continue;
+        }
+        if words.is_empty() {
+            // Without a command name, assignments change the shell itself. A
+            // bare redirection such as `> file` only creates or truncates the file
+            if count == 1 {
+                for (name, value) in &assignments {
+                    shell.vars.set(name, value);
+                }
+            }
+            // `x=$(cmd)` takes the status of `cmd`
+            status = shell.substitution_status.unwrap_or(0);
+            continue;
+        }
+
+        if let Some(body) = shell.functions.get(&words[0]).cloned() {
+            if alone {
+                status = run_function(shell, &body, &assignments, &words, &io);
+            } else if !fork_stage(shell, &io, &|shell, io| {
+                run_function(shell, &body, &assignments, &words, io)
+            }) {
+                status = 1;
+                break;
+            }
+        } else if builtins::is_builtin(&words[0]) {
+            if alone {
+                status = run_builtin(shell, &assignments, &words, &mut io);
+            } else if !fork_stage(shell, &io, &|shell, io| {
+                run_builtin(shell, &assignments, &words, &mut io.clone())
+            }) {
+                status = 1;
+                break;
+            }
+        } else {
+            match spawn_external(shell, &assignments, &words, &io, pgid) {
+                Ok(child) => {
+                    let pid = child.id() as libc::pid_t;
+                    join_pipeline(shell, pid, &mut pgid);
+                    if last {
+                        last_pid = Some(pid);
+                    }
+                    processes.push((pid, ProcessState::Running));
+                }
+                Err(e) => {
+                    status = if e.kind() == std::io::ErrorKind::NotFound {
+                        let _ = writeln!(
+                            io.stderr,
+                            "{}{}: command not found",
+                            shell.location, words[0]
+                        );
+                        127
+                    } else {
+                        let _ = writeln!(io.stderr, "{}: {}", words[0], e);
+                        126
+                    };
+                }
+            }
+            // Dropping `io` here closes the shell's copies of the pipe ends,
+            // so the neighbouring stages see EOF once the child exits
+        }
+    }
+
+    // Only an interactive shell stops waiting when Ctrl+Z stops the pipeline
+    jobs::wait_for(&mut processes, shell.terminal.is_some(), false);
+    if let Some(terminal) = &shell.terminal {
+        terminal.reclaim();
+    }
+
+    if processes.iter().any(|(_, state)| *state == ProcessState::Stopped) {
+        let pgid = pgid.unwrap_or(processes[0].0);
+        let id = shell.jobs.add(pgid, processes, pipeline.to_string());
+        return jobs::suspended(shell, id, &mut io.clone());
+    }
+    for (pid, state) in &processes {
+        if let (Some(last), ProcessState::Exited(code)) = (last_pid, state) {
+            if *pid == last {
+                status = *code;
+            }
+        }
+    }
+    // A command killed by Ctrl+C interrupts the shell's own work too, such
+    // as the loop it runs in
+    if shell.terminal.is_some() && last_pid.is_some() && status == 128 + libc::SIGINT {
+        signals::interrupt();
+    }
+    if pipeline.negated {
+        status = i32::from(status == 0);
+    }
+    status
+}
+
+/// Puts a pipeline's child process, an external command or a forked stage,
+/// into the pipeline's process group, the first child's own, and hands that
+/// group the terminal. Ctrl+C and Ctrl+Z then reach every stage, and a pager
+/// anywhere in the pipeline can read keys, as in `history | less`. Does
+/// nothing without job control.
+fn join_pipeline(shell: &Shell, pid: libc::pid_t, pgid: &mut Option<libc::pid_t>) {
+    if let Some(terminal) = &shell.terminal {
+        let group = *pgid.get_or_insert(pid);
+        // SAFETY: `pid` is our own child; mirrors the call it makes itself
+        unsafe {
+            libc::setpgid(pid, group);
+        }
+        terminal.give_to(group);
+    }
+}
+
+/// Runs a pipeline stage in a forked copy of the shell and returns the
+/// child's pid: the body of a `( list )`, or a builtin, function or compound
+/// command in a pipeline of several stages. Whatever the stage changes, from
+/// the working directory to variables and functions, goes away with the child.
+fn spawn_stage(
+    shell: &mut Shell,
+    io: &Io,
+    pgid: Option<libc::pid_t>,
+    run: &dyn Fn(&mut Shell, &Io) -> i32,
+) -> std::io::Result<libc::pid_t> {
+    // Anything still buffered would otherwise be written by both processes
+    let _ = std::io::stdout().flush();
+    // SAFETY: the shell runs on a single thread, so the child gets a
+    // consistent copy of it, locks included
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if pid == 0 {
+        if let Some(terminal) = shell.terminal.take() {
+            let _ = terminal.prepare_child(pgid.unwrap_or(0));
+        }
+        // Like any other stage, die once the next stage stops reading
+        // SAFETY: plain libc call in our own process
+        unsafe {
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        }
+        shell.jobs = Jobs::default();
+        io.close_others();
+        let status = run(shell, io);
+        exit::exit(status);
+    }
+    Ok(pid)
+}
+
+/// An I/O error without the ` (os error N)` Rust adds to it, as in
+/// `shell: fork: Resource temporarily unavailable` or `ls: cannot access
+/// 'x': No such file or directory`.
+pub(crate) fn describe(e: &std::io::Error) -> String {
+    let text = e.to_string();
+    match text.find(" (os error") {
+        Some(end) => text[..end].to_string(),
+        None => text,
+    }
+}
+
+/// Runs an `if`, loop, `case`, `{ ...; }` group, `(( ))` or `[[ ]]` in the
+/// shell itself and returns its status. A `( list )` outside a pipeline, as
+/// the body of a function, still gets its own process.
+fn run_compound(shell: &mut Shell, compound: &CompoundCommand, io: &Io) -> i32 {
+    match compound {
+        CompoundCommand::Group(body) => run_list(shell, body, io),
+        CompoundCommand::Subshell(_) => {
+            let pipeline = Pipeline {
+                negated: false,
+                commands: vec![ParsedCommand::Compound(compound.clone(), Vec::new())],
+            };
+            run_pipeline(shell, &pipeline, io)
+        }
+        CompoundCommand::Conditional(expr) => match test::conditional(shell, expr) {
+            Ok(result) => i32::from(!result),
+            Err(e) => {
+                let _ = writeln!(io.stderr.clone(), "{}", e);
+                2
+            }
+        },
+        CompoundCommand::Arithmetic(expr) => {
+            let value = expand::expand_arithmetic(shell, expr)
+                .and_then(|expr| arith::evaluate(shell, &expr));
+            match value {
+                Ok(value) => i32::from(value == 0),
+                Err(e) => {
+                    let _ = writeln!(io.stderr.clone(), "{}", e);
+                    1
+                }
+            }
+        }
+        CompoundCommand::If {
+            branches,
+            otherwise,
+        } => {
+            for (condition, body) in branches {
+                let test = run_list(shell, condition, io);
+                if shell.flow.is_some() || signals::interrupted() {
+                    return test;
+                }
+                if test == 0 {
+                    return run_list(shell, body, io);
+                }
+            }
+            match otherwise {
+                Some(body) => run_list(shell, body, io),
+                None => 0,
+            }
+        }
+        CompoundCommand::Loop {
+            until,
+            condition,
+            body,
+        } => {
+            let mut status = 0;
+            shell.loop_depth += 1;
+            loop {
+                let test = run_list(shell, condition, io);
+                if !keep_looping(shell) || (test == 0) == *until {
+                    break;
+                }
+                status = run_list(shell, body, io);
+                if !keep_looping(shell) {
+                    break;
+                }
+            }
+            shell.loop_depth -= 1;
+            status
+        }
+        CompoundCommand::For { name, words, body } => {
+            let values = match words {
+                Some(words) => match expand::expand_words(shell, words) {
+                    Ok(values) => values,
+                    Err(e) => {
+                        let _ = writeln!(io.stderr.clone(), "{}", e);
+                        return 1;
+                    }
+                },
+                None => shell.positional.clone(),
+            };
+            let mut status = 0;
+            shell.loop_depth += 1;
+            for value in values {
+                shell.vars.set(name, &value);
+                status = run_list(shell, body, io);
+                if !keep_looping(shell) {
+                    break;
+                }
+            }
+            shell.loop_depth -= 1;
+            status
+        }
+        CompoundCommand::Case { word, items } => {
+            let value = match expand::expand_word(shell, word) {
+                Ok(value) => value,
+                Err(e) => {
+                    let _ = writeln!(io.stderr.clone(), "{}", e);
+                    return 1;
+                }
+            };
+            for item in items {
+                for pattern in &item.patterns {
+                    match expand::expand_pattern(shell, pattern) {
+                        Ok(pattern) if pattern::matches(&pattern, &value) => {
+                            return run_list(shell, &item.body, io);
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            let _ = writeln!(io.stderr.clone(), "{}", e);
+                            return 1;
+                        }
+                    }
+                }
+            }
+            0
+        }
+    }
+}
+
+/// Deepest function nesting allowed, so runaway recursion is an error
+/// instead of a crash.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Calls a function with `words[1..]` as its positional parameters.
+fn run_function(
+    shell: &mut Shell,
+    body: &ParsedCommand,
+    assignments: &Assignments,
+    words: &[String],
+    io: &Io,
+) -> i32 {
+    if shell.vars.scope_depth() >= MAX_CALL_DEPTH {
+        let _ = writeln!(
+            io.stderr.clone(),
+            "{}: maximum function nesting level exceeded ({})",
+            words[0],
+            MAX_CALL_DEPTH
+        );
+        return 1;
+    }
+    let positional = mem::replace(&mut shell.positional, words[1..].to_vec());
+    // `break` inside a function cannot reach the caller's loops
+    let loop_depth = mem::take(&mut shell.loop_depth);
+    shell.vars.push_scope();
+    // Prefix assignments last for the call, like a local the function exports
+    for (name, value) in assignments {
+        shell.vars.make_local(name);
+        shell.vars.set(name, value);
+        shell.vars.export(name);
+    }
+
+    let status = match body {
+        ParsedCommand::Compound(compound, redirects) => {
+            let mut io = io.clone();
+            match redirect::apply(shell, redirects, &mut io) {
+                Ok(()) => run_compound(shell, compound, &io),
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "Error: {}", e);
+                    1
+                }
+            }
+        }
+        // The parser only accepts a compound command as a body
+        _ => 0,
+    };
+    if shell.flow == Some(Flow::Return) {
+        shell.flow = None;
+    }
+
+    shell.vars.pop_scope();
+    shell.loop_depth = loop_depth;
+    shell.positional = positional;
+    status
+}
+
+/// Acts on a pending `break` or `continue` at the end of a loop iteration.
+/// Returns whether this loop should go on with its next iteration; a jump
+/// meant for an outer loop is passed on with one level used up.
+fn keep_looping(shell: &mut Shell) -> bool {
+    if signals::interrupted() {
+        return false;
+    }
+    match shell.flow.take() {
+        None | Some(Flow::Continue(1)) => true,
+        Some(Flow::Break(1)) => false,
+        Some(Flow::Break(n)) => {
+            shell.flow = Some(Flow::Break(n - 1));
+            false
+        }
+        Some(Flow::Continue(n)) => {
+            shell.flow = Some(Flow::Continue(n - 1));
+            false
+        }
+        // `return` leaves every loop inside the function
+        Some(Flow::Return) => {
+            shell.flow = Some(Flow::Return);
+            false
+        }
+    }
+}
+
+/// Runs `list` in a forked copy of the shell, as for `$(...)`, and returns
+/// everything it wrote to stdout along with its exit status. Being a copy,
+/// it can `cd`, set variables or `exit` without affecting the shell.
+pub fn capture(shell: &mut Shell, list: &CommandList, io: &Io) -> (String, i32) {
+    let (mut reader, writer) = match stream::pipe() {
+        Ok(ends) => ends,
+        Err(e) => {
+            let _ = writeln!(io.stderr.clone(), "Error: {}", e);
+            return (String::new(), 1);
+        }
+    };
+    let _ = std::io::stdout().flush();
+    // SAFETY: the shell runs on a single thread, so the child gets a
+    // consistent copy of it, locks included
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        let _ = writeln!(io.stderr.clone(), "Error: {}", std::io::Error::last_os_error());
+        return (String::new(), 1);
+    }
+
+    if pid == 0 {
+        drop(reader);
+        let io = Io {
+            stdout: writer,
+            ..io.clone()
+        };
+        shell.terminal = None;
+        shell.jobs = Jobs::default();
+        io.close_others();
+        let status = run_list(shell, list, &io);
+        exit::exit(status);
+    }
+
+    drop(writer);
+    let mut output = Vec::new();
+    // Ctrl+C stops the read early; the child is interrupted too
+    let _ = reader.read_to_end(&mut output);
+    drop(reader);
+    let mut processes = [(pid, ProcessState::Running)];
+    jobs::wait_for(&mut processes, false, false);
+    let status = match processes[0].1 {
+        ProcessState::Exited(status) => status,
+        _ => 1,
+    };
+    (String::from_utf8_lossy(&output).into_owned(), status)
+}
+
+/// `NAME=value` pairs written before a command.
+type Assignments = Vec<(String, String)>;
+
+/// Expands a command's assignments and words.
+fn expand_command(
+    shell: &mut Shell,
+    command: &SimpleCommand,
+) -> Result<(Assignments, Vec<String>), String> {
+    let mut assignments = Vec::new();
+    for assignment in &command.assignments {
+        let value = expand::expand_word(shell, &assignment.value)?;
+        assignments.push((assignment.name.clone(), value));
+    }
+    let words = expand::expand_words(shell, &command.words)?;
+    Ok((assignments, words))
+}
+
+/// Runs a builtin with `assignments` in effect only for its duration.
+fn run_builtin(
+    shell: &mut Shell,
+    assignments: &Assignments,
+    words: &[String],
+    io: &mut Io,
+) -> i32 {
+    let saved: Vec<_> = assignments
+        .iter()
+        .map(|(name, _)| (name, shell.vars.var(name).cloned()))
+        .collect();
+    // Like for external commands, prefix assignments are in the environment
+    for (name, value) in assignments {
+        shell.vars.set(name, value);
+        shell.vars.export(name);
+    }
+
+    let args: Vec<&str> = words[1..].iter().map(String::as_str).collect();
+    let status = builtins::run(shell, &words[0], args, io);
+
+    for (name, var) in saved.into_iter().rev() {
+        shell.vars.restore(name, var);
+    }
+    status
+}
+
+fn spawn_external(
+    shell: &Shell,
+    assignments: &Assignments,
+    words: &[String],
+    io: &Io,
+    pgid: Option<libc::pid_t>,
+) -> std::io::Result<Child> {
+    let mut command = Command::new(&words[0]);
+    shell.vars.apply_env(&mut command);
+    command
+        .args(&words[1..])
+        .envs(assignments.iter().cloned())
+        .stdin(io.stdin.to_stdio()?)
+        .stdout(io.stdout.to_stdio()?)
+        .stderr(io.stderr.to_stdio()?);
+    if let Some(terminal) = shell.terminal {
+        let pgid = pgid.unwrap_or(0);
+        // SAFETY: `prepare_child` only makes async-signal-safe calls
+        unsafe {
+            command.pre_exec(move || terminal.prepare_child(pgid));
+        }
+    }
+    command.spawn()
+}
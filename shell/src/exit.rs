@@ -1,5 +1,31 @@
+use std::io::{self, Write};
 use std::process;
 
+use crate::getopt::Spec;
+
+pub const SPEC: Spec = Spec::new(
+    "Leave the shell, with the status of the last command by default.",
+    "[n]",
+    &[],
+)
+.numbers();
+
 pub fn exit(code: i32) -> ! {
+    // `process::exit` skips destructors, so push out anything still buffered
+    let _ = io::stdout().flush();
     process::exit(code);
 }
+
+/// Works out the status for `exit [n]`; without an argument the shell exits
+/// with the status of the last command.
+pub fn exit_code(args: &[&str], last_status: i32) -> Result<i32, String> {
+    match args {
+        [] => Ok(last_status),
+        [code] => code
+            .parse::<i64>()
+            // Like other shells, only the low eight bits reach the parent
+            .map(|code| (code & 0xff) as i32)
+            .map_err(|_| format!("exit: {}: numeric argument required", code)),
+        _ => Err("exit: too many arguments".to_string()),
+    }
+}
@@ -0,0 +1,837 @@
+// expand.rs
+//
+// Turns the words produced by the lexer into the strings a command receives.
+// Single-quoted text is taken as is. Unquoted and double-quoted text have
+// their parameters expanded (`$NAME`, `${NAME:-default}`, `$?` ...) and
+// commands substituted with their output (`$(cmd)` or `` `cmd` ``), the
+// results of unquoted expansions are split into separate fields on `IFS`, and
+// finally each field is matched against the filesystem if it holds unquoted
+// wildcards.
+//
+// While a field is being built, quoted characters are kept escaped with a
+// backslash (see `pattern::escape`) so that globbing can tell a quoted `*`
+// from one typed bare. The escapes are removed once globbing is done.
+
+use users::os::unix::UserExt;
+
+use crate::arith;
+use crate::exec;
+use crate::glob;
+use crate::lexer::{self, Word, WordPart};
+use crate::parser;
+use crate::pattern;
+use crate::state::Shell;
+use crate::stream::Io;
+
+/// Field separators used when `IFS` is unset.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// Expands `words` into command arguments. One word may produce several
+/// fields (`$LIST` holding `a b`, or `*.rs` matching several files) or none
+/// at all (an unset `$EMPTY`).
+pub fn expand_words(shell: &mut Shell, words: &[Word]) -> Result<Vec<String>, String> {
+    let ifs = shell
+        .vars
+        .get("IFS")
+        .unwrap_or(DEFAULT_IFS)
+        .to_string();
+    let mut fields = Vec::new();
+    for word in words {
+        let mut builder = Fields::new(Some(ifs.clone()));
+        expand_parts(shell, word, &mut builder)?;
+        for field in builder.finish() {
+            fields.extend(glob::expand(&field, &shell.options)?);
+        }
+    }
+    Ok(fields)
+}
+
+/// Expands `word` into a single string without field splitting, as used for
+/// assignment values and redirection targets.
+pub fn expand_word(shell: &mut Shell, word: &Word) -> Result<String, String> {
+    let mut builder = Fields::new(None);
+    expand_parts(shell, word, &mut builder)?;
+    Ok(pattern::unescape(&builder.finish().concat()))
+}
+
+/// Expands a `case` pattern like `expand_word`, but leaves quoted characters
+/// escaped so that they only match themselves.
+pub fn expand_pattern(shell: &mut Shell, word: &Word) -> Result<String, String> {
+    let mut builder = Fields::new(None);
+    expand_parts(shell, word, &mut builder)?;
+    Ok(builder.finish().concat())
+}
+
+/// Expands the regular expression after `=~` in `[[ ]]`. Quoted parts
+/// match literally; everything else, expansions included, is regex syntax.
+pub fn expand_regex(shell: &mut Shell, word: &Word) -> Result<String, String> {
+    let mut builder = Fields::new(None);
+    builder.regex = true;
+    expand_parts(shell, word, &mut builder)?;
+    Ok(builder.finish().concat())
+}
+
+/// Collects expanded text into fields, with quoted characters escaped.
+struct Fields {
+    /// Separators to split unquoted expansions on, or `None` to never split.
+    ifs: Option<String>,
+    /// Escape quoted text for a regular expression rather than a pattern.
+    regex: bool,
+    done: Vec<String>,
+    current: String,
+    /// Whether `current` holds a field, even an empty one such as `""`.
+    started: bool,
+}
+
+impl Fields {
+    fn new(ifs: Option<String>) -> Fields {
+        Fields {
+            ifs,
+            regex: false,
+            done: Vec::new(),
+            current: String::new(),
+            started: false,
+        }
+    }
+
+    /// Adds quoted text, which is neither split nor globbed.
+    fn push_quoted(&mut self, text: &str) {
+        if self.regex {
+            self.current.push_str(&regex::escape(text));
+        } else {
+            self.current.push_str(&pattern::escape(text));
+        }
+        self.started = true;
+    }
+
+    /// Adds unquoted text typed on the command line; its wildcards stay active.
+    fn push_literal(&mut self, text: &str) {
+        self.current.push_str(text);
+        self.started |= !text.is_empty();
+    }
+
+    /// Adds the result of an unquoted expansion, splitting it on `IFS`. Its
+    /// wildcards stay active too.
+    fn push_unquoted(&mut self, text: &str) {
+        let Some(ifs) = self.ifs.clone() else {
+            if self.regex {
+                self.push_literal(text);
+            } else {
+                self.push_literal(&text.replace('\\', "\\\\"));
+            }
+            return;
+        };
+        for c in text.chars() {
+            if !ifs.contains(c) {
+                if c == '\\' {
+                    self.current.push('\\');
+                }
+                self.current.push(c);
+                self.started = true;
+            } else if c.is_whitespace() {
+                // Runs of whitespace separate fields without creating empty ones
+                self.break_field();
+            } else {
+                // Any other separator always ends a field, even an empty one
+                self.started = true;
+                self.break_field();
+            }
+        }
+    }
+
+    /// Ends the current field even if it is empty, as between the
+    /// parameters of `"$@"`. Without splitting they are joined by a space.
+    fn next_field(&mut self) {
+        if self.ifs.is_none() {
+            self.current.push(' ');
+        } else {
+            self.started = true;
+            self.break_field();
+        }
+    }
+
+    fn break_field(&mut self) {
+        if self.started {
+            self.done.push(std::mem::take(&mut self.current));
+            self.started = false;
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.break_field();
+        self.done
+    }
+}
+
+fn expand_parts(shell: &mut Shell, word: &Word, out: &mut Fields) -> Result<(), String> {
+    for (i, part) in word.parts.iter().enumerate() {
+        match part {
+            WordPart::Literal(s) => {
+                let s = if i == 0 { expand_tilde(shell, s, out) } else { s };
+                expand_unquoted(shell, s, out)?;
+            }
+            WordPart::Quoted(s) => out.push_quoted(s),
+            WordPart::DoubleQuoted(s) => {
+                // Only `"$@"` produces more than one field, or none at all
+                for (j, text) in expand_double_quoted(shell, s)?.iter().enumerate() {
+                    if j > 0 {
+                        out.next_field();
+                    }
+                    out.push_quoted(text);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces a leading `~` or `~user` with a home directory and returns the
+/// rest of `text`.
+fn expand_tilde<'a>(shell: &Shell, text: &'a str, out: &mut Fields) -> &'a str {
+    let Some(rest) = text.strip_prefix('~') else {
+        return text;
+    };
+    let end = rest.find('/').unwrap_or(rest.len());
+    match home_dir(shell, &rest[..end]) {
+        Some(home) => {
+            out.push_quoted(&home);
+            &rest[end..]
+        }
+        // An unknown user leaves the word alone
+        None => text,
+    }
+}
+
+/// The home directory `~user` stands for: `$HOME` for plain `~`, otherwise
+/// the one in the user database.
+pub fn home_dir(shell: &Shell, user: &str) -> Option<String> {
+    if user.is_empty() {
+        shell.vars.get("HOME").map(str::to_string)
+    } else {
+        users::get_user_by_name(user).map(|u| u.home_dir().to_string_lossy().into_owned())
+    }
+}
+
+/// Expands unquoted text. Backslashes and quotes were already turned into
+/// separate parts by the lexer, so only `$` and backticks are special here.
+fn expand_unquoted(shell: &mut Shell, text: &str, out: &mut Fields) -> Result<(), String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    let mut literal = String::new();
+    while i < chars.len() {
+        let expansion = match chars[i] {
+            '$' => expand_dollar(shell, &chars, i + 1)?,
+            '`' => Some(expand_backquoted(shell, &chars, i + 1)?),
+            _ => None,
+        };
+        if let Some((value, next)) = expansion {
+            out.push_literal(&std::mem::take(&mut literal));
+            out.push_unquoted(&value);
+            i = next;
+            continue;
+        }
+        literal.push(chars[i]);
+        i += 1;
+    }
+    out.push_literal(&literal);
+    Ok(())
+}
+
+/// Expands the raw text of a double-quoted part. The result is a single
+/// string, except that `"$@"` turns into one string per positional parameter.
+fn expand_double_quoted(shell: &mut Shell, raw: &str) -> Result<Vec<String>, String> {
+    // `"$@"` with no parameters stands for no field at all
+    if (raw == "$@" || raw == "${@}") && shell.positional.is_empty() {
+        return Ok(Vec::new());
+    }
+    let chars: Vec<char> = raw.chars().collect();
+    let mut fields = Vec::new();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                let (text, taken) = lexer::double_quoted_escape(chars.get(i + 1).copied());
+                out.extend(text);
+                i += if taken { 2 } else { 1 };
+            }
+            '$' if at_sign(&chars, i + 1).is_some() => {
+                for (j, param) in shell.positional.iter().enumerate() {
+                    if j > 0 {
+                        fields.push(std::mem::take(&mut out));
+                    }
+                    out.push_str(param);
+                }
+                i = at_sign(&chars, i + 1).unwrap_or(i + 1);
+            }
+            '`' => {
+                let (value, next) = expand_backquoted(shell, &chars, i + 1)?;
+                out.push_str(&value);
+                i = next;
+            }
+            '$' => match expand_dollar(shell, &chars, i + 1)? {
+                Some((value, next)) => {
+                    out.push_str(&value);
+                    i = next;
+                }
+                None => {
+                    out.push('$');
+                    i += 1;
+                }
+            },
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    fields.push(out);
+    Ok(fields)
+}
+
+/// Expands the body of a here-document. It is treated like text in double
+/// quotes, except that a double quote is not special: `\"` stays as it is.
+pub fn expand_here_doc(shell: &mut Shell, body: &str) -> Result<String, String> {
+    let mut raw = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('"')) => {
+                // Escape the backslash so that both characters survive
+                raw.push_str("\\\\\"");
+                chars.next();
+            }
+            ('\\', Some(next)) => {
+                raw.push(c);
+                raw.push(next);
+                chars.next();
+            }
+            _ => raw.push(c),
+        }
+    }
+    Ok(expand_double_quoted(shell, &raw)?.join(" "))
+}
+
+/// Expands the parameters and command substitutions in an arithmetic
+/// expression, which is treated like text in double quotes.
+pub fn expand_arithmetic(shell: &mut Shell, expr: &str) -> Result<String, String> {
+    Ok(expand_double_quoted(shell, expr)?.join(" "))
+}
+
+/// If `chars[start..]` begins with `@` or `{@}`, returns the index just past it.
+fn at_sign(chars: &[char], start: usize) -> Option<usize> {
+    match chars.get(start..start + 3) {
+        Some(['{', '@', '}']) => Some(start + 3),
+        _ if chars.get(start) == Some(&'@') => Some(start + 1),
+        _ => None,
+    }
+}
+
+/// Expands the parameter following a `$` at `chars[start - 1]`. Returns the
+/// value and the index just past the expansion, or `None` if the `$` does not
+/// start an expansion and should be kept literally.
+fn expand_dollar(
+    shell: &mut Shell,
+    chars: &[char],
+    start: usize,
+) -> Result<Option<(String, usize)>, String> {
+    let Some(&c) = chars.get(start) else {
+        return Ok(None);
+    };
+    if c == '(' && chars.get(start + 1) == Some(&'(') {
+        // `$(( expr ))`, unless the inner parentheses close before the end
+        // as in `$((cd /tmp) && pwd)`
+        let end = lexer::find_closing_paren(chars, start + 2)?;
+        if chars.get(end + 1) == Some(&')') {
+            let expr: String = chars[start + 2..end].iter().collect();
+            let expr = expand_arithmetic(shell, &expr)?;
+            let value = arith::evaluate(shell, &expr)?;
+            return Ok(Some((value.to_string(), end + 2)));
+        }
+    }
+    if c == '(' {
+        let end = lexer::find_closing_paren(chars, start + 1)?;
+        let command: String = chars[start + 1..end].iter().collect();
+        let value = substitute(shell, &command)?;
+        return Ok(Some((value, end + 1)));
+    }
+    if c == '{' {
+        let end = lexer::find_closing_brace(chars, start + 1)?;
+        let body: String = chars[start + 1..end].iter().collect();
+        let value = expand_braced(shell, &body)?;
+        return Ok(Some((value, end + 1)));
+    }
+    if is_special(c) || c.is_ascii_digit() {
+        let value = shell.param(&c.to_string()).unwrap_or_default();
+        return Ok(Some((value, start + 1)));
+    }
+    if c.is_ascii_alphabetic() || c == '_' {
+        let mut end = start;
+        while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+            end += 1;
+        }
+        let name: String = chars[start..end].iter().collect();
+        let value = shell.param(&name).unwrap_or_default();
+        return Ok(Some((value, end)));
+    }
+    Ok(None)
+}
+
+/// Expands the `` `command` `` whose text starts at `chars[start]`. Returns
+/// the output and the index just past the closing backtick.
+fn expand_backquoted(
+    shell: &mut Shell,
+    chars: &[char],
+    start: usize,
+) -> Result<(String, usize), String> {
+    let end = lexer::find_closing_backtick(chars, start)?;
+    // Inside backticks a backslash only escapes `$`, a backtick or itself,
+    // which is what lets them nest as in `` `echo \`pwd\`` ``
+    let mut command = String::new();
+    let mut i = start;
+    while i < end {
+        match chars.get(i + 1) {
+            Some(&next) if chars[i] == '\\' && matches!(next, '$' | '`' | '\\') => {
+                command.push(next);
+                i += 2;
+            }
+            _ => {
+                command.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    Ok((substitute(shell, &command)?, end + 1))
+}
+
+/// Runs `command` and returns its output without the trailing newlines.
+fn substitute(shell: &mut Shell, command: &str) -> Result<String, String> {
+    let list = match parser::parse_input_with_aliases(command, &shell.aliases) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Ok(String::new()),
+        Err(e) => return Err(format!("command substitution: {}", e)),
+    };
+    let (output, status) = exec::capture(shell, &list, &Io::standard());
+    shell.substitution_status = Some(status);
+    Ok(output.trim_end_matches('\n').to_string())
+}
+
+/// Parameters with a one-character, non-alphanumeric name.
+fn is_special(c: char) -> bool {
+    matches!(c, '?' | '$' | '#' | '!' | '@' | '*' | '-')
+}
+
+/// Splits the body of `${...}` into the parameter name and whatever follows it.
+fn split_name(body: &str) -> (&str, &str) {
+    let first = match body.chars().next() {
+        Some(c) => c,
+        None => return ("", ""),
+    };
+    if is_special(first) {
+        return body.split_at(first.len_utf8());
+    }
+    let end = if first.is_ascii_digit() {
+        body.find(|c: char| !c.is_ascii_digit())
+    } else {
+        body.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+    };
+    body.split_at(end.unwrap_or(body.len()))
+}
+
+/// Expands the inside of `${...}`.
+fn expand_braced(shell: &mut Shell, body: &str) -> Result<String, String> {
+    let bad_substitution = || format!("${{{}}}: bad substitution", body);
+
+    // `${#NAME}` is the length of the value
+    if let Some(name) = body.strip_prefix('#') {
+        if !name.is_empty() {
+            let (name, rest) = split_name(name);
+            if name.is_empty() || !rest.is_empty() {
+                return Err(bad_substitution());
+            }
+            let value = shell.param(name).unwrap_or_default();
+            return Ok(value.chars().count().to_string());
+        }
+    }
+
+    let (name, rest) = split_name(body);
+    if name.is_empty() {
+        return Err(bad_substitution());
+    }
+    let value = shell.param(name);
+    if rest.is_empty() {
+        return Ok(value.unwrap_or_default());
+    }
+
+    // With a colon, an empty value counts as unset for `-`, `=`, `+` and `?`
+    let (colon, op_text) = match rest.strip_prefix(':') {
+        Some(op_text) => (true, op_text),
+        None => (false, rest),
+    };
+    let unset = match &value {
+        None => true,
+        Some(v) => colon && v.is_empty(),
+    };
+
+    let mut ops = op_text.chars();
+    let op = ops.next().ok_or_else(bad_substitution)?;
+    let operand = ops.as_str();
+    match op {
+        '-' => {
+            if unset {
+                expand_operand(shell, operand, false)
+            } else {
+                Ok(value.unwrap_or_default())
+            }
+        }
+        '=' => {
+            if !unset {
+                return Ok(value.unwrap_or_default());
+            }
+            if !crate::vars::is_valid_name(name) {
+                return Err(format!("${}: cannot assign in this way", name));
+            }
+            let default = expand_operand(shell, operand, false)?;
+            shell.vars.set(name, &default);
+            Ok(default)
+        }
+        '+' => {
+            if unset {
+                Ok(String::new())
+            } else {
+                expand_operand(shell, operand, false)
+            }
+        }
+        '?' => {
+            if !unset {
+                return Ok(value.unwrap_or_default());
+            }
+            let message = expand_operand(shell, operand, false)?;
+            if message.is_empty() {
+                Err(format!("{}: parameter null or not set", name))
+            } else {
+                Err(format!("{}: {}", name, message))
+            }
+        }
+        '#' | '%' if !colon => {
+            let value = value.unwrap_or_default();
+            let longest = operand.starts_with(op);
+            let operand = if longest { &operand[1..] } else { operand };
+            let pattern = expand_operand(shell, operand, true)?;
+            Ok(if op == '#' {
+                remove_prefix(&value, &pattern, longest)
+            } else {
+                remove_suffix(&value, &pattern, longest)
+            })
+        }
+        '/' if !colon => {
+            let value = value.unwrap_or_default();
+            let (all, operand) = match operand.strip_prefix('/') {
+                Some(operand) => (true, operand),
+                None => (false, operand),
+            };
+            let (raw_pattern, raw_replacement) = split_replacement(operand);
+            let pattern = expand_operand(shell, raw_pattern, true)?;
+            let replacement = expand_operand(shell, raw_replacement, false)?;
+            Ok(replace(&value, &pattern, &replacement, all))
+        }
+        _ => Err(bad_substitution()),
+    }
+}
+
+/// Expands the word after an operator inside `${...}`, honouring its quotes.
+/// With `as_pattern`, quoted characters are escaped so they match literally.
+fn expand_operand(shell: &mut Shell, raw: &str, as_pattern: bool) -> Result<String, String> {
+    let quote = |text: &str| {
+        if as_pattern {
+            pattern::escape(text)
+        } else {
+            text.to_string()
+        }
+    };
+    let chars: Vec<char> = raw.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\'' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| c == '\'')
+                    .map_or(chars.len(), |p| i + 1 + p);
+                let text: String = chars[i + 1..end].iter().collect();
+                out.push_str(&quote(&text));
+                i = end + 1;
+            }
+            '"' => {
+                let mut end = i + 1;
+                while end < chars.len() && chars[end] != '"' {
+                    if chars[end] == '\\' {
+                        end += 1;
+                    }
+                    end += 1;
+                }
+                let end = end.min(chars.len());
+                let inner: String = chars[i + 1..end].iter().collect();
+                let text = expand_double_quoted(shell, &inner)?.join(" ");
+                out.push_str(&quote(&text));
+                i = end + 1;
+            }
+            '\\' if i + 1 < chars.len() => {
+                out.push_str(&quote(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            '`' => {
+                let (value, next) = expand_backquoted(shell, &chars, i + 1)?;
+                out.push_str(&value);
+                i = next;
+            }
+            '$' => match expand_dollar(shell, &chars, i + 1)? {
+                Some((value, next)) => {
+                    out.push_str(&value);
+                    i = next;
+                }
+                None => {
+                    out.push('$');
+                    i += 1;
+                }
+            },
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Splits `pattern/replacement` at the first `/` that is not quoted or escaped.
+fn split_replacement(operand: &str) -> (&str, &str) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in operand.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (_, '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '/') => return (&operand[..i], &operand[i + 1..]),
+            _ => {}
+        }
+    }
+    (operand, "")
+}
+
+/// Byte offsets of every character boundary in `text`, including both ends.
+fn boundaries(text: &str) -> Vec<usize> {
+    let mut bounds: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    bounds.push(text.len());
+    bounds
+}
+
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut bounds = boundaries(value);
+    if longest {
+        bounds.reverse();
+    }
+    for end in bounds {
+        if pattern::matches(pattern, &value[..end]) {
+            return value[end..].to_string();
+        }
+    }
+    value.to_string()
+}
+
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut bounds = boundaries(value);
+    if !longest {
+        bounds.reverse();
+    }
+    for start in bounds {
+        if pattern::matches(pattern, &value[start..]) {
+            return value[..start].to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// Replaces the first (or every) longest match of `pattern` in `value`. A
+/// leading `#` or `%` anchors the pattern to the start or end of the value.
+fn replace(value: &str, pattern: &str, replacement: &str, all: bool) -> String {
+    let (pattern, anchor) = match pattern.chars().next() {
+        Some(c @ ('#' | '%')) => (&pattern[1..], Some(c)),
+        _ => (pattern, None),
+    };
+    if pattern.is_empty() && anchor.is_none() {
+        return value.to_string();
+    }
+
+    let bounds = boundaries(value);
+    let mut out = String::new();
+    let mut i = 0;
+    while i < bounds.len() {
+        let start = bounds[i];
+        let may_start = match anchor {
+            Some('#') => start == 0,
+            _ => true,
+        };
+        let found = may_start
+            .then(|| {
+                bounds[i..].iter().rev().copied().find(|&end| {
+                    (anchor != Some('%') || end == value.len())
+                        && pattern::matches(pattern, &value[start..end])
+                })
+            })
+            .flatten();
+
+        match found {
+            Some(end) => {
+                out.push_str(replacement);
+                if !all || end == value.len() {
+                    out.push_str(&value[end..]);
+                    return out;
+                }
+                if end == start {
+                    // An empty match: keep the character and move on
+                    out.push_str(&value[start..bounds[i + 1]]);
+                    i += 1;
+                } else {
+                    i = bounds.iter().position(|&b| b == end).unwrap_or(bounds.len());
+                }
+            }
+            None => {
+                if i + 1 < bounds.len() {
+                    out.push_str(&value[start..bounds[i + 1]]);
+                }
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+
+    /// The fields `input` expands to as command arguments.
+    fn fields(shell: &mut Shell, input: &str) -> Vec<String> {
+        let words: Vec<Word> = lexer::tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token {
+                Token::Word(word) => word,
+                other => panic!("not a word: {:?}", other),
+            })
+            .collect();
+        expand_words(shell, &words).unwrap()
+    }
+
+    fn braced(shell: &mut Shell, body: &str) -> String {
+        expand_braced(shell, body).unwrap()
+    }
+
+    #[test]
+    fn defaults_and_alternatives() {
+        let mut shell = Shell::default();
+        assert_eq!(braced(&mut shell, "x-a"), "a");
+        assert_eq!(braced(&mut shell, "x:-a"), "a");
+        assert_eq!(braced(&mut shell, "x+b"), "");
+        shell.vars.set("x", "");
+        assert_eq!(braced(&mut shell, "x-a"), "");
+        assert_eq!(braced(&mut shell, "x:-a"), "a");
+        assert_eq!(braced(&mut shell, "x+b"), "b");
+        assert_eq!(braced(&mut shell, "x:+b"), "");
+        assert_eq!(braced(&mut shell, "x:=c"), "c");
+        assert_eq!(shell.vars.get("x"), Some("c"));
+        assert_eq!(braced(&mut shell, "x:='d'"), "c");
+        assert_eq!(braced(&mut shell, "y-\"$x d\""), "c d");
+    }
+
+    #[test]
+    fn lengths_and_errors() {
+        let mut shell = Shell::default();
+        shell.vars.set("s", "héllo");
+        assert_eq!(braced(&mut shell, "#s"), "5");
+        assert_eq!(braced(&mut shell, "#unset"), "0");
+        assert_eq!(
+            expand_braced(&mut shell, "u?").unwrap_err(),
+            "u: parameter null or not set"
+        );
+        assert_eq!(expand_braced(&mut shell, "u:?no u").unwrap_err(), "u: no u");
+        assert_eq!(
+            expand_braced(&mut shell, "").unwrap_err(),
+            "${}: bad substitution"
+        );
+        assert_eq!(
+            expand_braced(&mut shell, "s:#a").unwrap_err(),
+            "${s:#a}: bad substitution"
+        );
+        assert_eq!(
+            expand_braced(&mut shell, "1=x").unwrap_err(),
+            "$1: cannot assign in this way"
+        );
+    }
+
+    #[test]
+    fn prefixes_and_suffixes() {
+        assert_eq!(remove_prefix("a/b/c", "*/", false), "b/c");
+        assert_eq!(remove_prefix("a/b/c", "*/", true), "c");
+        assert_eq!(remove_prefix("a/b/c", "x", false), "a/b/c");
+        assert_eq!(remove_suffix("f.tar.gz", ".*", false), "f.tar");
+        assert_eq!(remove_suffix("f.tar.gz", ".*", true), "f");
+        assert_eq!(remove_suffix("héé", "é", false), "hé");
+
+        let mut shell = Shell::default();
+        shell.vars.set("path", "/usr/local/bin");
+        assert_eq!(braced(&mut shell, "path##*/"), "bin");
+        assert_eq!(braced(&mut shell, "path%/*"), "/usr/local");
+        // A quoted pattern character matches only itself
+        shell.vars.set("star", "a*b*c");
+        assert_eq!(braced(&mut shell, "star#*'*'"), "b*c");
+        assert_eq!(braced(&mut shell, "star%%\\**"), "a");
+    }
+
+    #[test]
+    fn replacements() {
+        assert_eq!(replace("banana", "a", "o", false), "bonana");
+        assert_eq!(replace("banana", "a", "o", true), "bonono");
+        assert_eq!(replace("banana", "#b", "c", false), "canana");
+        assert_eq!(replace("banana", "#a", "c", false), "banana");
+        assert_eq!(replace("banana", "%a", "o", false), "banano");
+        // The longest match is replaced
+        assert_eq!(replace("abcabc", "a*c", "x", false), "x");
+        assert_eq!(replace("abc", "", "x", true), "abc");
+
+        let mut shell = Shell::default();
+        shell.vars.set("v", "a/b/c");
+        assert_eq!(braced(&mut shell, "v//\\//-"), "a-b-c");
+        assert_eq!(braced(&mut shell, "v/'/'"), "ab/c");
+    }
+
+    #[test]
+    fn field_splitting() {
+        let mut shell = Shell::default();
+        shell.vars.set("x", " a  b\tc ");
+        assert_eq!(fields(&mut shell, "$x"), ["a", "b", "c"]);
+        assert_eq!(fields(&mut shell, "\"$x\""), [" a  b\tc "]);
+        assert_eq!(fields(&mut shell, "1$x"), ["1", "a", "b", "c"]);
+        shell.vars.set("e", "");
+        assert!(fields(&mut shell, "$e").is_empty());
+        assert_eq!(fields(&mut shell, "\"$e\""), [""]);
+
+        // A separator other than whitespace ends a field even if it is empty
+        shell.vars.set("IFS", ":");
+        shell.vars.set("p", "a::b:");
+        assert_eq!(fields(&mut shell, "$p"), ["a", "", "b"]);
+        shell.vars.set("p", ":a");
+        assert_eq!(fields(&mut shell, "$p"), ["", "a"]);
+        // Without separators nothing is split
+        shell.vars.set("IFS", "");
+        assert_eq!(fields(&mut shell, "$x"), [" a  b\tc "]);
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::Write;
+
+use crate::getopt::{Args, Opt, Spec};
+use crate::stream::Io;
+use crate::vars::{self, Variables};
+
+pub const SPEC: Spec = Spec::new(
+    "Pass variables on to the commands the shell runs.",
+    "[name[=value] ...]",
+    &[Opt::flag('p', "print", "print the exported variables")],
+);
+
+pub fn export(args: Args, vars: &mut Variables, io: &mut Io) -> Result<(), String> {
+    if args.operands.is_empty() {
+        return print_exports(vars, io);
+    }
+
+    // Keep going after a bad name so the valid ones still get exported
+    let mut result = Ok(());
+    for arg in args.operands {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+        if !vars::is_valid_name(name) {
+            result = Err(format!("export: `{}': not a valid identifier", arg));
+            continue;
+        }
+        if let Some(value) = value {
+            vars.set(name, value);
+        }
+        vars.export(name);
+    }
+    result
+}
+
+/// Prints the exported variables in a form that can be read back by the shell.
+fn print_exports(vars: &Variables, io: &mut Io) -> Result<(), String> {
+    for (name, value) in vars.exported() {
+        writeln!(io.stdout, "export {}=\"{}\"", name, escape_double_quoted(value))
+            .map_err(|e| format!("export: {}", e))?;
+    }
+    Ok(())
+}
+
+fn escape_double_quoted(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
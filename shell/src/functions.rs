@@ -0,0 +1,104 @@
+// functions.rs
+//
+// Builtins for shell functions. A function is defined with `name() { ...; }`
+// and called like any command; while it runs, `$1`, `$2` ... are its own
+// arguments, `local` gives it variables that disappear when it returns, and
+// `return n` leaves it early with status n. `declare -f` lists definitions
+// and `unset -f` removes them.
+
+use std::io::Write;
+
+use crate::getopt::{Args, Opt, Spec};
+use crate::state::{Flow, Shell};
+use crate::stream::Io;
+use crate::vars;
+
+pub const LOCAL: Spec = Spec::new(
+    "Give the running function variables of its own.",
+    "name[=value] ...",
+    &[],
+);
+
+pub const RETURN: Spec =
+    Spec::new("Leave the running function or sourced file.", "[n]", &[]).numbers();
+
+pub const DECLARE: Spec = Spec::new(
+    "Print the definitions of functions, all of them by default.",
+    "[name ...]",
+    &[
+        Opt::flag('f', "functions", "print each function's definition"),
+        Opt::flag('F', "", "print only the names"),
+    ],
+);
+
+/// `local name[=value]...`
+pub fn local(args: Vec<&str>, shell: &mut Shell) -> Result<(), String> {
+    let mut result = Ok(());
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+        if !vars::is_valid_name(name) {
+            result = Err(format!("local: `{}': not a valid identifier", arg));
+            continue;
+        }
+        if !shell.vars.make_local(name) {
+            return Err("local: can only be used in a function".to_string());
+        }
+        if let Some(value) = value {
+            shell.vars.set(name, value);
+        }
+    }
+    result
+}
+
+/// `return [n]`: leaves the running function or sourced file. Without `n`
+/// the status is that of the last command it ran.
+pub fn return_from(args: Vec<&str>, shell: &mut Shell) -> Result<i32, String> {
+    if shell.vars.scope_depth() == 0 && shell.source_depth == 0 {
+        return Err("return: can only `return' from a function or sourced script".to_string());
+    }
+    let status = match args.as_slice() {
+        [] => shell.last_status,
+        [n] => n
+            .parse::<i64>()
+            // Only the low eight bits survive, as for `exit`
+            .map(|n| (n & 0xff) as i32)
+            .map_err(|_| format!("return: {}: numeric argument required", n))?,
+        _ => return Err("return: too many arguments".to_string()),
+    };
+    shell.flow = Some(Flow::Return);
+    Ok(status)
+}
+
+/// `declare -f [name...]` prints function definitions and `declare -F` only
+/// their names. Without names every function is listed.
+pub fn declare(args: Args, shell: &mut Shell, io: &mut Io) -> Result<(), String> {
+    if !args.has('f') && !args.has('F') {
+        return Err("declare: only -f and -F are supported".to_string());
+    }
+    let names_only = args.has('F');
+    let names = &args.operands;
+
+    let mut result = Ok(());
+    let listed: Vec<&str> = if names.is_empty() {
+        shell.functions.keys().map(String::as_str).collect()
+    } else {
+        names.to_vec()
+    };
+    for name in listed {
+        let Some(body) = shell.functions.get(name) else {
+            // Like other shells, a missing name only affects the status
+            result = Err(String::new());
+            continue;
+        };
+        let line = if names_only {
+            format!("declare -f {}", name)
+        } else {
+            format!("{} () {}", name, body)
+        };
+        writeln!(io.stdout, "{}", line).map_err(|e| format!("declare: {}", e))?;
+    }
+    result
+}
@@ -0,0 +1,400 @@
+// getopt.rs
+//
+// The option parser the builtins share. Each builtin describes its options
+// in a `Spec`, and the dispatcher parses its arguments with it before
+// calling it, so they all behave alike: short options can be clustered
+// (`ls -la`), long ones spelled out or shortened while unambiguous
+// (`--all`, `--al`), an option's argument can be attached (`-d5`,
+// `--offset=5`) or follow it, operands may come before, between or after
+// the options, and `--` makes everything after it an operand. `--help`
+// prints a summary generated from the spec, and a mistake prints the usage
+// line.
+//
+// `echo`, `test`, `[`, `set` and `let` read their arguments themselves, as
+// in other shells: `echo -x` prints `-x`, and `set +o` or `let -1` are not
+// options at all. Builtins that take a number, such as `exit` and `shift`,
+// say so in their spec, and for them a negative one is an operand.
+
+use std::fmt::Write;
+
+/// How a builtin is called.
+pub struct Spec {
+    /// What the builtin does, for `--help`.
+    pub about: &'static str,
+    /// The operands as shown in the usage line, such as `[file ...]`. A
+    /// builtin whose spec shows none does not take any.
+    pub operands: &'static str,
+    pub options: &'static [Opt],
+    /// Whether the options end at the first operand, for builtins that pass
+    /// the operands after it on, as in `env NAME=value command -x`.
+    pub in_order: bool,
+    /// Whether a negative number, as in `exit -1`, is an operand rather
+    /// than a cluster of options.
+    pub numbers: bool,
+}
+
+/// One option a builtin takes.
+pub struct Opt {
+    pub short: char,
+    /// The long name, without its `--`, or empty if there is none.
+    pub long: &'static str,
+    /// What the option's argument is called, if it takes one.
+    pub argument: Option<&'static str>,
+    pub help: &'static str,
+}
+
+impl Spec {
+    pub const fn new(about: &'static str, operands: &'static str, options: &'static [Opt]) -> Spec {
+        Spec {
+            about,
+            operands,
+            options,
+            in_order: false,
+            numbers: false,
+        }
+    }
+
+    pub const fn in_order(self) -> Spec {
+        Spec {
+            in_order: true,
+            ..self
+        }
+    }
+
+    pub const fn numbers(self) -> Spec {
+        Spec {
+            numbers: true,
+            ..self
+        }
+    }
+}
+
+impl Opt {
+    pub const fn flag(short: char, long: &'static str, help: &'static str) -> Opt {
+        Opt {
+            short,
+            long,
+            argument: None,
+            help,
+        }
+    }
+
+    pub const fn with_argument(
+        short: char,
+        long: &'static str,
+        argument: &'static str,
+        help: &'static str,
+    ) -> Opt {
+        Opt {
+            short,
+            long,
+            argument: Some(argument),
+            help,
+        }
+    }
+}
+
+/// The arguments of a builtin, sorted into options and operands.
+#[derive(Debug, Default)]
+pub struct Args<'a> {
+    /// The options given, by their short name, with their arguments.
+    pub options: Vec<(char, Option<&'a str>)>,
+    pub operands: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    /// Whether option `short` was given, in either form.
+    pub fn has(&self, short: char) -> bool {
+        self.options.iter().any(|(option, _)| *option == short)
+    }
+
+    /// The argument given to option `short`, the last one if it was given
+    /// more than once.
+    pub fn value(&self, short: char) -> Option<&'a str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| *option == short)
+            .and_then(|(_, value)| *value)
+    }
+}
+
+/// Sorts the arguments of builtin `command` into options and operands.
+/// Returns `None` when `--help` was asked for.
+pub fn parse<'a>(command: &str, spec: &Spec, args: &[&'a str]) -> Result<Option<Args<'a>>, String> {
+    let error = |message: String| format!("{}: {}\n{}", command, message, usage(command, spec));
+    let mut parsed = Args::default();
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.operands.extend(args);
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let Some(option) = find_long(spec, name).map_err(error)? else {
+                return Ok(None);
+            };
+            let value = match (option.argument, value) {
+                (None, Some(_)) => {
+                    let message = format!("option '--{}' doesn't allow an argument", option.long);
+                    return Err(error(message));
+                }
+                (Some(_), None) => match args.next() {
+                    Some(value) => Some(value),
+                    None => {
+                        let message = format!("option '--{}' requires an argument", option.long);
+                        return Err(error(message));
+                    }
+                },
+                (_, value) => value,
+            };
+            parsed.options.push((option.short, value));
+            continue;
+        }
+        // `-` alone, such as stdin for `cat`, is an operand, and so is a
+        // negative number for builtins that take one
+        let number = spec.numbers && arg.parse::<i64>().is_ok();
+        let is_option = arg.len() > 1 && arg.starts_with('-') && !number;
+        if !is_option {
+            parsed.operands.push(arg);
+            if spec.in_order {
+                parsed.operands.extend(args);
+                break;
+            }
+            continue;
+        }
+        for (i, short) in arg.char_indices().skip(1) {
+            let Some(option) = spec.options.iter().find(|option| option.short == short) else {
+                return Err(error(format!("invalid option -- '{}'", short)));
+            };
+            if option.argument.is_none() {
+                parsed.options.push((short, None));
+                continue;
+            }
+            // The rest of the cluster is the argument, or else the next word
+            let rest = &arg[i + short.len_utf8()..];
+            let value = if rest.is_empty() { args.next() } else { Some(rest) };
+            let Some(value) = value else {
+                return Err(error(format!("option requires an argument -- '{}'", short)));
+            };
+            parsed.options.push((short, Some(value)));
+            break;
+        }
+    }
+    if spec.operands.is_empty() && !parsed.operands.is_empty() {
+        return Err(error("too many arguments".to_string()));
+    }
+    Ok(Some(parsed))
+}
+
+/// Finds the option `--name` stands for, which may be shortened to any
+/// prefix that only one option starts with. `--help` gives `None`.
+fn find_long<'s>(spec: &'s Spec, name: &str) -> Result<Option<&'s Opt>, String> {
+    let longs = spec.options.iter().filter(|option| !option.long.is_empty());
+    if name == "help" {
+        return Ok(None);
+    }
+    if let Some(option) = longs.clone().find(|option| option.long == name) {
+        return Ok(Some(option));
+    }
+    let matches: Vec<&Opt> = longs.filter(|option| option.long.starts_with(name)).collect();
+    let help = !name.is_empty() && "help".starts_with(name);
+    match (matches.as_slice(), help) {
+        ([], true) => Ok(None),
+        ([option], false) => Ok(Some(option)),
+        ([], false) => Err(format!("unrecognized option '--{}'", name)),
+        _ => Err(format!("option '--{}' is ambiguous", name)),
+    }
+}
+
+/// The usage line for builtin `command`, such as
+/// `usage: history [-c] [-d offset] [n]`.
+pub fn usage(command: &str, spec: &Spec) -> String {
+    let mut usage = format!("usage: {}", command);
+    let flags: String = spec
+        .options
+        .iter()
+        .filter(|option| option.argument.is_none())
+        .map(|option| option.short)
+        .collect();
+    if !flags.is_empty() {
+        let _ = write!(usage, " [-{}]", flags);
+    }
+    for option in spec.options {
+        if let Some(argument) = option.argument {
+            let _ = write!(usage, " [-{} {}]", option.short, argument);
+        }
+    }
+    if !spec.operands.is_empty() {
+        let _ = write!(usage, " {}", spec.operands);
+    }
+    usage
+}
+
+/// What `command --help` prints: the usage line, what the builtin does and
+/// a line for each option.
+pub fn help(command: &str, spec: &Spec) -> String {
+    let mut lines: Vec<(String, &str)> = spec
+        .options
+        .iter()
+        .map(|option| {
+            let mut names = format!("-{}", option.short);
+            if !option.long.is_empty() {
+                let _ = write!(names, ", --{}", option.long);
+            }
+            if let Some(argument) = option.argument {
+                let separator = if option.long.is_empty() { " " } else { "=" };
+                let _ = write!(names, "{}{}", separator, argument);
+            }
+            (names, option.help)
+        })
+        .collect();
+    lines.push(("    --help".to_string(), "show this help"));
+    let width = lines.iter().map(|(names, _)| names.len()).max().unwrap_or(0);
+
+    let mut help = format!("{}\n{}\n\nOptions:\n", usage(command, spec), spec.about);
+    for (names, text) in lines {
+        let _ = writeln!(help, "  {:width$}  {}", names, text, width = width);
+    }
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: Spec = Spec::new(
+        "Do things.",
+        "[file ...]",
+        &[
+            Opt::flag('a', "all", "everything"),
+            Opt::flag('l', "", "long"),
+            Opt::flag('r', "recursive", "go down"),
+            Opt::with_argument('d', "delete", "offset", "remove one"),
+            Opt::flag('D', "delay", "wait"),
+        ],
+    );
+
+    fn parsed<'a>(args: &[&'a str]) -> Args<'a> {
+        parse("cmd", &SPEC, args).unwrap().unwrap()
+    }
+
+    fn error(spec: &Spec, args: &[&str]) -> String {
+        parse("cmd", spec, args).unwrap_err()
+    }
+
+    #[test]
+    fn clustered_flags() {
+        let args = parsed(&["-la", "-r"]);
+        assert_eq!(args.options, [('l', None), ('a', None), ('r', None)]);
+        assert!(args.has('a') && args.has('l') && args.has('r'));
+        assert!(!args.has('D'));
+        assert!(args.operands.is_empty());
+    }
+
+    #[test]
+    fn operands_anywhere() {
+        let args = parsed(&["x", "-a", "y", "-"]);
+        assert!(args.has('a'));
+        assert_eq!(args.operands, ["x", "y", "-"]);
+    }
+
+    #[test]
+    fn negative_numbers() {
+        // Digits are options unless the builtin takes numbers
+        assert_eq!(
+            error(&SPEC, &["-1"]).lines().next(),
+            Some("cmd: invalid option -- '1'")
+        );
+        const NUMBERS: Spec = Spec::new("Count.", "[n]", &[Opt::flag('a', "", "all")]).numbers();
+        let args = parse("cmd", &NUMBERS, &["-a", "-5"]).unwrap().unwrap();
+        assert!(args.has('a'));
+        assert_eq!(args.operands, ["-5"]);
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let args = parsed(&["-a", "--", "-l", "--all"]);
+        assert_eq!(args.options, [('a', None)]);
+        assert_eq!(args.operands, ["-l", "--all"]);
+    }
+
+    #[test]
+    fn option_arguments() {
+        assert_eq!(parsed(&["-d", "5"]).value('d'), Some("5"));
+        assert_eq!(parsed(&["-d5"]).value('d'), Some("5"));
+        // The rest of a cluster after an option that takes an argument is it
+        let args = parsed(&["-ad5"]);
+        assert!(args.has('a'));
+        assert_eq!(args.value('d'), Some("5"));
+        assert_eq!(parsed(&["--delete=5"]).value('d'), Some("5"));
+        assert_eq!(parsed(&["--delete", "5"]).value('d'), Some("5"));
+        // An argument may itself start with `-`
+        assert_eq!(parsed(&["-d", "-a"]).value('d'), Some("-a"));
+        // The last one given wins
+        assert_eq!(parsed(&["-d1", "-d2"]).value('d'), Some("2"));
+        assert_eq!(parsed(&["-a"]).value('d'), None);
+    }
+
+    #[test]
+    fn long_options() {
+        assert!(parsed(&["--all"]).has('a'));
+        assert!(parsed(&["--al"]).has('a'));
+        assert!(parsed(&["--rec"]).has('r'));
+        assert_eq!(parsed(&["--dela"]).options, [('D', None)]);
+        assert!(error(&SPEC, &["--de"]).starts_with("cmd: option '--de' is ambiguous\n"));
+        assert!(error(&SPEC, &["--nope"]).starts_with("cmd: unrecognized option '--nope'\n"));
+        assert!(error(&SPEC, &["--all=1"]).contains("option '--all' doesn't allow an argument"));
+        assert!(error(&SPEC, &["--delete"]).contains("option '--delete' requires an argument"));
+    }
+
+    #[test]
+    fn mistakes_show_the_usage() {
+        assert_eq!(
+            error(&SPEC, &["-x"]),
+            "cmd: invalid option -- 'x'\nusage: cmd [-alrD] [-d offset] [file ...]"
+        );
+        assert!(error(&SPEC, &["-d"]).starts_with("cmd: option requires an argument -- 'd'\n"));
+        let none = Spec::new("Nothing.", "", &[]);
+        assert_eq!(error(&none, &["x"]), "cmd: too many arguments\nusage: cmd");
+    }
+
+    #[test]
+    fn in_order_stops_at_the_first_operand() {
+        const SPEC: Spec = Spec::new("Run.", "command [arg ...]", &[Opt::flag('i', "", "")]);
+        let spec = SPEC.in_order();
+        let args = parse("env", &spec, &["-i", "ls", "-l", "--", "-i"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(args.options, [('i', None)]);
+        assert_eq!(args.operands, ["ls", "-l", "--", "-i"]);
+    }
+
+    #[test]
+    fn help() {
+        assert!(parse("cmd", &SPEC, &["-a", "--help"]).unwrap().is_none());
+        assert!(parse("cmd", &SPEC, &["--he"]).unwrap().is_none());
+        assert_eq!(
+            super::help("cmd", &SPEC),
+            r#"usage: cmd [-alrD] [-d offset] [file ...]
+Do things.
+
+Options:
+  -a, --all            everything
+  -l                   long
+  -r, --recursive      go down
+  -d, --delete=offset  remove one
+  -D, --delay          wait
+      --help           show this help
+"#
+        );
+        const OPTIONS: [Opt; 1] = [Opt::with_argument('n', "", "count", "how many")];
+        let spec = Spec::new("Go.", "", &OPTIONS);
+        assert_eq!(usage("go", &spec), "usage: go [-n count]");
+        assert!(super::help("go", &spec).contains("  -n count    how many\n"));
+    }
+}
@@ -0,0 +1,238 @@
+// glob.rs
+//
+// Pathname expansion. A field with unquoted wildcards is replaced by the
+// sorted list of paths it matches, one path component at a time, so
+// `src/*.rs` only lists `src` and `*/*.rs` lists every directory it needs.
+// A `**` component matches any number of nested directories.
+//
+// Names starting with `.` are only matched by a component that itself starts
+// with `.`, unless `dotglob` is set, and `.` and `..` are never matched by a
+// wildcard. What happens when nothing matches is up to `nullglob` and
+// `failglob`; by default the pattern is passed on unchanged.
+
+use std::fs;
+use std::path::Path;
+
+use crate::pattern;
+use crate::state::Options;
+
+/// Expands one field whose quoted characters are escaped, returning the
+/// arguments it stands for with the escapes removed.
+pub fn expand(field: &str, options: &Options) -> Result<Vec<String>, String> {
+    if options.noglob || !pattern::has_wildcards(field) {
+        return Ok(vec![pattern::unescape(field)]);
+    }
+
+    let mut matches = glob(field, options);
+    if matches.is_empty() {
+        if options.failglob {
+            return Err(format!("no match: {}", pattern::unescape(field)));
+        }
+        if options.nullglob {
+            return Ok(Vec::new());
+        }
+        return Ok(vec![pattern::unescape(field)]);
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Returns every existing path matching `pattern`, in no particular order.
+fn glob(pattern: &str, options: &Options) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        if component.is_empty() {
+            // A trailing slash keeps only directories; doubled slashes are ignored
+            if last {
+                paths = paths
+                    .into_iter()
+                    .filter(|path| Path::new(path).is_dir())
+                    .map(|path| join(&path, ""))
+                    .collect();
+            }
+            continue;
+        }
+
+        paths = if *component == "**" {
+            let mut found = Vec::new();
+            for path in &paths {
+                walk(path, options, last, &mut found);
+            }
+            found
+        } else if pattern::has_wildcards(component) {
+            let mut found = Vec::new();
+            for path in &paths {
+                found.extend(match_dir(path, component, options));
+            }
+            found
+        } else {
+            let name = pattern::unescape(component);
+            paths
+                .iter()
+                .map(|path| join(path, &name))
+                .filter(|path| fs::symlink_metadata(path).is_ok())
+                .collect()
+        };
+        if paths.is_empty() {
+            break;
+        }
+    }
+    paths
+}
+
+/// Lists the entries of `dir` whose names match `component`.
+fn match_dir(dir: &str, component: &str, options: &Options) -> Vec<String> {
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        // Unreadable or not a directory: nothing matches
+        Err(_) => return Vec::new(),
+    };
+    let wants_hidden = pattern::unescape(component).starts_with('.');
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.') || wants_hidden || options.dotglob)
+        .filter(|name| pattern::matches(component, name))
+        .map(|name| join(dir, &name))
+        .collect()
+}
+
+/// Collects what `**` matches below `dir`. In the middle of a pattern it
+/// matches `dir` itself and every directory under it; as the last component
+/// it matches every file and directory under `dir`.
+fn walk(dir: &str, options: &Options, last: bool, found: &mut Vec<String>) {
+    if !last {
+        found.push(dir.to_string());
+    }
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return;
+    };
+    let mut names: Vec<(String, bool)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            // Symlinked directories are not followed, so loops cannot occur
+            let is_dir = entry.file_type().ok()?.is_dir();
+            Some((entry.file_name().into_string().ok()?, is_dir))
+        })
+        .filter(|(name, _)| !name.starts_with('.') || options.dotglob)
+        .collect();
+    names.sort();
+
+    for (name, is_dir) in names {
+        let path = join(dir, &name);
+        if is_dir {
+            if last {
+                found.push(path.clone());
+            }
+            walk(&path, options, last, found);
+        } else if last {
+            found.push(path);
+        }
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own for test `name`, holding `files`, with the
+    /// directories they need.
+    fn tree(name: &str, files: &[&str]) -> String {
+        let dir = std::env::temp_dir().join(format!("glob-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir.to_string_lossy().into_owned()
+    }
+
+    /// What `pattern` expands to under `dir`, relative to it.
+    fn expanded(dir: &str, pattern: &str, options: &Options) -> Vec<String> {
+        let prefix = format!("{}/", dir);
+        expand(&format!("{}{}", prefix, pattern), options)
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(&prefix).unwrap_or(&path).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn components() {
+        let dir = tree("components", &["a.rs", "b.txt", "src/c.rs", "src/d/e.rs"]);
+        let options = Options::default();
+        assert_eq!(expanded(&dir, "*.rs", &options), ["a.rs"]);
+        assert_eq!(expanded(&dir, "*/*.rs", &options), ["src/c.rs"]);
+        assert_eq!(expanded(&dir, "[ab].*", &options), ["a.rs", "b.txt"]);
+        assert_eq!(expanded(&dir, "*/", &options), ["src/"]);
+        // A quoted wildcard only matches itself
+        assert_eq!(expanded(&dir, "\\*.rs", &options), ["*.rs"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn double_star() {
+        let dir = tree(
+            "double_star",
+            &["a.rs", "src/c.rs", "src/d/e.rs", "src/d/f.txt"],
+        );
+        let options = Options::default();
+        assert_eq!(
+            expanded(&dir, "**/*.rs", &options),
+            ["a.rs", "src/c.rs", "src/d/e.rs"]
+        );
+        assert_eq!(
+            expanded(&dir, "src/**", &options),
+            ["src/c.rs", "src/d", "src/d/e.rs", "src/d/f.txt"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dotfiles() {
+        let dir = tree("dotfiles", &[".hidden", "shown", ".git/config"]);
+        let mut options = Options::default();
+        assert_eq!(expanded(&dir, "*", &options), ["shown"]);
+        assert_eq!(expanded(&dir, ".*", &options), [".git", ".hidden"]);
+        assert_eq!(expanded(&dir, "**", &options), ["shown"]);
+        options.dotglob = true;
+        assert_eq!(expanded(&dir, "*", &options), [".git", ".hidden", "shown"]);
+        assert_eq!(
+            expanded(&dir, "**", &options),
+            [".git", ".git/config", ".hidden", "shown"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn no_match() {
+        let dir = tree("no_match", &["a"]);
+        let mut options = Options::default();
+        assert_eq!(expanded(&dir, "*.x", &options), ["*.x"]);
+        options.nullglob = true;
+        assert!(expanded(&dir, "*.x", &options).is_empty());
+        options.failglob = true;
+        assert!(expand(&format!("{}/*.x", dir), &options).is_err());
+        options.noglob = true;
+        assert_eq!(expanded(&dir, "*", &options), ["*"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
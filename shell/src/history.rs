@@ -0,0 +1,542 @@
+// history.rs
+//
+// Command history. An interactive shell remembers every command line it
+// reads and appends it straight away to its history file, by default
+// `~/.0shell_history`, so the next session starts with it and shells running
+// side by side do not lose each other's commands. Each entry in the file is
+// a `#<seconds since the epoch>` line followed by the command, which may span
+// several lines. Scripts keep no history.
+//
+// The variables `HISTFILE`, `HISTSIZE` (entries kept in memory),
+// `HISTFILESIZE` (entries kept in the file) and `HISTCONTROL` (`ignorespace`,
+// `ignoredups` or `ignoreboth`) configure it, and `HISTTIMEFORMAT` makes
+// `history` show when each command was run.
+
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use chrono::{Local, TimeZone};
+
+use crate::getopt::{Args, Opt, Spec};
+use crate::state::Shell;
+use crate::stream::Io;
+use crate::vars::Variables;
+
+/// Entries kept when `HISTSIZE` is not set, as in bash.
+const DEFAULT_SIZE: usize = 500;
+
+/// Name of the history file in the home directory.
+const FILE_NAME: &str = ".0shell_history";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// When the command was entered, in seconds since the epoch.
+    pub time: i64,
+    pub line: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    /// How many entries were dropped from the front, so that the remaining
+    /// ones keep their numbers.
+    base: usize,
+    /// The file entries are appended to; `None` until the history is loaded,
+    /// or when `HISTFILE` is empty.
+    file: Option<PathBuf>,
+}
+
+impl History {
+    /// Reads the history file named by `HISTFILE`, or the default one in the
+    /// home directory, and saves new entries to it from now on. A file that
+    /// grew past `HISTFILESIZE` entries is cut down to its newest ones.
+    pub fn load(&mut self, vars: &Variables) {
+        let path = match vars.get("HISTFILE") {
+            Some("") => return,
+            Some(path) => PathBuf::from(path),
+            None => match vars.get("HOME") {
+                Some(home) => PathBuf::from(home).join(FILE_NAME),
+                None => return,
+            },
+        };
+        let file_size = size(vars, "HISTFILESIZE").unwrap_or_else(|| memory_size(vars));
+        let mut entries = Vec::new();
+        let _ = rewrite(&path, |mut saved| {
+            let excess = saved.len().saturating_sub(file_size);
+            saved.drain(..excess);
+            entries = saved;
+            (excess > 0).then(|| entries.clone())
+        });
+        self.entries = entries;
+        self.file = Some(path);
+        self.limit(memory_size(vars));
+    }
+
+    /// Records a command line, unless `HISTCONTROL` says to skip it, and
+    /// appends it to the history file.
+    pub fn add(&mut self, line: &str, vars: &Variables) {
+        let line = line.trim_end_matches('\n');
+        let control = vars.get("HISTCONTROL").unwrap_or_default();
+        let ignore = |option: &str| {
+            control
+                .split(':')
+                .any(|value| value == option || value == "ignoreboth")
+        };
+        if line.trim().is_empty()
+            || (ignore("ignorespace") && line.starts_with([' ', '\t']))
+            || (ignore("ignoredups") && self.entries.last().is_some_and(|last| last.line == line))
+        {
+            return;
+        }
+
+        let entry = Entry {
+            time: Local::now().timestamp(),
+            line: line.to_string(),
+        };
+        if let Some(path) = &self.file {
+            if let Err(e) = append(path, &entry) {
+                eprintln!("history: {}: {}", path.display(), e);
+            }
+        }
+        self.entries.push(entry);
+        self.limit(memory_size(vars));
+    }
+
+    /// Drops the oldest entries beyond `size`.
+    fn limit(&mut self, size: usize) {
+        let excess = self.entries.len().saturating_sub(size);
+        self.entries.drain(..excess);
+        self.base += excess;
+    }
+
+    /// Forgets every entry, in the history file as well, and starts the
+    /// numbering again from 1.
+    fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.base = 0;
+        match &self.file {
+            Some(path) => rewrite(path, |_| Some(Vec::new())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The number `history` shows for the entry at `index`.
+    fn number(&self, index: usize) -> usize {
+        self.base + index + 1
+    }
+
+    /// Carries out the history expansions in `line`:
+    ///
+    ///   !!        the previous command
+    ///   !n  !-n   command number n, or the nth one back
+    ///   !prefix   the latest command starting with prefix
+    ///   !$        the last word of the previous command
+    ///
+    /// Returns `None` when the line has none. A `!` in single quotes, after a
+    /// backslash, or followed by a blank, `=`, `(` or the end of the line is
+    /// left alone, and so are `$!` and `${!`.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, String> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut out = String::new();
+        let mut changed = false;
+        let mut single = false;
+        let mut double = false;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\\' if !single => {
+                    out.extend(chars[i..(i + 2).min(chars.len())].iter());
+                    i += 2;
+                    continue;
+                }
+                '\'' if !double => single = !single,
+                '"' if !single => double = !double,
+                '!' if !single => {
+                    let next = chars.get(i + 1).copied();
+                    let after_dollar = i > 0 && chars[i - 1] == '$'
+                        || i > 1 && chars[i - 1] == '{' && chars[i - 2] == '$';
+                    let literal = match next {
+                        None => true,
+                        Some(next) => {
+                            next.is_whitespace() || "=(".contains(next) || (double && next == '"')
+                        }
+                    };
+                    if !literal && !after_dollar {
+                        let (text, end) = self.event(&chars, i + 1)?;
+                        out.push_str(&text);
+                        changed = true;
+                        i = end;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            out.push(c);
+            i += 1;
+        }
+        Ok(changed.then_some(out))
+    }
+
+    /// Resolves the history event starting at `chars[start]`, just after a
+    /// `!`. Returns its text and the index after the designator.
+    fn event(&self, chars: &[char], start: usize) -> Result<(String, usize), String> {
+        let last = || self.entries.last().ok_or("!!: event not found".to_string());
+        match chars[start] {
+            '!' => Ok((last()?.line.clone(), start + 1)),
+            '$' => {
+                let last = last().map_err(|_| "!$: event not found".to_string())?;
+                let word = words(&last.line).pop().unwrap_or_default();
+                Ok((word.to_string(), start + 1))
+            }
+            _ => {
+                let end = (start..chars.len())
+                    .find(|&i| chars[i].is_whitespace() || ";&|<>()'\"".contains(chars[i]))
+                    .unwrap_or(chars.len());
+                let designator: String = chars[start..end].iter().collect();
+                let not_found = || format!("!{}: event not found", designator);
+                let entry = if let Ok(n) = designator.parse::<i64>() {
+                    let index = if n < 0 {
+                        self.entries.len().checked_sub(n.unsigned_abs() as usize)
+                    } else {
+                        (n as usize).checked_sub(self.base + 1)
+                    };
+                    index.and_then(|index| self.entries.get(index))
+                } else {
+                    self.entries
+                        .iter()
+                        .rev()
+                        .find(|entry| entry.line.starts_with(&designator))
+                };
+                let entry = entry.ok_or_else(not_found)?;
+                Ok((entry.line.clone(), end))
+            }
+        }
+    }
+}
+
+/// Splits a command line into its words as written, quotes included.
+fn words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quote == Some(c) {
+            quote = None;
+        } else if quote.is_none() {
+            match c {
+                '\\' => escaped = true,
+                '\'' | '"' => quote = Some(c),
+                c if c.is_whitespace() => {
+                    if let Some(start) = start.take() {
+                        words.push(&line[start..i]);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(start) = start {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+/// The value of a size variable such as `HISTSIZE`, if it is a number.
+fn size(vars: &Variables, name: &str) -> Option<usize> {
+    vars.get(name)?.trim().parse().ok()
+}
+
+fn memory_size(vars: &Variables) -> usize {
+    size(vars, "HISTSIZE").unwrap_or(DEFAULT_SIZE)
+}
+
+/// Locks `file` until it is closed, so that only one shell at a time
+/// changes the history file.
+fn lock(file: &File) -> io::Result<()> {
+    // SAFETY: flock only acts on the open descriptor
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Appends `entry` to the history file with a single write.
+fn append(path: &PathBuf, entry: &Entry) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)?;
+    lock(&file)?;
+    file.write_all(format!("#{}\n{}\n", entry.time, entry.line).as_bytes())
+}
+
+/// Reads the entries in the history file and, if `change` returns new ones,
+/// replaces the file's contents with them, all while holding the lock.
+fn rewrite(
+    path: &PathBuf,
+    change: impl FnOnce(Vec<Entry>) -> Option<Vec<Entry>>,
+) -> io::Result<()> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            change(Vec::new());
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    lock(&file)?;
+    let mut text = Vec::new();
+    file.read_to_end(&mut text)?;
+    let Some(entries) = change(parse(&String::from_utf8_lossy(&text))) else {
+        return Ok(());
+    };
+    let text: String = entries
+        .iter()
+        .map(|entry| format!("#{}\n{}\n", entry.time, entry.line))
+        .collect();
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(text.as_bytes())
+}
+
+/// Reads the entries of a history file. Lines before the first timestamp,
+/// as in a file written without them, are entries of their own.
+fn parse(text: &str) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    // Whether the last entry has a timestamp and may take more lines
+    let mut open = false;
+    for line in text.lines() {
+        let time = line.strip_prefix('#').and_then(|time| time.parse().ok());
+        match (time, entries.last_mut()) {
+            (Some(time), _) => {
+                entries.push(Entry {
+                    time,
+                    line: String::new(),
+                });
+                open = true;
+            }
+            (None, Some(entry)) if open => {
+                if !entry.line.is_empty() {
+                    entry.line.push('\n');
+                }
+                entry.line.push_str(line);
+            }
+            (None, _) => entries.push(Entry {
+                time: 0,
+                line: line.to_string(),
+            }),
+        }
+    }
+    entries.retain(|entry| !entry.line.is_empty());
+    entries
+}
+
+pub const SPEC: Spec = Spec::new(
+    "List the commands entered, or the last n of them.",
+    "[n]",
+    &[
+        Opt::flag('c', "clear", "delete every command"),
+        Opt::with_argument('d', "delete", "offset", "delete the command at offset"),
+    ],
+);
+
+/// `history [n]`, `history -c` and `history -d offset`: lists the last `n`
+/// commands (all of them by default), clears the history or deletes one entry.
+/// Clearing and deleting change the history file as well.
+pub fn history(args: Args, shell: &mut Shell, io: &mut Io) -> Result<(), String> {
+    let history = &mut shell.history;
+    match (args.has('c'), args.value('d'), &args.operands[..]) {
+        (true, _, _) => history.clear().map_err(|e| format!("history: {}", e)),
+        (false, Some(offset), _) => {
+            let index = offset.parse::<i64>().ok().and_then(|n| {
+                if n < 0 {
+                    history.entries.len().checked_sub(n.unsigned_abs() as usize)
+                } else {
+                    (n as usize).checked_sub(history.base + 1)
+                }
+            });
+            let Some(index) = index.filter(|&index| index < history.entries.len()) else {
+                return Err(format!("history: {}: history position out of range", offset));
+            };
+            let entry = history.entries.remove(index);
+            if let Some(path) = &history.file {
+                rewrite(path, |mut saved| {
+                    let position = saved.iter().rposition(|saved| *saved == entry)?;
+                    saved.remove(position);
+                    Some(saved)
+                })
+                .map_err(|e| format!("history: {}", e))?;
+            }
+            Ok(())
+        }
+        (false, None, [] | [_]) => {
+            let count = match args.operands.first() {
+                Some(count) => count
+                    .parse()
+                    .map_err(|_| format!("history: {}: numeric argument required", count))?,
+                None => history.entries.len(),
+            };
+            let format = shell.vars.get("HISTTIMEFORMAT");
+            let start = history.entries.len().saturating_sub(count);
+            for (index, entry) in history.entries.iter().enumerate().skip(start) {
+                let time = format.map(|format| entry_time(entry.time, format)).unwrap_or_default();
+                writeln!(io.stdout, "{:5}  {}{}", history.number(index), time, entry.line)
+                    .map_err(|e| format!("history: {}", e))?;
+            }
+            Ok(())
+        }
+        _ => Err("history: too many arguments".to_string()),
+    }
+}
+
+/// When a command ran, in the `HISTTIMEFORMAT` format. A format `strftime`
+/// cannot use shows nothing, rather than the listing failing.
+fn entry_time(time: i64, format: &str) -> String {
+    let Some(time) = Local.timestamp_opt(time, 0).single() else {
+        return String::new();
+    };
+    let mut out = String::new();
+    match write!(out, "{}", time.format(format)) {
+        Ok(()) => out,
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history holding `lines`, numbered from 1, that saves nothing.
+    fn history(lines: &[&str]) -> History {
+        let mut history = History::default();
+        for line in lines {
+            history.add(line, &Variables::default());
+        }
+        history
+    }
+
+    fn expanded(history: &History, line: &str) -> Result<Option<String>, String> {
+        history.expand(line)
+    }
+
+    #[test]
+    fn events() {
+        let history = history(&["ls -l", "echo 'a b' c", "cd /tmp"]);
+        assert_eq!(expanded(&history, "!!"), Ok(Some("cd /tmp".to_string())));
+        assert_eq!(
+            expanded(&history, "sudo !!"),
+            Ok(Some("sudo cd /tmp".to_string()))
+        );
+        assert_eq!(
+            expanded(&history, "!1 | wc"),
+            Ok(Some("ls -l | wc".to_string()))
+        );
+        assert_eq!(
+            expanded(&history, "!-2"),
+            Ok(Some("echo 'a b' c".to_string()))
+        );
+        assert_eq!(
+            expanded(&history, "!ec;!l"),
+            Ok(Some("echo 'a b' c;ls -l".to_string()))
+        );
+        assert_eq!(expanded(&history, "x !$"), Ok(Some("x /tmp".to_string())));
+        assert_eq!(
+            expanded(&history, "!9"),
+            Err("!9: event not found".to_string())
+        );
+        assert_eq!(
+            expanded(&history, "!nope"),
+            Err("!nope: event not found".to_string())
+        );
+        assert_eq!(
+            expanded(&History::default(), "!!"),
+            Err("!!: event not found".to_string())
+        );
+        assert_eq!(
+            expanded(&History::default(), "!$"),
+            Err("!$: event not found".to_string())
+        );
+    }
+
+    #[test]
+    fn last_word_keeps_its_quotes() {
+        let history = history(&["echo \\'a b\\' 'c d'"]);
+        assert_eq!(expanded(&history, "!$"), Ok(Some("'c d'".to_string())));
+    }
+
+    #[test]
+    fn literal_bangs() {
+        let history = history(&["ls"]);
+        for line in [
+            "echo '!!'",
+            "echo \\!!",
+            "echo ! x",
+            "a!",
+            "[ ! -f x ]",
+            "echo $! ${!x}",
+        ] {
+            assert_eq!(expanded(&history, line), Ok(None), "{}", line);
+        }
+        assert_eq!(
+            expanded(&history, "echo \"!!\""),
+            Ok(Some("echo \"ls\"".to_string()))
+        );
+        assert_eq!(expanded(&history, "echo \"hi!\""), Ok(None));
+    }
+
+    #[test]
+    fn numbering() {
+        let mut vars = Variables::default();
+        vars.set("HISTSIZE", "2");
+        let mut history = History::default();
+        for line in ["a", "b", "c"] {
+            history.add(line, &vars);
+        }
+        // Dropped entries leave the others their numbers
+        assert_eq!(history.number(0), 2);
+        assert_eq!(expanded(&history, "!2"), Ok(Some("b".to_string())));
+        assert!(expanded(&history, "!1").is_err());
+
+        // Clearing starts again from 1
+        history.clear().unwrap();
+        assert!(history.entries().is_empty());
+        history.add("d", &vars);
+        assert_eq!(history.number(0), 1);
+        assert_eq!(expanded(&history, "!1"), Ok(Some("d".to_string())));
+    }
+
+    #[test]
+    fn file_entries() {
+        let text = "#100\nls\n#200\nfor x in a\ndo echo $x\ndone\n#300\n#400\n#nope\n";
+        let entry = |time, line: &str| Entry {
+            time,
+            line: line.to_string(),
+        };
+        assert_eq!(
+            parse(text),
+            [
+                entry(100, "ls"),
+                entry(200, "for x in a\ndo echo $x\ndone"),
+                entry(400, "#nope"),
+            ]
+        );
+        // A file without timestamps has an entry per line
+        assert_eq!(parse("ls\ncd /\n"), [entry(0, "ls"), entry(0, "cd /")]);
+        assert!(parse("").is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+// input.rs
+//
+// Where the shell reads its commands from. At a terminal each line is typed
+// into the line editor after a prompt; otherwise it reads a script silently:
+// a file named on the command line or run by `source`, the string given to
+// `-c`, or whatever is piped into stdin. Scripts count their lines so errors
+// can say where they happened.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor};
+
+use crate::editor::Editor;
+use crate::signals;
+
+pub enum Input {
+    /// Lines typed at the prompt.
+    Terminal(Editor),
+    /// Lines of a script, read without prompting.
+    Script {
+        /// The name errors are reported against: the script path, or `$0`.
+        name: String,
+        /// `None` reads the shell's own stdin, which is locked only while a
+        /// line is read so that the commands run can read from it too.
+        reader: Option<Box<dyn BufRead>>,
+        /// Number of the line read last.
+        line: usize,
+    },
+}
+
+impl Input {
+    pub fn file(path: &str) -> io::Result<Input> {
+        let file = File::open(path)?;
+        Ok(Input::script(path, Some(Box::new(BufReader::new(file)))))
+    }
+
+    pub fn string(name: &str, text: &str) -> Input {
+        let reader = Cursor::new(text.to_string().into_bytes());
+        Input::script(name, Some(Box::new(reader)))
+    }
+
+    /// Standard input when it is not a terminal.
+    pub fn stdin(name: &str) -> Input {
+        Input::script(name, None)
+    }
+
+    fn script(name: &str, reader: Option<Box<dyn BufRead>>) -> Input {
+        Input::Script {
+            name: name.to_string(),
+            reader,
+            line: 0,
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        matches!(self, Input::Terminal(_))
+    }
+
+    /// Reads the next line into `line`. Returns `Ok(0)` at the end of input.
+    pub fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        match self {
+            Input::Terminal(_) => read_line(&mut io::stdin().lock(), line),
+            Input::Script {
+                reader,
+                line: number,
+                ..
+            } => {
+                *number += 1;
+                match reader {
+                    Some(reader) => read_line(reader, line),
+                    None => read_line(&mut io::stdin().lock(), line),
+                }
+            }
+        }
+    }
+
+    /// Prefix for errors found in a script, such as `build.sh: line 3: `.
+    pub fn location(&self) -> String {
+        match self {
+            Input::Terminal(_) => String::new(),
+            Input::Script { name, line, .. } => format!("{}: line {}: ", name, line),
+        }
+    }
+}
+
+/// Reads a line like `BufRead::read_line`, except that a read interrupted
+/// by Ctrl+C returns an `Interrupted` error instead of being retried.
+pub fn read_line(reader: &mut dyn BufRead, input: &mut String) -> io::Result<usize> {
+    let mut bytes = Vec::new();
+    loop {
+        let buffer = match reader.fill_buf() {
+            Ok(buffer) => buffer,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if signals::take_interrupt() {
+                    return Err(e);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if buffer.is_empty() {
+            break;
+        }
+        match buffer.iter().position(|&b| b == b'\n') {
+            Some(end) => {
+                bytes.extend_from_slice(&buffer[..=end]);
+                reader.consume(end + 1);
+                break;
+            }
+            None => {
+                let len = buffer.len();
+                bytes.extend_from_slice(buffer);
+                reader.consume(len);
+            }
+        }
+    }
+    input.push_str(&String::from_utf8_lossy(&bytes));
+    Ok(bytes.len())
+}
@@ -0,0 +1,462 @@
+// jobs.rs
+//
+// Job control. A job is a pipeline started in the background with `&` or one
+// that was stopped with Ctrl+Z while in the foreground. Each job remembers its
+// process group so it can be resumed (`fg`, `bg`) or waited for (`wait`) as a
+// whole, and the shell reports jobs that changed state before the next prompt.
+
+use std::io::Write;
+
+use libc::pid_t;
+
+use crate::getopt::{Args, Opt, Spec};
+use crate::signals;
+use crate::state::Shell;
+use crate::stream::Io;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProcessState {
+    Running,
+    Stopped,
+    /// Finished with this shell status (128 + signal if it was killed).
+    Exited(i32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub id: usize,
+    pub pgid: pid_t,
+    pub processes: Vec<(pid_t, ProcessState)>,
+    pub command: String,
+}
+
+impl Job {
+    pub fn is_done(&self) -> bool {
+        self.processes
+            .iter()
+            .all(|(_, state)| matches!(state, ProcessState::Exited(_)))
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        !self.is_done()
+            && self
+                .processes
+                .iter()
+                .all(|(_, state)| *state != ProcessState::Running)
+    }
+
+    /// The status of the job's last process, which is the job's status.
+    pub fn status(&self) -> i32 {
+        match self.processes.last() {
+            Some((_, ProcessState::Exited(status))) => *status,
+            _ => 0,
+        }
+    }
+
+    fn state_name(&self) -> String {
+        if self.is_done() {
+            match self.status() {
+                0 => "Done".to_string(),
+                status => format!("Exit {}", status),
+            }
+        } else if self.is_stopped() {
+            "Stopped".to_string()
+        } else {
+            "Running".to_string()
+        }
+    }
+}
+
+/// The shell's job table.
+#[derive(Clone, Debug, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// The job `%+` refers to: the one most recently started or stopped.
+    current: Option<usize>,
+    /// The job `%-` refers to.
+    previous: Option<usize>,
+}
+
+impl Jobs {
+    /// Adds a job and makes it the current one. Returns its job number.
+    pub fn add(
+        &mut self,
+        pgid: pid_t,
+        processes: Vec<(pid_t, ProcessState)>,
+        command: String,
+    ) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pgid,
+            processes,
+            command,
+        });
+        self.make_current(id);
+        id
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        if self.current == Some(id) {
+            self.current = self.previous.take();
+        } else if self.previous == Some(id) {
+            self.previous = None;
+        }
+        // Fall back to the newest remaining job
+        if self.current.is_none() {
+            self.current = self
+                .jobs
+                .iter()
+                .rev()
+                .map(|job| job.id)
+                .find(|&other| other != id);
+        }
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn make_current(&mut self, id: usize) {
+        if self.current != Some(id) {
+            self.previous = self.current;
+            self.current = Some(id);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        self.jobs.iter().map(|job| job.id).collect()
+    }
+
+    /// Resolves a job spec: `%n`, `%+` or `%%`, `%-`, `%prefix`, a bare job
+    /// number, or nothing for the current job.
+    pub fn find(&self, spec: Option<&str>) -> Result<usize, String> {
+        let spec = spec.unwrap_or("%+");
+        let body = spec.strip_prefix('%').unwrap_or(spec);
+        let id = match body {
+            "" | "+" | "%" => self.current,
+            "-" => self.previous,
+            _ => match body.parse::<usize>() {
+                Ok(n) => self.jobs.iter().find(|job| job.id == n).map(|job| job.id),
+                Err(_) => self
+                    .jobs
+                    .iter()
+                    .rev()
+                    .find(|job| job.command.starts_with(body))
+                    .map(|job| job.id),
+            },
+        };
+        id.ok_or_else(|| format!("{}: no such job", spec))
+    }
+
+    /// Formats a job the way `jobs` lists it, e.g. `[1]+  Running  sleep 5 &`.
+    pub fn describe(&self, job: &Job) -> String {
+        let marker = if self.current == Some(job.id) {
+            '+'
+        } else if self.previous == Some(job.id) {
+            '-'
+        } else {
+            ' '
+        };
+        format!(
+            "[{}]{}  {:<24}{}",
+            job.id,
+            marker,
+            job.state_name(),
+            job.command
+        )
+    }
+}
+
+/// Decodes a status from `waitpid`. Returns `None` for a resumed process.
+fn decode(status: libc::c_int) -> Option<ProcessState> {
+    if libc::WIFEXITED(status) {
+        Some(ProcessState::Exited(libc::WEXITSTATUS(status)))
+    } else if libc::WIFSIGNALED(status) {
+        Some(ProcessState::Exited(128 + libc::WTERMSIG(status)))
+    } else if libc::WIFSTOPPED(status) {
+        Some(ProcessState::Stopped)
+    } else {
+        None
+    }
+}
+
+/// Blocks until every running process in `processes` has exited, updating
+/// their states. With `untraced` a stopped process counts as done waiting too;
+/// only an interactive shell asks for that, since only it can resume them.
+/// With `interruptible`, Ctrl+C ends the wait early.
+pub fn wait_for(processes: &mut [(pid_t, ProcessState)], untraced: bool, interruptible: bool) {
+    let flags = if untraced { libc::WUNTRACED } else { 0 };
+    for (pid, state) in processes.iter_mut() {
+        while *state == ProcessState::Running {
+            let mut status = 0;
+            // SAFETY: `status` is a valid out pointer
+            let result = unsafe { libc::waitpid(*pid, &mut status, flags) };
+            if result < 0 {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    if interruptible && signals::interrupted() {
+                        return;
+                    }
+                    continue;
+                }
+                // Already reaped elsewhere; nothing more to learn about it
+                *state = ProcessState::Exited(0);
+            } else if let Some(new_state) = decode(status) {
+                *state = new_state;
+            }
+        }
+    }
+}
+
+/// Checks every job without blocking and reports the ones that finished or
+/// stopped since the last check. Finished jobs leave the table.
+pub fn notify(shell: &mut Shell, io: &mut Io) {
+    let interactive = shell.terminal.is_some();
+    for id in shell.jobs.ids() {
+        let Some(job) = shell.jobs.get_mut(id) else {
+            continue;
+        };
+        let was_stopped = job.is_stopped();
+        let mut changed = false;
+        for (pid, state) in job.processes.iter_mut() {
+            if matches!(state, ProcessState::Exited(_)) {
+                continue;
+            }
+            let mut status = 0;
+            let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+            // SAFETY: `status` is a valid out pointer
+            let result = unsafe { libc::waitpid(*pid, &mut status, flags) };
+            if result > 0 {
+                *state = decode(status).unwrap_or(ProcessState::Running);
+                changed = true;
+            } else if result < 0 {
+                *state = ProcessState::Exited(0);
+                changed = true;
+            }
+        }
+
+        let done = job.is_done();
+        let newly_stopped = !was_stopped && job.is_stopped();
+        if changed && (done || newly_stopped) && interactive {
+            let job = job.clone();
+            let _ = writeln!(io.stderr, "{}", shell.jobs.describe(&job));
+        }
+        if done {
+            shell.jobs.remove(id);
+        }
+    }
+}
+
+/// Waits for a job that was just put in the foreground. If it stops again it
+/// stays in the table; otherwise it is removed. Returns its status.
+pub fn wait_foreground(shell: &mut Shell, id: usize, io: &mut Io) -> i32 {
+    let Some(job) = shell.jobs.get_mut(id) else {
+        return 0;
+    };
+    wait_for(&mut job.processes, true, false);
+    if let Some(terminal) = &shell.terminal {
+        terminal.reclaim();
+    }
+
+    if job.is_stopped() {
+        return suspended(shell, id, io);
+    }
+    let status = job.status();
+    shell.jobs.remove(id);
+    status
+}
+
+/// Reports that job `id` was stopped from the foreground and makes it the
+/// current job. Returns the status of a command stopped by Ctrl+Z.
+pub fn suspended(shell: &mut Shell, id: usize, io: &mut Io) -> i32 {
+    shell.jobs.make_current(id);
+    if let Some(job) = shell.jobs.get_mut(id) {
+        let job = job.clone();
+        let _ = writeln!(io.stderr, "\n{}", shell.jobs.describe(&job));
+    }
+    128 + libc::SIGTSTP
+}
+
+/// Sends `SIGCONT` to a job and marks its stopped processes as running.
+fn resume(job: &mut Job) {
+    // SAFETY: signalling a process group we created
+    unsafe {
+        libc::kill(-job.pgid, libc::SIGCONT);
+    }
+    for (_, state) in job.processes.iter_mut() {
+        if *state == ProcessState::Stopped {
+            *state = ProcessState::Running;
+        }
+    }
+}
+
+pub const JOBS: Spec = Spec::new(
+    "List the jobs.",
+    "",
+    &[
+        Opt::flag('l', "", "show each job's process group ID too"),
+        Opt::flag('p', "", "show only the process group IDs"),
+    ],
+);
+
+pub const FG: Spec = Spec::new("Continue a job in the foreground.", "[job]", &[]);
+
+pub const BG: Spec = Spec::new("Continue a stopped job in the background.", "[job]", &[]);
+
+pub const WAIT: Spec = Spec::new(
+    "Wait for jobs to finish, all of them by default.",
+    "[job|pid ...]",
+    &[],
+);
+
+pub const DISOWN: Spec = Spec::new(
+    "Forget jobs, the current one by default.",
+    "[job ...]",
+    &[Opt::flag('a', "all", "forget every job")],
+);
+
+/// `jobs [-l|-p]`: lists the job table.
+pub fn jobs(args: Args, shell: &mut Shell, io: &mut Io) -> Result<(), String> {
+    let pids_only = args.has('p');
+    let long = args.has('l') && !pids_only;
+    notify(shell, io);
+    for id in shell.jobs.ids() {
+        let Some(job) = shell.jobs.get_mut(id) else {
+            continue;
+        };
+        let job = job.clone();
+        let line = if pids_only {
+            job.pgid.to_string()
+        } else if long {
+            let line = shell.jobs.describe(&job);
+            format!("{} {}", job.pgid, line)
+        } else {
+            shell.jobs.describe(&job)
+        };
+        writeln!(io.stdout, "{}", line).map_err(|e| format!("jobs: {}", e))?;
+    }
+    Ok(())
+}
+
+/// `fg [job]`: continues a job in the foreground and waits for it.
+pub fn fg(args: Vec<&str>, shell: &mut Shell, io: &mut Io) -> Result<i32, String> {
+    let Some(terminal) = shell.terminal else {
+        return Err("fg: no job control".to_string());
+    };
+    let id = shell
+        .jobs
+        .find(args.first().copied())
+        .map_err(|e| format!("fg: {}", e))?;
+    let job = shell.jobs.get_mut(id).ok_or("fg: no such job")?;
+    let _ = writeln!(io.stdout, "{}", job.command.trim_end_matches(" &"));
+    job.command = job.command.trim_end_matches(" &").to_string();
+
+    terminal.give_to(job.pgid);
+    resume(job);
+    Ok(wait_foreground(shell, id, io))
+}
+
+/// `bg [job]`: continues a stopped job in the background.
+pub fn bg(args: Vec<&str>, shell: &mut Shell, io: &mut Io) -> Result<(), String> {
+    if shell.terminal.is_none() {
+        return Err("bg: no job control".to_string());
+    }
+    let id = shell
+        .jobs
+        .find(args.first().copied())
+        .map_err(|e| format!("bg: {}", e))?;
+    let job = shell.jobs.get_mut(id).ok_or("bg: no such job")?;
+    if !job.is_stopped() {
+        return Err(format!("bg: job {} already in background", id));
+    }
+    resume(job);
+    if !job.command.ends_with(" &") {
+        job.command.push_str(" &");
+    }
+    let line = format!("[{}]+ {}", job.id, job.command);
+    shell.jobs.make_current(id);
+    writeln!(io.stdout, "{}", line).map_err(|e| format!("bg: {}", e))
+}
+
+/// `wait [job|pid]...`: waits for the given jobs, or all of them, to finish
+/// and returns the status of the last one.
+pub fn wait(args: Vec<&str>, shell: &mut Shell, io: &mut Io) -> Result<i32, String> {
+    let ids = if args.is_empty() {
+        shell.jobs.ids()
+    } else {
+        let mut ids = Vec::new();
+        for arg in &args {
+            let id = match arg.parse::<pid_t>() {
+                // A bare number is a process ID
+                Ok(pid) => shell
+                    .jobs
+                    .ids()
+                    .into_iter()
+                    .find(|&id| {
+                        shell
+                            .jobs
+                            .get_mut(id)
+                            .is_some_and(|job| job.processes.iter().any(|(p, _)| *p == pid))
+                    })
+                    .ok_or_else(|| format!("wait: pid {} is not a child of this shell", pid))?,
+                Err(_) => shell
+                    .jobs
+                    .find(Some(arg))
+                    .map_err(|e| format!("wait: {}", e))?,
+            };
+            ids.push(id);
+        }
+        ids
+    };
+
+    let untraced = shell.terminal.is_some();
+    let mut status = 0;
+    for id in ids {
+        let Some(job) = shell.jobs.get_mut(id) else {
+            continue;
+        };
+        // A job that stops while we wait is reported like any other stop
+        wait_for(&mut job.processes, untraced, true);
+        // Ctrl+C ends the wait but leaves the job running
+        signals::check().map_err(|_| String::new())?;
+        status = if job.is_stopped() {
+            128 + libc::SIGTSTP
+        } else {
+            job.status()
+        };
+        if job.is_done() {
+            shell.jobs.remove(id);
+        }
+    }
+    let _ = io.stdout.flush();
+    Ok(status)
+}
+
+/// `disown [-a] [job]...`: removes jobs from the table so the shell forgets them.
+pub fn disown(args: Args, shell: &mut Shell) -> Result<(), String> {
+    if args.has('a') {
+        for id in shell.jobs.ids() {
+            shell.jobs.remove(id);
+        }
+        return Ok(());
+    }
+    let specs: Vec<Option<&str>> = if args.operands.is_empty() {
+        vec![None]
+    } else {
+        args.operands.into_iter().map(Some).collect()
+    };
+    for spec in specs {
+        let id = shell
+            .jobs
+            .find(spec)
+            .map_err(|e| format!("disown: {}", e))?;
+        shell.jobs.remove(id);
+    }
+    Ok(())
+}
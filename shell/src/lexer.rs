@@ -0,0 +1,803 @@
+// lexer.rs
+//
+// Turns a raw command line into a stream of tokens. Quoting is resolved here so
+// that every builtin receives its arguments exactly as the user meant them:
+// `cat "my file.txt"` yields one word, `echo 'a  b'` keeps both spaces, and
+// adjacent segments such as `"foo"'bar'baz` join into a single word.
+
+use std::fmt;
+
+/// One piece of a word, remembering how it was quoted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Unquoted text.
+    Literal(String),
+    /// Text from single quotes or a backslash escape, taken verbatim.
+    Quoted(String),
+    /// Raw text from inside double quotes. Backslashes are kept so the
+    /// expansion stage can tell `\$` from `$`.
+    DoubleQuoted(String),
+}
+
+/// A shell word made of one or more adjacent parts.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+/// Control operators that separate words.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    /// `|`
+    Pipe,
+    /// `;`
+    Semi,
+    /// `&&`
+    AndIf,
+    /// `||`
+    OrIf,
+    /// `&`, which runs the command before it in the background
+    Amp,
+    /// `;;`, which ends a `case` branch
+    DSemi,
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// A line break, which separates commands like `;`
+    Newline,
+    /// `<`
+    Less,
+    /// `<<`, which starts a here-document
+    DLess,
+    /// `<<-`, a here-document with leading tabs removed
+    DLessDash,
+    /// `<<<`, which feeds a single word to stdin
+    TLess,
+    /// `>`
+    Great,
+    /// `>>`
+    DGreat,
+    /// `>|`, which overwrites even when `noclobber` is set
+    Clobber,
+    /// `<>`
+    LessGreat,
+    /// `<&`
+    LessAnd,
+    /// `>&`
+    GreatAnd,
+    /// `&>`
+    AndGreat,
+    /// `&>>`
+    AndDGreat,
+}
+
+impl Operator {
+    pub fn is_redirect(self) -> bool {
+        !matches!(
+            self,
+            Operator::Pipe
+                | Operator::Semi
+                | Operator::AndIf
+                | Operator::OrIf
+                | Operator::Amp
+                | Operator::DSemi
+                | Operator::LParen
+                | Operator::RParen
+                | Operator::Newline
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Operator::Pipe => "|",
+            Operator::Semi => ";",
+            Operator::AndIf => "&&",
+            Operator::OrIf => "||",
+            Operator::Amp => "&",
+            Operator::DSemi => ";;",
+            Operator::LParen => "(",
+            Operator::RParen => ")",
+            Operator::Newline => "newline",
+            Operator::Less => "<",
+            Operator::DLess => "<<",
+            Operator::DLessDash => "<<-",
+            Operator::TLess => "<<<",
+            Operator::Great => ">",
+            Operator::DGreat => ">>",
+            Operator::Clobber => ">|",
+            Operator::LessGreat => "<>",
+            Operator::LessAnd => "<&",
+            Operator::GreatAnd => ">&",
+            Operator::AndGreat => "&>",
+            Operator::AndDGreat => "&>>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(Word),
+    Operator(Operator),
+    /// The file descriptor number written directly before a redirection, as in `2>`.
+    IoNumber(u32),
+    /// The expression of an arithmetic command `(( expr ))`, kept raw.
+    Arithmetic(String),
+    /// The body of a here-document, placed right after its delimiter word.
+    HereDoc(HereDoc),
+}
+
+/// The lines of a here-document, read after the command line it belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HereDoc {
+    pub body: String,
+    /// Whether parameters and commands in the body are expanded, which a
+    /// quoted delimiter such as `<<'EOF'` turns off.
+    pub expand: bool,
+}
+
+impl Word {
+    /// Returns the word with all quoting removed.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(s) | WordPart::Quoted(s) => text.push_str(s),
+                WordPart::DoubleQuoted(s) => text.push_str(&unescape_double_quoted(s)),
+            }
+        }
+        text
+    }
+
+    fn push_literal(&mut self, c: char) {
+        if let Some(WordPart::Literal(s)) = self.parts.last_mut() {
+            s.push(c);
+        } else {
+            self.parts.push(WordPart::Literal(c.to_string()));
+        }
+    }
+}
+
+/// Writes the word back out as it could have been typed, for job listings.
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(s) => write!(f, "{}", s)?,
+                // Only a backslash escape can produce a quoted `'`
+                WordPart::Quoted(s) if s == "'" => write!(f, "\\'")?,
+                WordPart::Quoted(s) => write!(f, "'{}'", s)?,
+                WordPart::DoubleQuoted(s) => write!(f, "\"{}\"", s)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Characters a backslash may escape inside double quotes.
+pub(crate) const DOUBLE_QUOTE_ESCAPES: [char; 4] = ['$', '`', '"', '\\'];
+
+/// What a backslash followed by `next` stands for inside double quotes, and
+/// whether it takes `next` with it: the escaped character, nothing for an
+/// escaped newline, and otherwise the backslash itself.
+pub(crate) fn double_quoted_escape(next: Option<char>) -> (Option<char>, bool) {
+    match next {
+        Some(next) if DOUBLE_QUOTE_ESCAPES.contains(&next) => (Some(next), true),
+        Some('\n') => (None, true),
+        _ => (Some('\\'), false),
+    }
+}
+
+/// Removes the backslashes that are special inside double quotes.
+pub(crate) fn unescape_double_quoted(raw: &str) -> String {
+    let mut out = String::new();
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let (text, taken) = double_quoted_escape(chars.peek().copied());
+            out.extend(text);
+            if taken {
+                chars.next();
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Splits `input` into tokens. Every error means the input ended in the
+/// middle of a quote or similar, so more input could complete it.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut i = 0;
+    // Tokens before this index have had their here-documents read
+    let mut here_docs_read = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        // `((` starting a word opens an arithmetic command if a `))` closes it
+        if c == '(' && word.is_none() && chars.get(i + 1) == Some(&'(') {
+            let end = find_closing_paren(&chars, i + 2)?;
+            if chars.get(end + 1) == Some(&')') {
+                let expr: String = chars[i + 2..end].iter().collect();
+                tokens.push(Token::Arithmetic(expr));
+                i = end + 2;
+                continue;
+            }
+        }
+        match c {
+            ' ' | '\t' => {
+                if let Some(w) = word.take() {
+                    tokens.push(Token::Word(w));
+                }
+                i += 1;
+            }
+            // In the regular expression after `=~` in `[[ ]]`, as in
+            // `[[ $x =~ ^(a|b)$ ]]`, these are ordinary characters
+            '|' | '<' | '>' | '(' | ')' if after_regex_operator(&tokens) => {
+                word.get_or_insert_with(Word::default).push_literal(c);
+                i += 1;
+            }
+            '|' | '<' | '>' | ';' | '(' | ')' | '\n' => {
+                let (op, len) = match (c, chars.get(i + 1)) {
+                    ('|', Some('|')) => (Operator::OrIf, 2),
+                    (';', Some(';')) => (Operator::DSemi, 2),
+                    (';', _) => (Operator::Semi, 1),
+                    ('(', _) => (Operator::LParen, 1),
+                    (')', _) => (Operator::RParen, 1),
+                    ('\n', _) => (Operator::Newline, 1),
+                    ('>', Some('>')) => (Operator::DGreat, 2),
+                    ('>', Some('|')) => (Operator::Clobber, 2),
+                    ('>', Some('&')) => (Operator::GreatAnd, 2),
+                    ('>', _) => (Operator::Great, 1),
+                    ('<', Some('<')) => match chars.get(i + 2) {
+                        Some('<') => (Operator::TLess, 3),
+                        Some('-') => (Operator::DLessDash, 3),
+                        _ => (Operator::DLess, 2),
+                    },
+                    ('<', Some('>')) => (Operator::LessGreat, 2),
+                    ('<', Some('&')) => (Operator::LessAnd, 2),
+                    ('<', _) => (Operator::Less, 1),
+                    _ => (Operator::Pipe, 1),
+                };
+                if let Some(w) = word.take() {
+                    // Digits glued to a redirection name the descriptor it applies to
+                    match io_number(&w) {
+                        Some(fd) if op.is_redirect() => tokens.push(Token::IoNumber(fd)),
+                        _ => tokens.push(Token::Word(w)),
+                    }
+                }
+                tokens.push(Token::Operator(op));
+                i += len;
+                // Here-documents begin on the line after their command
+                if op == Operator::Newline {
+                    i = read_here_docs(&chars, i, &mut tokens, here_docs_read)?;
+                    here_docs_read = tokens.len();
+                }
+            }
+            '&' if chars.get(i + 1) == Some(&'&') => {
+                if let Some(w) = word.take() {
+                    tokens.push(Token::Word(w));
+                }
+                tokens.push(Token::Operator(Operator::AndIf));
+                i += 2;
+            }
+            '&' if chars.get(i + 1) == Some(&'>') => {
+                if let Some(w) = word.take() {
+                    tokens.push(Token::Word(w));
+                }
+                if chars.get(i + 2) == Some(&'>') {
+                    tokens.push(Token::Operator(Operator::AndDGreat));
+                    i += 3;
+                } else {
+                    tokens.push(Token::Operator(Operator::AndGreat));
+                    i += 2;
+                }
+            }
+            '&' => {
+                if let Some(w) = word.take() {
+                    tokens.push(Token::Word(w));
+                }
+                tokens.push(Token::Operator(Operator::Amp));
+                i += 1;
+            }
+            // A comment runs to the end of the line, but only at the start of a word
+            '#' if word.is_none() => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '\\' => {
+                i += 1;
+                match chars.get(i) {
+                    // Backslash-newline is a line continuation and disappears,
+                    // but at the very end the continuation is still to come
+                    Some('\n') if i + 1 == chars.len() => {
+                        return Err("syntax error: unexpected end of file".to_string());
+                    }
+                    Some('\n') => {}
+                    Some(&escaped) => word
+                        .get_or_insert_with(Word::default)
+                        .parts
+                        .push(WordPart::Quoted(escaped.to_string())),
+                    None => word
+                        .get_or_insert_with(Word::default)
+                        .parts
+                        .push(WordPart::Quoted("\\".to_string())),
+                }
+                i += 1;
+            }
+            '\'' => {
+                let start = i + 1;
+                let end = find_closing(&chars, start, '\'')?;
+                let text: String = chars[start..end].iter().collect();
+                word.get_or_insert_with(Word::default)
+                    .parts
+                    .push(WordPart::Quoted(text));
+                i = end + 1;
+            }
+            '"' => {
+                let start = i + 1;
+                let end = find_closing(&chars, start, '"')?;
+                let text: String = chars[start..end].iter().collect();
+                word.get_or_insert_with(Word::default)
+                    .parts
+                    .push(WordPart::DoubleQuoted(text));
+                i = end + 1;
+            }
+            // `${...}` may hold spaces and quotes, as in `${NAME:-a b}`, and a
+            // command substitution a whole command line, so keep them whole
+            '$' | '`' => {
+                let end = match (c, chars.get(i + 1)) {
+                    ('$', Some('{')) => find_closing_brace(&chars, i + 2)?,
+                    ('$', Some('(')) => find_closing_paren(&chars, i + 2)?,
+                    ('`', _) => find_closing_backtick(&chars, i + 1)?,
+                    _ => i,
+                };
+                let w = word.get_or_insert_with(Word::default);
+                for &c in &chars[i..=end] {
+                    w.push_literal(c);
+                }
+                i = end + 1;
+            }
+            _ => {
+                word.get_or_insert_with(Word::default).push_literal(c);
+                i += 1;
+            }
+        }
+    }
+
+    if let Some(w) = word {
+        tokens.push(Token::Word(w));
+    }
+    // A here-document still to come means the command is not complete
+    if let Some(delimiter) = here_doc_delimiters(&tokens[here_docs_read..]).next() {
+        return Err(format!(
+            "here-document delimited by end-of-file (wanted `{}')",
+            delimiter.text()
+        ));
+    }
+    Ok(tokens)
+}
+
+/// The delimiter words of the here-documents opened in `tokens`.
+fn here_doc_delimiters(tokens: &[Token]) -> impl Iterator<Item = &Word> {
+    tokens.windows(2).filter_map(|pair| match pair {
+        [Token::Operator(Operator::DLess | Operator::DLessDash), Token::Word(delimiter)] => {
+            Some(delimiter)
+        }
+        _ => None,
+    })
+}
+
+/// Reads the bodies of the here-documents opened in `tokens[from..]`, one
+/// after another from `chars[start..]`, and puts each after its delimiter.
+/// Returns the index just past the last body.
+fn read_here_docs(
+    chars: &[char],
+    start: usize,
+    tokens: &mut Vec<Token>,
+    from: usize,
+) -> Result<usize, String> {
+    let mut i = start;
+    let mut k = from;
+    while k + 1 < tokens.len() {
+        let (strip_tabs, delimiter) = match (&tokens[k], &tokens[k + 1]) {
+            (Token::Operator(Operator::DLess), Token::Word(delimiter)) => (false, delimiter),
+            (Token::Operator(Operator::DLessDash), Token::Word(delimiter)) => (true, delimiter),
+            _ => {
+                k += 1;
+                continue;
+            }
+        };
+        let end_line = delimiter.text();
+        let expand = delimiter.parts.iter().all(|part| matches!(part, WordPart::Literal(_)));
+
+        let mut body = String::new();
+        loop {
+            if i >= chars.len() {
+                return Err(format!(
+                    "here-document delimited by end-of-file (wanted `{}')",
+                    end_line
+                ));
+            }
+            let end = chars[i..].iter().position(|&c| c == '\n').map_or(chars.len(), |p| i + p);
+            let mut line: String = chars[i..end].iter().collect();
+            i = end + 1;
+            if strip_tabs {
+                line = line.trim_start_matches('\t').to_string();
+            }
+            if line == end_line {
+                break;
+            }
+            body.push_str(&line);
+            body.push('\n');
+        }
+        tokens.insert(k + 2, Token::HereDoc(HereDoc { body, expand }));
+        k += 3;
+    }
+    Ok(i)
+}
+
+/// Whether the word being read follows `=~`, the regex operator of `[[ ]]`.
+fn after_regex_operator(tokens: &[Token]) -> bool {
+    match tokens.last() {
+        Some(Token::Word(word)) => word.parts == [WordPart::Literal("=~".to_string())],
+        _ => false,
+    }
+}
+
+/// Returns the descriptor number if `word` is made only of unquoted digits.
+fn io_number(word: &Word) -> Option<u32> {
+    match word.parts.as_slice() {
+        [WordPart::Literal(s)] if s.chars().all(|c| c.is_ascii_digit()) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Finds the index of the quote that closes a quoted segment starting at `start`.
+fn find_closing(chars: &[char], start: usize, quote: char) -> Result<usize, String> {
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == quote {
+            return Ok(i);
+        }
+        if quote == '"' {
+            match chars[i] {
+                // Inside double quotes a backslash protects the next character
+                '\\' => i += 1,
+                '$' if chars.get(i + 1) == Some(&'{') => i = find_closing_brace(chars, i + 2)?,
+                '$' if chars.get(i + 1) == Some(&'(') => i = find_closing_paren(chars, i + 2)?,
+                '`' => i = find_closing_backtick(chars, i + 1)?,
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    Err(format!("unexpected EOF while looking for matching `{}'", quote))
+}
+
+/// Finds the `}` that closes a `${` whose body starts at `start`, skipping
+/// over quotes and nested braces inside it.
+pub fn find_closing_brace(chars: &[char], start: usize) -> Result<usize, String> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' | '"' => i = find_closing(chars, i + 1, chars[i])?,
+            '`' => i = find_closing_backtick(chars, i + 1)?,
+            '$' if chars.get(i + 1) == Some(&'(') => i = find_closing_paren(chars, i + 2)?,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Ok(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    Err("unexpected EOF while looking for matching `}'".to_string())
+}
+
+/// Finds the `)` that closes a `$(` whose command starts at `start`. Quotes
+/// and nested parentheses, as in `$(echo ")" $(pwd))`, are skipped over.
+pub fn find_closing_paren(chars: &[char], start: usize) -> Result<usize, String> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' | '"' => i = find_closing(chars, i + 1, chars[i])?,
+            '`' => i = find_closing_backtick(chars, i + 1)?,
+            '$' if chars.get(i + 1) == Some(&'{') => i = find_closing_brace(chars, i + 2)?,
+            '(' => depth += 1,
+            ')' if depth == 0 => return Ok(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    Err("unexpected EOF while looking for matching `)'".to_string())
+}
+
+/// Finds the backtick that closes a `` `command` `` starting at `start`. A
+/// backslash inside escapes the next character, including a backtick.
+pub fn find_closing_backtick(chars: &[char], start: usize) -> Result<usize, String> {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '`' => return Ok(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    Err("unexpected EOF while looking for matching ``'".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(text: &str) -> WordPart {
+        WordPart::Literal(text.to_string())
+    }
+
+    fn quoted(text: &str) -> WordPart {
+        WordPart::Quoted(text.to_string())
+    }
+
+    fn word(parts: Vec<WordPart>) -> Token {
+        Token::Word(Word { parts })
+    }
+
+    /// The words of `input` with their quoting removed.
+    fn words(input: &str) -> Vec<String> {
+        tokenize(input)
+            .unwrap()
+            .iter()
+            .map(|token| match token {
+                Token::Word(word) => word.text(),
+                other => panic!("not a word: {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(words("cat \"my file.txt\""), ["cat", "my file.txt"]);
+        assert_eq!(words("echo 'a  b'"), ["echo", "a  b"]);
+        assert_eq!(words("echo a\\ b"), ["echo", "a b"]);
+        assert_eq!(words("echo \"foo\"'bar'baz"), ["echo", "foobarbaz"]);
+        assert_eq!(words("echo \"a \\\"b\\\" \\$c\""), ["echo", "a \"b\" $c"]);
+        assert_eq!(words("echo '\\$x'"), ["echo", "\\$x"]);
+        assert_eq!(words("echo ''"), ["echo", ""]);
+    }
+
+    #[test]
+    fn quoting_is_remembered() {
+        let tokens = tokenize("a'b'\\c\"$d\"").unwrap();
+        let parts = vec![
+            literal("a"),
+            quoted("b"),
+            quoted("c"),
+            WordPart::DoubleQuoted("$d".to_string()),
+        ];
+        assert_eq!(tokens, [word(parts)]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(words("echo a # b c"), ["echo", "a"]);
+        assert_eq!(words("echo a#b"), ["echo", "a#b"]);
+        assert_eq!(words("echo '#'"), ["echo", "#"]);
+    }
+
+    #[test]
+    fn incomplete_quotes() {
+        assert!(tokenize("echo 'abc").is_err());
+        assert!(tokenize("echo \"abc").is_err());
+        assert!(tokenize("echo a\\\n").is_err());
+        // A continued line joins the next one
+        assert_eq!(words("echo a\\\nb"), ["echo", "ab"]);
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for input in ["echo 'a b' \"$c\" d\\'e", "a\"b\"'c'"] {
+            let tokens = tokenize(input).unwrap();
+            let shown: Vec<String> = tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Word(word) => word.to_string(),
+                    other => panic!("not a word: {:?}", other),
+                })
+                .collect();
+            assert_eq!(tokenize(&shown.join(" ")).unwrap(), tokens);
+        }
+    }
+
+    fn op(op: Operator) -> Token {
+        Token::Operator(op)
+    }
+
+    #[test]
+    fn pipes() {
+        let tokens = tokenize("a|b | c").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                word(vec![literal("a")]),
+                op(Operator::Pipe),
+                word(vec![literal("b")]),
+                op(Operator::Pipe),
+                word(vec![literal("c")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn redirections() {
+        let tokens = tokenize("cmd 2>&1 >>out <in &>all 3<>rw >|x").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                word(vec![literal("cmd")]),
+                Token::IoNumber(2),
+                op(Operator::GreatAnd),
+                word(vec![literal("1")]),
+                op(Operator::DGreat),
+                word(vec![literal("out")]),
+                op(Operator::Less),
+                word(vec![literal("in")]),
+                op(Operator::AndGreat),
+                word(vec![literal("all")]),
+                Token::IoNumber(3),
+                op(Operator::LessGreat),
+                word(vec![literal("rw")]),
+                op(Operator::Clobber),
+                word(vec![literal("x")]),
+            ]
+        );
+        // Only unquoted digits glued to the operator name a descriptor
+        assert_eq!(tokenize("echo 2 >x").unwrap()[1], word(vec![literal("2")]));
+        assert_eq!(tokenize("echo '2'>x").unwrap()[1], word(vec![quoted("2")]));
+    }
+
+    #[test]
+    fn list_operators() {
+        let tokens = tokenize("a;b&&c||d\n").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                word(vec![literal("a")]),
+                op(Operator::Semi),
+                word(vec![literal("b")]),
+                op(Operator::AndIf),
+                word(vec![literal("c")]),
+                op(Operator::OrIf),
+                word(vec![literal("d")]),
+                op(Operator::Newline),
+            ]
+        );
+    }
+
+    #[test]
+    fn parameter_expansions_stay_whole() {
+        assert_eq!(
+            words("echo ${x:-a b} \"${y#'*'}\""),
+            ["echo", "${x:-a b}", "${y#'*'}"]
+        );
+        assert!(tokenize("echo ${x").is_err());
+    }
+
+    #[test]
+    fn background() {
+        let tokens = tokenize("a&b &>x").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                word(vec![literal("a")]),
+                op(Operator::Amp),
+                word(vec![literal("b")]),
+                op(Operator::AndGreat),
+                word(vec![literal("x")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn case_terminator() {
+        let tokens = tokenize("a;;b").unwrap();
+        assert_eq!(tokens[1], op(Operator::DSemi));
+    }
+
+    #[test]
+    fn command_substitutions_stay_whole() {
+        assert_eq!(
+            words("echo $(echo 'c d' \")\") `e f`"),
+            ["echo", "$(echo 'c d' \")\")", "`e f`"]
+        );
+        assert!(tokenize("echo $(ls").is_err());
+        assert!(tokenize("echo `ls").is_err());
+    }
+
+    #[test]
+    fn arithmetic_command() {
+        assert_eq!(
+            tokenize("((x = (1 + 2) * 3))").unwrap(),
+            [Token::Arithmetic("x = (1 + 2) * 3".to_string())]
+        );
+        // Two subshells are not arithmetic
+        assert_eq!(tokenize("((a) )").unwrap()[0], op(Operator::LParen));
+    }
+
+    #[test]
+    fn here_documents() {
+        let tokens = tokenize("cat <<EOF\nhello $x\n  EOF\nEOF\necho").unwrap();
+        assert_eq!(
+            tokens[1..5],
+            [
+                op(Operator::DLess),
+                word(vec![literal("EOF")]),
+                Token::HereDoc(HereDoc {
+                    body: "hello $x\n  EOF\n".to_string(),
+                    expand: true,
+                }),
+                op(Operator::Newline),
+            ]
+        );
+        assert_eq!(tokens[5], word(vec![literal("echo")]));
+
+        let tokens = tokenize("cat <<'EOF'\n$x\nEOF\n").unwrap();
+        let body = HereDoc {
+            body: "$x\n".to_string(),
+            expand: false,
+        };
+        assert_eq!(tokens[3], Token::HereDoc(body));
+
+        let tokens = tokenize("cat <<-EOF\n\t\tindented\n\tEOF\n").unwrap();
+        let body = HereDoc {
+            body: "indented\n".to_string(),
+            expand: true,
+        };
+        assert_eq!(tokens[3], Token::HereDoc(body));
+
+        // Two on one line are read one after the other
+        let tokens = tokenize("cat <<A <<B\na\nA\nb\nB\n").unwrap();
+        let bodies: Vec<&str> = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::HereDoc(doc) => Some(doc.body.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bodies, ["a\n", "b\n"]);
+
+        assert!(tokenize("cat <<EOF").is_err());
+        assert!(tokenize("cat <<EOF\nbody\n").is_err());
+    }
+
+    #[test]
+    fn here_strings() {
+        assert_eq!(
+            tokenize("cat <<<'a b'").unwrap()[1..],
+            [op(Operator::TLess), word(vec![quoted("a b")])]
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            tokenize("(a)\n").unwrap(),
+            [
+                op(Operator::LParen),
+                word(vec![literal("a")]),
+                op(Operator::RParen),
+                op(Operator::Newline),
+            ]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+// loops.rs
+//
+// `break [n]` and `continue [n]`. They only record the jump in the shell's
+// state; the loops in the executor see it once the current command finishes
+// and leave or restart as many levels as asked.
+
+use crate::getopt::Spec;
+use crate::state::{Flow, Shell};
+
+pub const BREAK: Spec = Spec::new("Leave the innermost n loops, or one.", "[n]", &[]);
+
+pub const CONTINUE: Spec = Spec::new(
+    "Start the next iteration of the nth enclosing loop, or the innermost.",
+    "[n]",
+    &[],
+);
+
+pub fn break_loop(args: Vec<&str>, shell: &mut Shell) -> Result<(), String> {
+    let levels = levels("break", &args, shell)?;
+    shell.flow = Some(Flow::Break(levels));
+    Ok(())
+}
+
+pub fn continue_loop(args: Vec<&str>, shell: &mut Shell) -> Result<(), String> {
+    let levels = levels("continue", &args, shell)?;
+    shell.flow = Some(Flow::Continue(levels));
+    Ok(())
+}
+
+/// How many loops to jump out of, capped at the number that are running.
+fn levels(name: &str, args: &[&str], shell: &Shell) -> Result<usize, String> {
+    if shell.loop_depth == 0 {
+        return Err(format!(
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            name
+        ));
+    }
+    let levels = match args {
+        [] => 1,
+        [n] => match n.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(format!("{}: {}: loop count out of range", name, n)),
+        },
+        _ => return Err(format!("{}: too many arguments", name)),
+    };
+    Ok(levels.min(shell.loop_depth))
+}
@@ -1,47 +1,103 @@
-// cmd_executor.rs
+// ls.rs
+//
+// The `ls` builtin: lists what is in directories, and files themselves, with
+// `-l` details, `-a` hidden names and `-F` type marks.
 
-use std::env;
 use std::fs;
+use std::io::Write;
 use std::os::unix::fs::MetadataExt;
 use users::{get_user_by_uid, get_group_by_gid};
 use chrono::prelude::*;
 use xattr::list;
 use std::path::Path;
-use exacl::{getfacl, setfacl, AclEntry, Perm};
-
-    pub fn handlels(args: Vec<&str>) -> Result<(), String> {
-        let mut args = args; // Declare args as mutable
-        let directory = if args.len() > 0 && !args[0].starts_with('-') {
-            Some(args.remove(0))
-        } else {
-            None
-        };
-    
-        let mut list_hidden = false;
-        let mut list_long = false; 
-        let mut list_indicator = false;
-        for arg in args.iter() {
-            match *arg {
-                "-a" => list_hidden = true,
-                "-l" => list_long = true,
-                "-F" => list_indicator = true,
-                 _=> return Err(format!("ls: invalid option -- '{}'\nusage: ls [-a] [-l] [-F]", arg)),
+use exacl::{getfacl, AclEntry};
+
+use crate::exec::describe;
+use crate::getopt::{Args, Opt, Spec};
+use crate::stream::Io;
+
+pub const SPEC: Spec = Spec::new(
+    "List the files in directories, and files themselves, the current directory by default.",
+    "[file ...]",
+    &[
+        Opt::flag('a', "all", "include names starting with ."),
+        Opt::flag('d', "directory", "list directories themselves, not what is in them"),
+        Opt::flag('l', "", "show permissions, owner, size and time"),
+        Opt::flag('F', "classify", "mark directories with / and programs with *"),
+    ],
+);
+
+/// `ls [file...]`. Like coreutils, the files named are listed first, then
+/// each directory's contents under a `name:` header when there is more than
+/// one operand. An operand that cannot be listed is reported and the others
+/// still are.
+pub fn handlels(args: Args, io: &mut Io) -> Result<(), String> {
+    let list_hidden = args.has('a');
+    let list_long = args.has('l');
+    let list_indicator = args.has('F');
+    let list_directories = args.has('d');
+    let operands = if args.operands.is_empty() { vec!["."] } else { args.operands };
+
+    let mut failed = false;
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for operand in &operands {
+        // Following links, so that a link to a directory lists what is in it,
+        // unless it leads nowhere
+        let metadata = fs::metadata(operand).or_else(|_| fs::symlink_metadata(operand));
+        match metadata {
+            Ok(metadata) if metadata.is_dir() && !list_directories => dirs.push(*operand),
+            Ok(metadata) => files.push((*operand, metadata)),
+            Err(err) => {
+                let _ = writeln!(io.stderr, "ls: cannot access '{}': {}", operand, describe(&err));
+                failed = true;
             }
         }
-        let dir = match directory {
-            Some(dir) => Path::new(dir),
-            None => Path::new("."),
-        };
-        // env::current_dir() returns a Result<PathBuf, Error> that contains the current directory.
-        // If the current directory cannot be determined, an error is returned.
-    /*     let current_dir = match env::current_dir() {
-            Ok(dir) => dir,
-            Err(err) => return Err(format!("ls: {}", err)),
-        }; */
-        print_entries(&dir, list_long, list_hidden, list_indicator)
     }
+    files.sort_by_key(|(name, _)| *name);
+    dirs.sort();
 
-fn print_entries(dir: &Path, details:bool, list_hidden: bool, list_indicator: bool) -> Result<(), String> {
+    let out = &mut io.stdout;
+    for (name, metadata) in &files {
+        // The metadata looked up above, so that a link that leads nowhere is
+        // shown as the link itself
+        let printed = print_file(Path::new(name), name, metadata, list_long, list_indicator, out);
+        match printed {
+            Ok(()) => {}
+            // The reader went away, so there is no point going on
+            Err(e) if e.is_empty() => return Err(e),
+            Err(e) => {
+                let _ = writeln!(io.stderr, "{}", e);
+                failed = true;
+            }
+        }
+    }
+    let headers = operands.len() > 1;
+    for (i, dir) in dirs.iter().enumerate() {
+        if i > 0 || !files.is_empty() {
+            writeln!(out).map_err(write_error)?;
+        }
+        if headers {
+            writeln!(out, "{}:", dir).map_err(write_error)?;
+        }
+        match print_entries(Path::new(dir), list_long, list_hidden, list_indicator, out) {
+            Ok(()) => {}
+            // The reader went away, so there is no point going on
+            Err(e) if e.is_empty() => return Err(e),
+            Err(e) => {
+                let _ = writeln!(io.stderr, "{}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        // Each failure was reported as it happened
+        return Err(String::new());
+    }
+    Ok(())
+}
+
+fn print_entries(dir: &Path, details:bool, list_hidden: bool, list_indicator: bool, out: &mut dyn Write) -> Result<(), String> {
         let mut entries = match fs::read_dir(dir) {
             Ok(entries) => {
                 let mut entries = entries.filter_map(Result::ok).collect::<Vec<_>>();
@@ -50,107 +106,180 @@ fn print_entries(dir: &Path, details:bool, list_hidden: bool, list_indicator: bo
                 } 
                 entries
             }
-            Err(err) => return Err(format!("ls: {}", err)),
+            Err(err) => {
+                let dir = dir.display();
+                return Err(format!("ls: cannot open directory '{}': {}", dir, describe(&err)));
+            }
         };
 
-    entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    entries.sort_by_key(|entry| entry.file_name());
     // Print the total size of the entries if the -l flag is set
     if details {
-        let total_size = calculate_total_size(&entries).unwrap();
-        println!("total {}", total_size);
+        let total_size = calculate_total_size(&entries)?;
+        writeln!(out, "total {}", total_size).map_err(write_error)?;
     }
 
     if list_hidden {
-        add_current_and_parent_name_to_entries(details, list_indicator);
+        add_current_and_parent_name_to_entries(dir, details, list_indicator, out)?;
     }
 
     // Iterate over entries
     for entry in entries {
-        // Print entry details
-        if details {
-            let print_entry_details: Result<(), String> = print_entry_details(&entry.path());
-            if print_entry_details.is_err() {
-                return Err(print_entry_details.unwrap_err());
-            }
-        }
-        // Print entry name
-        print!(" {}", entry.file_name().to_string_lossy());
-
-        if list_indicator {
-            print_indicator(&entry);
-        }
-        println!();
-
+        // Not following links, as for the total above
+        let metadata = entry.metadata().map_err(|err| {
+            format!("ls: cannot access '{}': {}", entry.path().display(), describe(&err))
+        })?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        print_file(&entry.path(), &name, &metadata, details, list_indicator, out)?;
     }
 
     Ok(())
 }
 
-fn print_entry_details(entry_path:&Path) -> Result<(), String> {
-    let metadata = match entry_path.metadata() {
-        Ok(meta) => meta,
-        Err(err) => return Err(format!("ls: {}", err)),
-    };
-    let user = get_user_by_uid(metadata.uid()).unwrap();
-    let group = get_group_by_gid(metadata.gid()).unwrap();
-    let mut formatted_time = String::new();
-    // check if the year of the file is the current year or not otherwise print the year instead of %H:%M
-    if Local::now().year() != Local.timestamp(metadata.mtime(), 0).year() {
-        formatted_time = Local.timestamp(metadata.mtime(), 0).format("%b %e  %Y").to_string();
+/// Describes a failed write. A reader that went away (`ls | head -1`) is not an
+/// error worth reporting, so it maps to an empty message.
+fn write_error(err: std::io::Error) -> String {
+    if err.kind() == std::io::ErrorKind::BrokenPipe {
+        String::new()
     } else {
-        formatted_time = Local.timestamp(metadata.mtime(), 0).format("%b %e %H:%M").to_string();
+        format!("ls: {}", err)
+    }
+}
+
+/// One line of the listing: ` name`, after the details with `-l` and before
+/// the type mark with `-F`.
+fn print_file(
+    path: &Path,
+    name: &str,
+    metadata: &fs::Metadata,
+    details: bool,
+    list_indicator: bool,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    if details {
+        print_entry_details(path, metadata, out)?;
     }
-    let permissions = convert_to_permission(&metadata, entry_path.to_str().unwrap());
-    print!("{:12} {:>3} {:20} {:10} {:6} {:12}",
+    write!(out, " {}", name).map_err(write_error)?;
+    if list_indicator {
+        print_indicator(metadata, out)?;
+    }
+    writeln!(out).map_err(write_error)
+}
+
+fn print_entry_details(
+    entry_path: &Path,
+    metadata: &fs::Metadata,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    // An owner missing from the user database shows as its number
+    let user = get_user_by_uid(metadata.uid())
+        .map(|user| user.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| metadata.uid().to_string());
+    let group = get_group_by_gid(metadata.gid())
+        .map(|group| group.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| metadata.gid().to_string());
+    // check if the year of the file is the current year or not otherwise print the year instead of %H:%M
+    let formatted_time = match Local.timestamp_opt(metadata.mtime(), 0).earliest() {
+        Some(modified) if Local::now().year() != modified.year() => {
+            modified.format("%b %e  %Y").to_string()
+        }
+        Some(modified) => modified.format("%b %e %H:%M").to_string(),
+        // A time chrono cannot represent shows as the raw seconds
+        None => metadata.mtime().to_string(),
+    };
+    let permissions = convert_to_permission(metadata, entry_path);
+    write!(out, "{:12} {:>3} {:20} {:10} {:6} {:12}",
         permissions,
         metadata.nlink(),
-        user.name().to_string_lossy(),
-        group.name().to_string_lossy(),
+        user,
+        group,
         // print the size of the file in bytes if the file is not a directory
         metadata.size(),
         formatted_time,
-    );
+    ).map_err(write_error)?;
 
     Ok(())
 }
 
-fn has_extended_acl(path: &str) -> Result<bool, std::io::Error> {
+fn has_extended_acl(path: &Path) -> Result<bool, std::io::Error> {
     let acl: Vec<AclEntry> = getfacl(path, None)?;
-    if acl.is_empty() {
-        return Ok(false);
+    Ok(!acl.is_empty())
+}
+
+// Define a mapping of bit positions to permission characters
+// the codes are octal literals representing file mode permission bits in Unix-like systems
+/// Mapping of permission characters to their corresponding octal values.
+/// Each tuple represents a permission character and its corresponding octal value.
+/// The first element of the tuple represents the permission character ('r', 'w', or 'x'),
+/// and the second element represents the octal value.
+const PERMISSIONS_MAPPING: [(char, u32); 9] = [
+    ('r', 0o0400),  // Read permission for owner
+    ('w', 0o0200),  // Write permission for owner
+    ('x', 0o0100),  // Execute permission for owner
+    ('r', 0o0040),  // Read permission for group
+    ('w', 0o0020),  // Write permission for group
+    ('x', 0o0010),  // Execute permission for group
+    ('r', 0o0004),  // Read permission for others
+    ('w', 0o0002),  // Write permission for others
+    ('x', 0o0001),  // Execute permission for others
+];
+
+/// Whether the current user may read, write or execute (`'r'`, `'w'` or `'x'`)
+/// a file, going by the same permission bits `ls -l` shows. Only the owner,
+/// group or others triple that applies to the user counts.
+pub fn has_permission(metadata: &fs::Metadata, permission: char) -> bool {
+    // SAFETY: plain libc calls without arguments
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    if uid == 0 {
+        // root may read and write anything, and execute what anyone may
+        return permission != 'x' || metadata.mode() & 0o111 != 0;
+    }
+    let class = if metadata.uid() == uid {
+        &PERMISSIONS_MAPPING[0..3]
+    } else if metadata.gid() == gid || in_group(metadata.gid()) {
+        &PERMISSIONS_MAPPING[3..6]
+    } else {
+        &PERMISSIONS_MAPPING[6..9]
+    };
+    class
+        .iter()
+        .any(|&(char, mask)| char == permission && metadata.mode() & mask != 0)
+}
+
+/// Whether `gid` is one of the current user's supplementary groups.
+fn in_group(gid: u32) -> bool {
+    // SAFETY: the first call only counts the groups, the second fills a
+    // buffer of that size
+    unsafe {
+        let count = libc::getgroups(0, std::ptr::null_mut());
+        if count <= 0 {
+            return false;
+        }
+        let mut groups = vec![0; count as usize];
+        let count = libc::getgroups(count, groups.as_mut_ptr());
+        groups.iter().take(count.max(0) as usize).any(|&group| group == gid)
     }
-    return Ok(true);
 }
 
-fn convert_to_permission(metadata: &fs::Metadata, path: &str) -> String {
-    // Define a mapping of bit positions to permission characters
-    // the codes are octal literals representing file mode permission bits in Unix-like systems
-    /// Mapping of permission characters to their corresponding octal values.
-    /// Each tuple represents a permission character and its corresponding octal value.
-    /// The first element of the tuple represents the permission character ('r', 'w', or 'x'),
-    /// and the second element represents the octal value.
-    const PERMISSIONS_MAPPING: [(char, u32); 9] = [
-        ('r', 0o0400),  // Read permission for owner
-        ('w', 0o0200),  // Write permission for owner
-        ('x', 0o0100),  // Execute permission for owner
-        ('r', 0o0040),  // Read permission for group
-        ('w', 0o0020),  // Write permission for group
-        ('x', 0o0010),  // Execute permission for group
-        ('r', 0o0004),  // Read permission for others
-        ('w', 0o0002),  // Write permission for others
-        ('x', 0o0001),  // Execute permission for others
-    ];
+fn convert_to_permission(metadata: &fs::Metadata, path: &Path) -> String {
     // Check if extended attributes are present for the given path
     let extended_attributes_present = match list(path) {
         Ok(attributes) => attributes.count() != 0, // Extended attributes found
         Err(_) => false, // Error occurred or no extended attributes found
     };
-    let acl_indicator =  has_extended_acl(path).unwrap();
+    let _acl_indicator = has_extended_acl(path).unwrap_or(false);
 
     // Determine the extended attributes indicator based on the result
     let extended_attributes_indicator: &str = if extended_attributes_present { "@" } else { " " };
     // Determine the directory indicator based on the mode bits
-    let directory_indicator = if metadata.is_dir() { 'd' } else { '-' };
+    let directory_indicator = if metadata.is_dir() {
+        'd'
+    } else if metadata.is_symlink() {
+        'l'
+    } else {
+        '-'
+    };
 
     // Iterate over the mapping, applying it to construct the permissions string
     let permissions: String = PERMISSIONS_MAPPING
@@ -176,23 +305,25 @@ fn calculate_total_size(entries: &[fs::DirEntry]) -> Result<u64, String> {
     Ok(total_size)
 }
 
-fn print_indicator(entry: &fs::DirEntry) {
-    if let Ok(metadata) = entry.metadata() {
-        if metadata.is_dir() {
-            print!("/");
-        } else if metadata.mode() & 0o111 != 0 {
-            print!("*");
-        } else if metadata.mode() & 0o1000 != 0 {
-            print!("|"); // Symbol for FIFOs or pipes
-        } else if metadata.mode() & 0o2000 != 0 {
-            print!("="); // Symbol for sockets
-        } else if metadata.mode() & 0o20000 != 0 {
-            print!("%"); // Symbol for whiteouts
-        } else {
-            // Print a space for other file types
-            print!(" ");
-        }
-    }
+fn print_indicator(metadata: &fs::Metadata, out: &mut dyn Write) -> Result<(), String> {
+    let indicator = if metadata.is_dir() {
+        "/"
+    } else if metadata.is_symlink() {
+        "@"
+    } else if metadata.mode() & 0o111 != 0 {
+        "*"
+    } else if metadata.mode() & 0o1000 != 0 {
+        "|" // Symbol for FIFOs or pipes
+    } else if metadata.mode() & 0o2000 != 0 {
+        "=" // Symbol for sockets
+    } else if metadata.mode() & 0o20000 != 0 {
+        "%" // Symbol for whiteouts
+    } else {
+        // Print a space for other file types
+        " "
+    };
+    write!(out, "{}", indicator).map_err(write_error)?;
+    Ok(())
 }
 
 fn is_hidden(entry: &fs::DirEntry) -> bool {
@@ -204,49 +335,23 @@ fn is_hidden(entry: &fs::DirEntry) -> bool {
 }
 
 /// Adds the entries for the current directory (`.`) and parent directory (`..`) to the list of entries.
-fn add_current_and_parent_name_to_entries(details:bool, list_indicator: bool) { 
-
-    if !details{
-        // only check if the current directory and parent directory are exist and then print . and ..
-        let current_dir = Path::new(".");
-        let parent_dir = Path::new("..");
-        if let Ok(_current_dir_entry) = fs::metadata(current_dir) { 
-            if list_indicator {
-                println!("./");
-            }else{
-                println!(".");
-            
-            }
-        }
-        if let Ok(_parent_dir_entry) = fs::metadata(parent_dir) {
-            if list_indicator {
-                println!("../");
-            }else{
-                println!("..");
-            
-            }
-        }
-        return; 
-    }
-    // if the -l flag is set, we need to print the current directory and parent directory details as well
-    // The metadata() method returns a Result<Metadata, Error> that contains the metadata for the file.
-    // If the metadata cannot be retrieved, an error is returned.
-    if let Ok(current_dir_name) = env::current_dir() {
-        let current_dir = Path::new(&current_dir_name);
-        print_entry_details(&current_dir).unwrap();
-        print!(" .");
-        if list_indicator {
-            print!("/");
-        }
-        println!();
-        if let Ok(parent_dir_name) = env::current_dir() {
-            let parent_dir: &Path = Path::new(&parent_dir_name).parent().unwrap();
-            print_entry_details(&parent_dir).unwrap();
-            print!(" ..");
-            if list_indicator {
-                print!("/");
-            }
-            println!();
+fn add_current_and_parent_name_to_entries(
+    dir: &Path,
+    details: bool,
+    list_indicator: bool,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    let indicator = if list_indicator { "/" } else { "" };
+    // `.` and `..` of the directory being listed, not of the current one
+    let parent = dir.join("..");
+    for (path, name) in [(dir, "."), (parent.as_path(), "..")] {
+        let Ok(metadata) = fs::metadata(path) else {
+            continue;
+        };
+        if details {
+            print_entry_details(path, &metadata, out)?;
         }
+        writeln!(out, " {}{}", name, indicator).map_err(write_error)?;
     }
+    Ok(())
 }
\ No newline at end of file
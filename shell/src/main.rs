@@ -1,9 +1,6 @@
-use std::env;
-use std::io::{ self, Write };
-use std::process::Command;
+use std::io::{ self, IsTerminal };
+use std::time::{ Duration, Instant };
 
-use crate::ls::handlels;
-use crate::rm::handle_rm;
 pub mod echo;
 pub mod cd;
 pub mod ls;
@@ -14,113 +11,267 @@ pub mod rm;
 pub mod mv;
 pub mod mkdir;
 pub mod exit;
+pub mod lexer;
+pub mod parser;
+pub mod stream;
+pub mod builtins;
+pub mod exec;
+pub mod redirect;
+pub mod set;
+pub mod state;
+pub mod terminal;
+pub mod expand;
+pub mod vars;
+pub mod pattern;
+pub mod export;
+pub mod unset;
+pub mod env;
+pub mod glob;
+pub mod shopt;
+pub mod jobs;
+pub mod signals;
+pub mod input;
+pub mod shift;
+pub mod loops;
+pub mod functions;
+pub mod arith;
+pub mod test;
+pub mod history;
+pub mod editor;
+pub mod complete;
+pub mod prompt;
+pub mod source;
+pub mod alias;
+pub mod getopt;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut invocation = match invocation(&args) {
+        Ok(invocation) => invocation,
+        Err((message, status)) => {
+            eprintln!("shell: {}", message);
+            exit::exit(status);
+        }
+    };
+
+    let interactive = invocation.input.is_interactive();
+    let mut shell = state::Shell::new(interactive);
+    shell.name = invocation.name;
+    shell.positional = invocation.positional;
+    if invocation.login {
+        source::startup(&mut shell, source::PROFILE);
+    }
+    if interactive {
+        if !invocation.norc {
+            source::startup(&mut shell, source::RC);
+        }
+        // After the rc file, which may set HISTFILE or HISTSIZE
+        shell.history.load(&shell.vars);
+    }
+    run(&mut shell, &mut invocation.input);
+    // Like other shells, exit with the status of the last command
+    exit::exit(shell.last_status);
+}
+
+/// What the shell was asked to do.
+struct Invocation {
+    input: input::Input,
+    /// `$0`
+    name: String,
+    positional: Vec<String>,
+    /// Read the login profile first.
+    login: bool,
+    /// Skip the rc file.
+    norc: bool,
+}
+
+/// Works out what to run from the command line:
+///
+///   shell                          commands from stdin, interactive at a terminal
+///   shell script.sh [args...]      commands from a file
+///   shell -c 'commands' [name [args...]]
+///
+/// Any of these may start with `-l` (or `--login`) to run as a login shell,
+/// as may a shell whose name starts with `-`, and `--norc`.
+/// On error returns a message and the status to exit with.
+fn invocation(args: &[String]) -> Result<Invocation, (String, i32)> {
+    let shell_name = args.first().cloned().unwrap_or_else(|| "shell".to_string());
+    let mut login = shell_name.starts_with('-');
+    let mut norc = false;
+    let mut rest = args.iter().skip(1).peekable();
+    let is_startup_option = |arg: &&String| ["-l", "--login", "--norc"].contains(&arg.as_str());
+    while let Some(option) = rest.next_if(is_startup_option) {
+        match option.as_str() {
+            "--norc" => norc = true,
+            _ => login = true,
+        }
+    }
+    let invocation = |input, name, positional| Invocation {
+        input,
+        name,
+        positional,
+        login,
+        norc,
+    };
+    match rest.next().map(String::as_str) {
+        Some("-c") => {
+            let Some(command) = rest.next() else {
+                return Err(("-c: option requires an argument".to_string(), 2));
+            };
+            let name = rest.next().cloned().unwrap_or(shell_name);
+            let input = input::Input::string(&name, command);
+            Ok(invocation(input, name, rest.cloned().collect()))
+        }
+        Some("--") | None => {
+            let input = if io::stdin().is_terminal() {
+                input::Input::Terminal(editor::Editor::default())
+            } else {
+                input::Input::stdin(&shell_name)
+            };
+            Ok(invocation(input, shell_name, rest.cloned().collect()))
+        }
+        Some(option) if option.starts_with('-') && option != "-" => {
+            Err((format!("{}: invalid option", option), 2))
+        }
+        Some(path) => match input::Input::file(path) {
+            Ok(input) => Ok(invocation(input, path.to_string(), rest.cloned().collect())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err((format!("{}: No such file or directory", path), 127))
+            }
+            Err(e) => Err((format!("{}: {}", path, e), 126)),
+        },
+    }
+}
+
+/// Reads and runs commands from `input` until it runs out. A command may
+/// span several lines, as in an `if` or a loop; the shell keeps reading,
+/// with a `> ` prompt at the terminal, until it is complete. A script stops
+/// at its first syntax error, like in other shells, and a sourced one also
+/// at `return`.
+fn run(shell: &mut state::Shell, input: &mut input::Input) {
+    let interactive = input.is_interactive();
+    // Lines read so far of a command that is not complete yet
+    let mut pending = String::new();
+    // How long the last command took, for the prompt
+    let mut duration = Duration::ZERO;
     loop {
-       let current_dir = env::current_dir().unwrap();
-        let current_dir_str = current_dir
-            .to_str()
-            .unwrap()
-            .replace(env::var("HOME").unwrap().as_str(), "~");
+        if pending.is_empty() {
+            // Report background jobs that finished or stopped since the last prompt
+            jobs::notify(shell, &mut stream::Io::standard());
+        }
 
-        print!("{} $ ", current_dir_str);
-        io::stdout().flush().unwrap();
+        let (prompt, right_prompt) = if interactive {
+            prompt::prompts(shell, !pending.is_empty(), duration)
+        } else {
+            Default::default()
+        };
 
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
+        let mut line = String::new();
+        let result = match input {
+            input::Input::Terminal(editor) => {
+                let history = shell.history.entries();
+                let complete = |text: &str| complete::complete(shell, text);
+                let vi = shell.options.vi;
+                editor.read_line(&prompt, &right_prompt, history, &complete, vi, &mut line)
+            }
+            _ => input.read_line(&mut line),
+        };
+        match result {
+            // Ctrl+C at the prompt throws the command away and starts over
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                pending.clear();
+                println!();
+                continue;
+            }
             Ok(0) => {
-                println!("Ctrl+D (EOF) detected");
+                if !pending.is_empty() {
+                    // Input ended in the middle of a command
+                    if let Err(e) = parser::parse_input(&pending) {
+                        report_syntax_error(input, &e.to_string());
+                    }
+                    shell.last_status = 2;
+                } else if interactive {
+                    println!("Ctrl+D (EOF) detected");
+                }
                 break; // Exit the program when Ctrl+D is detected
             }
             Ok(_) => {
-                if input.trim().is_empty() {
-                    continue; // Skip the rest of the loop if the input is empty
-                }
-
-                let mut parts = input.trim().split_whitespace();
-                let command = parts.next().unwrap();
-                let args: Vec<&str> = parts.collect();
-
-                match command {
-                    "exit" => {
-                        exit::exit(0);
-                    }
-                    "cd" => {
-                            let _ = cd::handle_cd(args);
-                    }
-                    "ls" => {
-                        let _ = handlels(args);
-                    }
-                    "pwd" => {
-                        pwd::pwd();
-                    }
-                    "cat" => {
-                        if let Some(filename) = args.get(0) {
-                            cat::cat(filename);
-                        } else {
-                            eprintln!("No filename provided for cat command");
-                        }
-                    }
-                    "cp" => {
-                        if args.len() >= 2 {
-                            let _ = cp::cp(args[0], args[1]);
-                        } else {
-                            eprintln!("Usage: cp <source> <destination>");
+                if interactive {
+                    match shell.history.expand(&line) {
+                        // Show the command as it will run
+                        Ok(Some(expanded)) => {
+                            print!("{}", expanded);
+                            line = expanded;
                         }
-                    }
-                    "rm" => {let _ = handle_rm(args);}
-                    
-                    "mkdir" => {
-                        if let Some(dir) = args.get(0) {
-                            let _ = mkdir::mkdir(dir, false);
-                        } else {
-                            eprintln!("No directory name provided for mkdir command");
+                        Ok(None) => {}
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            pending.clear();
+                            continue;
                         }
                     }
-                    "mv" => {
-                        if args.len() >= 2 {
-                            let mut command = Command::new("mv");
-                            command.args(&args);
+                }
+                pending.push_str(&line);
+                if pending.trim().is_empty() {
+                    pending.clear();
+                    continue; // Skip the rest of the loop if the input is empty
+                }
 
-                            match command.status() {
-                                Ok(status) => {
-                                    if !status.success() {
-                                        eprintln!("Error: mv command failed with exit status {}", status);
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!("Error: {}", e);
-                                }
-                            }
-                        } else {
-                            eprintln!("Usage: mv <source> <destination>");
-                        }
+                let parsed = parser::parse_input_with_aliases(&pending, &shell.aliases);
+                // A complete command goes into the history, even one with an error
+                if interactive && !matches!(parsed, Err(parser::ParseError::Incomplete(_))) {
+                    shell.history.add(&pending, &shell.vars);
+                }
+                let list = match parsed {
+                    Ok(Some(list)) => list,
+                    Ok(None) => {
+                        // The lines held nothing but comments
+                        pending.clear();
+                        continue;
                     }
-
-                    "echo" => {
-                        let result = echo::echo(&args);
-                        if let Err(e) = result {
-                            eprintln!("Error: {}", e);
+                    Err(parser::ParseError::Incomplete(_)) => continue,
+                    Err(parser::ParseError::Syntax(e)) => {
+                        pending.clear();
+                        report_syntax_error(input, &e);
+                        // Bash reports syntax errors with status 2
+                        shell.last_status = 2;
+                        if interactive {
+                            continue;
                         }
+                        break;
                     }
-                    _ => {
-                        let output = Command::new(command).args(&args).output();
+                };
+                pending.clear();
 
-                        match output {
-                            Ok(output) => {
-                                println!("{}", String::from_utf8_lossy(&output.stdout));
-                            }
-                            Err(e) => {
-                                // Handle errors if needed
-                                eprintln!("Error: {}", e);
-                            }
-                        }
-                    }
+                signals::take_interrupt();
+                let started = Instant::now();
+                let location = std::mem::replace(&mut shell.location, input.location());
+                exec::run_list(shell, &list, &stream::Io::standard());
+                shell.location = location;
+                duration = started.elapsed();
+                // Ctrl+C cut the command short; the next prompt starts on a new line
+                if signals::take_interrupt() {
+                    eprintln!();
+                    shell.last_status = 130;
+                }
+                if shell.flow == Some(state::Flow::Return) {
+                    break;
                 }
             }
             Err(e) => {
                 eprintln!("Error: {}", e);
+                if !interactive {
+                    break;
+                }
             }
         }
     }
 }
+
+fn report_syntax_error(input: &input::Input, error: &str) {
+    if input.is_interactive() {
+        eprintln!("Error: {}", error);
+    } else {
+        eprintln!("{}{}", input.location(), error);
+    }
+}
@@ -1,6 +1,14 @@
 use std::fs;
 use std::io;
 
+use crate::getopt::{Args, Opt, Spec};
+
+pub const SPEC: Spec = Spec::new(
+    "Create directories.",
+    "dir ...",
+    &[Opt::flag('p', "parents", "create missing parents, and accept existing directories")],
+);
+
 pub fn mkdir(directory: &str, create_parents: bool) -> io::Result<()> {
     if create_parents {
         fs::create_dir_all(directory)?;
@@ -10,17 +18,13 @@ pub fn mkdir(directory: &str, create_parents: bool) -> io::Result<()> {
     Ok(())
 }
 
-pub fn mkdir_with_flag(args: &[&str]) -> io::Result<()> {
-    let mut create_parents = false;
-    let mut directory = "";
-
-    for (index, arg) in args.iter().enumerate() {
-        if *arg == "-p" {
-            create_parents = true;
-        } else {
-            directory = args[index];
-        }
+pub fn mkdir_with_flag(args: &Args) -> Result<(), String> {
+    if args.operands.is_empty() {
+        return Err("mkdir: missing operand".to_string());
     }
-
-    mkdir(directory, create_parents)
+    let create_parents = args.has('p');
+    for directory in &args.operands {
+        mkdir(directory, create_parents).map_err(|e| format!("mkdir: {}: {}", directory, e))?;
+    }
+    Ok(())
 }
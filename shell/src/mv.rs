@@ -1,17 +1,38 @@
 use std::fs;
+use std::io;
 use std::path::Path;
 
-pub fn handle_mv(args: Vec<&str>) -> Result<(), String> {
-    // Check if the number of arguments is valid
-    if args.len() != 2 {
-        return Err("Usage: mv <source> <destination>".to_string());
-    }
+use crate::cp;
+use crate::getopt::{self, Spec};
+
+pub const SPEC: Spec = Spec::new(
+    "Move or rename a file or directory, or move several into a directory.",
+    "source ... destination",
+    &[],
+);
 
-    let source = args[0];
-    let destination = args[1];
+pub fn handle_mv(operands: &[&str]) -> Result<(), String> {
+    let Some((destination, sources)) = operands.split_last() else {
+        return Err(format!("mv: missing operand\n{}", getopt::usage("mv", &SPEC)));
+    };
+    if sources.is_empty() {
+        let message = format!("missing destination file operand after '{}'", destination);
+        return Err(format!("mv: {}\n{}", message, getopt::usage("mv", &SPEC)));
+    }
+    if sources.len() > 1 && !Path::new(destination).is_dir() {
+        return Err(format!("mv: target '{}' is not a directory", destination));
+    }
 
-    // Call the move_file function
-    move_file(source, destination)
+    // Move what we can and report the first failure at the end
+    let mut result = Ok(());
+    for source in sources {
+        if let Err(e) = move_file(source, destination) {
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+    result
 }
 
 fn move_file(source: &str, destination: &str) -> Result<(), String> {
@@ -19,21 +40,33 @@ fn move_file(source: &str, destination: &str) -> Result<(), String> {
     let destination_path = Path::new(destination);
 
     // Check if the source path exists
-    if !source_path.exists() {
+    if fs::symlink_metadata(source_path).is_err() {
         return Err(format!("mv: {}: No such file or directory", source));
     }
 
     // If the destination is an existing directory, append the filename to it
-    let destination_path = if destination_path.is_dir() {
-        destination_path.join(source_path.file_name().unwrap())
-    } else {
-        destination_path.to_path_buf()
+    let destination_path = match source_path.file_name() {
+        Some(name) if destination_path.is_dir() => destination_path.join(name),
+        _ => destination_path.to_path_buf(),
     };
 
     // Perform the move operation
-    if let Err(err) = fs::rename(source_path, &destination_path) {
-        return Err(format!("mv: {}: {}", source, err));
+    match fs::rename(source_path, &destination_path) {
+        Ok(()) => Ok(()),
+        // Another file system: copy, then remove the original
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            copy_and_remove(source_path, &destination_path)
+                .map_err(|err| format!("mv: {}: {}", source, err))
+        }
+        Err(err) => Err(format!("mv: {}: {}", source, err)),
     }
+}
 
-    Ok(())
-}
\ No newline at end of file
+fn copy_and_remove(source: &Path, destination: &Path) -> io::Result<()> {
+    cp::cp(&source.to_string_lossy(), &destination.to_string_lossy())?;
+    if source.is_dir() {
+        fs::remove_dir_all(source)
+    } else {
+        fs::remove_file(source)
+    }
+}
@@ -0,0 +1,1127 @@
+// parser.rs
+//
+// Groups the lexer's tokens into the commands the executor runs. The grammar
+// follows POSIX, from the top down:
+//
+//   list      := linebreak [ and_or ( separator and_or )* [ separator ] ]
+//   separator := ( ';' | '&' | newline ) linebreak
+//   and_or    := pipeline ( ( '&&' | '||' ) linebreak pipeline )*
+//   pipeline  := [ '!' ] command ( '|' linebreak command )*
+//   command   := simple | compound redirect* | name '(' ')' linebreak compound
+//   simple    := assignment* ( word | redirect )+
+//   compound  := 'if' list 'then' list ( 'elif' list 'then' list )* [ 'else' list ] 'fi'
+//              | ( 'while' | 'until' ) list 'do' list 'done'
+//              | 'for' name [ linebreak 'in' word* ] separator 'do' list 'done'
+//              | 'case' word linebreak 'in' linebreak case_item* 'esac'
+//              | '{' list '}'
+//              | '(' list ')'
+//              | '((' expression '))'
+//              | '[[' cond_or ']]'
+//   cond_or   := cond_and ( '||' linebreak cond_and )*
+//   cond_and  := cond_not ( '&&' linebreak cond_not )*
+//   cond_not  := '!' cond_not | '(' cond_or ')' | word [ binary_op word ] | unary_op word
+//   case_item := [ '(' ] word ( '|' word )* ')' list [ ';;' linebreak ]
+//
+// Reserved words such as `if` and `done` are ordinary words to the lexer and
+// only recognised here, when they appear unquoted where a command may start.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::sync::Arc;
+use std::vec::IntoIter;
+
+use crate::alias;
+use crate::lexer::{self, HereDoc, Operator, Token, Word, WordPart};
+use crate::test;
+use crate::vars;
+
+/// A redirection such as `2>> log` or `2>&1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    /// The descriptor written before the operator, if any.
+    pub fd: Option<u32>,
+    pub op: Operator,
+    /// The file or descriptor, or for a here-document its delimiter.
+    pub target: Word,
+    /// The body of a `<<` or `<<-` here-document.
+    pub here_doc: Option<HereDoc>,
+}
+
+/// A `NAME=value` word written before the command name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+/// A command name followed by its arguments and redirections.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SimpleCommand {
+    /// Assignments that apply to this command only, or to the shell when
+    /// there is no command name.
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// One stage of a pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// A compound command with the redirections written after it, which
+    /// apply to every command inside.
+    Compound(CompoundCommand, Vec<Redirect>),
+    /// `name() body`, which defines a function. The body is shared with the
+    /// shell's function table once defined.
+    Function(String, Arc<Command>),
+}
+
+/// Commands that contain other commands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundCommand {
+    /// `if`, with a condition and body for the `if` and every `elif`.
+    If {
+        branches: Vec<(CommandList, CommandList)>,
+        otherwise: Option<CommandList>,
+    },
+    /// `while`, or `until` when `until` is set, which loops while the
+    /// condition fails instead.
+    Loop {
+        until: bool,
+        condition: CommandList,
+        body: CommandList,
+    },
+    /// `for name in words`; without `in` it loops over the positional parameters.
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: CommandList,
+    },
+    Case { word: Word, items: Vec<CaseItem> },
+    /// `{ list; }`, which runs the list in the current shell.
+    Group(CommandList),
+    /// `( list )`, which runs the list in a copy of the shell so that `cd`,
+    /// assignments and `exit` inside do not affect the shell itself.
+    Subshell(CommandList),
+    /// `(( expr ))`, which succeeds when the expression is non-zero.
+    Arithmetic(String),
+    /// `[[ expr ]]`, a test whose words are not split or globbed.
+    Conditional(Conditional),
+}
+
+/// The expression inside `[[ ]]`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conditional {
+    Not(Box<Conditional>),
+    And(Box<Conditional>, Box<Conditional>),
+    Or(Box<Conditional>, Box<Conditional>),
+    /// A test on one operand, such as `-f file` or `-z string`.
+    Unary(String, Word),
+    /// A comparison such as `$a == pattern`, `$a =~ regex` or `$n -lt 3`.
+    Binary(Word, String, Word),
+    /// A lone word, true when it expands to something non-empty.
+    Word(Word),
+}
+
+/// A `pattern | pattern) commands ;;` branch of a `case`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: CommandList,
+}
+
+/// Commands joined by `|`, each one's stdout feeding the next one's stdin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    /// Set by a leading `!`, which inverts the pipeline's status.
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+/// How a pipeline in an `AndOr` chain depends on the one before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    /// `&&`: run only if the previous pipeline succeeded.
+    And,
+    /// `||`: run only if the previous pipeline failed.
+    Or,
+}
+
+/// Pipelines joined by `&&` and `||`, evaluated left to right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Set when the chain ends with `&` and runs as a background job.
+    pub background: bool,
+}
+
+/// `AndOr` chains separated by `;`, `&` or newlines, run one after another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandList {
+    pub items: Vec<AndOr>,
+}
+
+/// Why input could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The input stops in the middle of a command, so more lines may complete it.
+    Incomplete(String),
+    Syntax(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Incomplete(e) | ParseError::Syntax(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Words that open or close a compound command where a command may start.
+const RESERVED: [&str; 17] = [
+    "if", "then", "elif", "else", "fi", "while", "until", "for", "do", "done", "case", "esac",
+    "{", "}", "!", "[[", "]]",
+];
+
+/// Reserved words that start a compound command.
+const COMPOUND: [&str; 7] = ["if", "while", "until", "for", "case", "{", "[["];
+
+/// Reserved words that end the list before them.
+const CLOSING: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+/// Tokenizes and parses `input`. Returns `None` when it holds no command.
+pub fn parse_input(input: &str) -> Result<Option<CommandList>, ParseError> {
+    let tokens = lexer::tokenize(input).map_err(ParseError::Incomplete)?;
+    parse(tokens)
+}
+
+/// Like `parse_input`, expanding `aliases` where a command name is expected.
+pub fn parse_input_with_aliases(
+    input: &str,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Option<CommandList>, ParseError> {
+    let tokens = lexer::tokenize(input).map_err(ParseError::Incomplete)?;
+    parse(alias::expand(tokens, aliases))
+}
+
+/// Parses a full command line. Returns `None` when the line holds no command.
+pub fn parse(tokens: Vec<Token>) -> Result<Option<CommandList>, ParseError> {
+    let mut parser = Parser {
+        tokens: tokens.into_iter().peekable(),
+    };
+    let list = parser.list()?;
+    if let Some(token) = parser.tokens.next() {
+        return Err(unexpected(Some(&token)));
+    }
+    if list.items.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(list))
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn list(&mut self) -> Result<CommandList, ParseError> {
+        let mut items = Vec::new();
+        loop {
+            self.linebreak();
+            if self.at_list_end() {
+                break;
+            }
+            let mut item = self.and_or()?;
+            let separated = if self.eat(Operator::Amp) {
+                item.background = true;
+                true
+            } else {
+                self.eat(Operator::Semi) || self.eat(Operator::Newline)
+            };
+            items.push(item);
+            if !separated {
+                break;
+            }
+        }
+        Ok(CommandList { items })
+    }
+
+    /// A list inside a compound command, which must hold at least one command.
+    fn compound_list(&mut self) -> Result<CommandList, ParseError> {
+        let list = self.list()?;
+        if list.items.is_empty() {
+            return Err(unexpected(self.tokens.peek()));
+        }
+        Ok(list)
+    }
+
+    /// Whether the next token ends a list: the end of input, a closing
+    /// reserved word, `)` or `;;`.
+    fn at_list_end(&mut self) -> bool {
+        match self.tokens.peek() {
+            None => true,
+            Some(Token::Operator(op)) => matches!(op, Operator::RParen | Operator::DSemi),
+            Some(Token::Word(word)) => CLOSING.iter().any(|keyword| is_keyword(word, keyword)),
+            Some(Token::IoNumber(_) | Token::Arithmetic(_) | Token::HereDoc(_)) => false,
+        }
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = if self.eat(Operator::AndIf) {
+                Connector::And
+            } else if self.eat(Operator::OrIf) {
+                Connector::Or
+            } else {
+                break;
+            };
+            self.linebreak();
+            rest.push((connector, self.pipeline()?));
+        }
+        Ok(AndOr {
+            first,
+            rest,
+            background: false,
+        })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self.eat_keyword("!");
+        let mut commands = vec![self.command()?];
+        while self.eat(Operator::Pipe) {
+            self.linebreak();
+            commands.push(self.command()?);
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        let compound = if self.eat_keyword("if") {
+            self.if_clause()?
+        } else if self.eat_keyword("while") {
+            self.loop_clause(false)?
+        } else if self.eat_keyword("until") {
+            self.loop_clause(true)?
+        } else if self.eat_keyword("for") {
+            self.for_clause()?
+        } else if self.eat_keyword("case") {
+            self.case_clause()?
+        } else if self.eat_keyword("{") {
+            let body = self.compound_list()?;
+            self.expect_keyword("}")?;
+            CompoundCommand::Group(body)
+        } else if self.eat(Operator::LParen) {
+            let body = self.compound_list()?;
+            if !self.eat(Operator::RParen) {
+                return Err(unexpected(self.tokens.peek()));
+            }
+            CompoundCommand::Subshell(body)
+        } else if self.eat_keyword("[[") {
+            let expr = self.conditional_or()?;
+            self.expect_keyword("]]")?;
+            CompoundCommand::Conditional(expr)
+        } else if let Some(Token::Arithmetic(expr)) = self.tokens.peek() {
+            let expr = expr.clone();
+            self.tokens.next();
+            CompoundCommand::Arithmetic(expr)
+        } else {
+            // Any other reserved word cannot start a command
+            let mut first = None;
+            if let Some(Token::Word(word)) = self.tokens.peek() {
+                if RESERVED.iter().any(|keyword| is_keyword(word, keyword)) {
+                    return Err(unexpected(self.tokens.peek()));
+                }
+                if let Some(Token::Word(word)) = self.tokens.next() {
+                    if self.eat(Operator::LParen) {
+                        return self.function_definition(word);
+                    }
+                    first = Some(word);
+                }
+            }
+            return Ok(Command::Simple(self.simple_command(first)?));
+        };
+
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    fn if_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let condition = self.compound_list()?;
+            self.expect_keyword("then")?;
+            let body = self.compound_list()?;
+            branches.push((condition, body));
+            if self.eat_keyword("elif") {
+                continue;
+            }
+            if self.eat_keyword("else") {
+                otherwise = Some(self.compound_list()?);
+            }
+            self.expect_keyword("fi")?;
+            return Ok(CompoundCommand::If {
+                branches,
+                otherwise,
+            });
+        }
+    }
+
+    fn loop_clause(&mut self, until: bool) -> Result<CompoundCommand, ParseError> {
+        let condition = self.compound_list()?;
+        let body = self.do_group()?;
+        Ok(CompoundCommand::Loop {
+            until,
+            condition,
+            body,
+        })
+    }
+
+    fn for_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        let name = match self.tokens.next() {
+            Some(Token::Word(word)) if vars::is_valid_name(&word.text()) => word.text(),
+            token => return Err(unexpected(token.as_ref())),
+        };
+        self.linebreak();
+        let mut words = None;
+        if self.eat_keyword("in") {
+            let mut list = Vec::new();
+            while let Some(Token::Word(_)) = self.tokens.peek() {
+                if let Some(Token::Word(word)) = self.tokens.next() {
+                    list.push(word);
+                }
+            }
+            words = Some(list);
+            if !self.eat(Operator::Semi) && !self.eat(Operator::Newline) {
+                return Err(unexpected(self.tokens.peek()));
+            }
+        } else {
+            self.eat(Operator::Semi);
+        }
+        self.linebreak();
+        let body = self.do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    /// `do list done`
+    fn do_group(&mut self) -> Result<CommandList, ParseError> {
+        self.expect_keyword("do")?;
+        let body = self.compound_list()?;
+        self.expect_keyword("done")?;
+        Ok(body)
+    }
+
+    fn case_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        let word = match self.tokens.next() {
+            Some(Token::Word(word)) => word,
+            token => return Err(unexpected(token.as_ref())),
+        };
+        self.linebreak();
+        self.expect_keyword("in")?;
+        self.linebreak();
+
+        let mut items = Vec::new();
+        while !self.eat_keyword("esac") {
+            self.eat(Operator::LParen);
+            let mut patterns = Vec::new();
+            loop {
+                match self.tokens.next() {
+                    Some(Token::Word(word)) => patterns.push(word),
+                    token => return Err(unexpected(token.as_ref())),
+                }
+                if !self.eat(Operator::Pipe) {
+                    break;
+                }
+            }
+            if !self.eat(Operator::RParen) {
+                return Err(unexpected(self.tokens.peek()));
+            }
+            // A branch may be empty, and the last one needs no `;;`
+            let body = self.list()?;
+            items.push(CaseItem { patterns, body });
+            if self.eat(Operator::DSemi) {
+                self.linebreak();
+            } else {
+                self.expect_keyword("esac")?;
+                break;
+            }
+        }
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    fn conditional_or(&mut self) -> Result<Conditional, ParseError> {
+        let mut expr = self.conditional_and()?;
+        while self.eat(Operator::OrIf) {
+            self.linebreak();
+            expr = Conditional::Or(Box::new(expr), Box::new(self.conditional_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn conditional_and(&mut self) -> Result<Conditional, ParseError> {
+        let mut expr = self.conditional_not()?;
+        while self.eat(Operator::AndIf) {
+            self.linebreak();
+            expr = Conditional::And(Box::new(expr), Box::new(self.conditional_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn conditional_not(&mut self) -> Result<Conditional, ParseError> {
+        if self.eat_keyword("!") {
+            return Ok(Conditional::Not(Box::new(self.conditional_not()?)));
+        }
+        if self.eat(Operator::LParen) {
+            let expr = self.conditional_or()?;
+            if !self.eat(Operator::RParen) {
+                return Err(unexpected(self.tokens.peek()));
+            }
+            return Ok(expr);
+        }
+        let word = match self.tokens.next() {
+            Some(Token::Word(word)) if !is_keyword(&word, "]]") => word,
+            token => return Err(unexpected(token.as_ref())),
+        };
+
+        // `<` and `>` arrive as redirection operators
+        let op = match self.tokens.peek() {
+            Some(Token::Word(op)) if test::is_binary(&op.text()) => Some(op.text()),
+            Some(Token::Operator(op @ (Operator::Less | Operator::Great))) => {
+                Some(op.as_str().to_string())
+            }
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.tokens.next();
+            return match self.tokens.next() {
+                Some(Token::Word(right)) if !is_keyword(&right, "]]") => {
+                    Ok(Conditional::Binary(word, op, right))
+                }
+                token => Err(unexpected(token.as_ref())),
+            };
+        }
+        let text = word.text();
+        if is_keyword(&word, &text) && test::is_unary(&text) {
+            if let Some(Token::Word(operand)) = self.tokens.peek() {
+                if !is_keyword(operand, "]]") {
+                    let operand = operand.clone();
+                    self.tokens.next();
+                    return Ok(Conditional::Unary(text, operand));
+                }
+            }
+        }
+        Ok(Conditional::Word(word))
+    }
+
+    /// `name() compound-command`, after the name and the `(`.
+    fn function_definition(&mut self, name: Word) -> Result<Command, ParseError> {
+        if !self.eat(Operator::RParen) {
+            return Err(unexpected(self.tokens.peek()));
+        }
+        let text = name.text();
+        if !is_keyword(&name, &text) || !vars::is_valid_name(&text) {
+            return Err(ParseError::Syntax(format!("`{}': not a valid identifier", text)));
+        }
+        self.linebreak();
+        // The body must be a compound command such as `{ ...; }`
+        let starts_compound = match self.tokens.peek() {
+            Some(Token::Word(word)) => COMPOUND
+                .iter()
+                .any(|keyword| is_keyword(word, keyword)),
+            Some(Token::Arithmetic(_) | Token::Operator(Operator::LParen)) => true,
+            _ => false,
+        };
+        if !starts_compound {
+            return Err(unexpected(self.tokens.peek()));
+        }
+        let body = self.command()?;
+        Ok(Command::Function(text, Arc::new(body)))
+    }
+
+    /// A simple command, starting with `first` if the caller already took
+    /// its first word.
+    fn simple_command(&mut self, first: Option<Word>) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand::default();
+        let mut next_word = first;
+        loop {
+            if next_word.is_none() {
+                if let Some(redirect) = self.redirect()? {
+                    command.redirects.push(redirect);
+                    continue;
+                }
+                if let Some(Token::Word(_)) = self.tokens.peek() {
+                    if let Some(Token::Word(word)) = self.tokens.next() {
+                        next_word = Some(word);
+                    }
+                }
+            }
+            let Some(word) = next_word.take() else {
+                break;
+            };
+            // Assignments are only recognised before the command name
+            match assignment(&word) {
+                Some(assignment) if command.words.is_empty() => {
+                    command.assignments.push(assignment)
+                }
+                _ => command.words.push(word),
+            }
+        }
+
+        if command.words.is_empty()
+            && command.redirects.is_empty()
+            && command.assignments.is_empty()
+        {
+            return Err(unexpected(self.tokens.peek()));
+        }
+        Ok(command)
+    }
+
+    /// Parses a redirection if one comes next.
+    fn redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        match self.tokens.peek() {
+            Some(Token::IoNumber(fd)) => {
+                let fd = *fd;
+                self.tokens.next();
+                // The lexer only emits a descriptor number right before a redirection
+                match self.tokens.next() {
+                    Some(Token::Operator(op)) => self.redirect_rest(Some(fd), op).map(Some),
+                    token => Err(unexpected(token.as_ref())),
+                }
+            }
+            Some(Token::Operator(op)) if op.is_redirect() => {
+                let op = *op;
+                self.tokens.next();
+                self.redirect_rest(None, op).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The rest of a redirection after its operator.
+    fn redirect_rest(&mut self, fd: Option<u32>, op: Operator) -> Result<Redirect, ParseError> {
+        let target = self.redirect_target()?;
+        // The lexer puts the body of a here-document right after its delimiter
+        let here_doc = match self.tokens.peek() {
+            Some(Token::HereDoc(here_doc)) => {
+                let here_doc = here_doc.clone();
+                self.tokens.next();
+                Some(here_doc)
+            }
+            _ => None,
+        };
+        Ok(Redirect {
+            fd,
+            op,
+            target,
+            here_doc,
+        })
+    }
+
+    /// The token after a redirection operator must be the word naming its target.
+    fn redirect_target(&mut self) -> Result<Word, ParseError> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) => Ok(word),
+            token => Err(unexpected(token.as_ref())),
+        }
+    }
+
+    /// Consumes the next token if it is `op`.
+    fn eat(&mut self, op: Operator) -> bool {
+        if self.tokens.peek() == Some(&Token::Operator(op)) {
+            self.tokens.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the next token if it is the reserved word `keyword`.
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.peek() {
+            Some(Token::Word(word)) if is_keyword(word, keyword) => {
+                self.tokens.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(unexpected(self.tokens.peek()))
+        }
+    }
+
+    /// Skips any number of newlines.
+    fn linebreak(&mut self) {
+        while self.eat(Operator::Newline) {}
+    }
+}
+
+/// Whether `word` is `keyword` written without any quoting.
+fn is_keyword(word: &Word, keyword: &str) -> bool {
+    matches!(word.parts.as_slice(), [WordPart::Literal(s)] if s == keyword)
+}
+
+// Commands are written back out as text for the job table
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(fd) = self.fd {
+            write!(f, "{}", fd)?;
+        }
+        // A here-document shows as its delimiter, as the body is on other lines
+        write!(f, "{} {}", self.op.as_str(), self.target)
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut words: Vec<String> = self
+            .assignments
+            .iter()
+            .map(|assignment| format!("{}={}", assignment.name, assignment.value))
+            .collect();
+        words.extend(self.words.iter().map(Word::to_string));
+        words.extend(self.redirects.iter().map(Redirect::to_string));
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Simple(command) => write!(f, "{}", command),
+            Command::Function(name, body) => write!(f, "{} () {}", name, body),
+            Command::Compound(command, redirects) => {
+                write!(f, "{}", command)?;
+                for redirect in redirects {
+                    write!(f, " {}", redirect)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    write!(f, "{} {}; then {}; ", keyword, condition, body)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, "else {}; ", otherwise)?;
+                }
+                write!(f, "fi")
+            }
+            CompoundCommand::Loop {
+                until,
+                condition,
+                body,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(f, "{} {}; do {}; done", keyword, condition, body)
+            }
+            CompoundCommand::For { name, words, body } => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {}", word)?;
+                    }
+                }
+                write!(f, "; do {}; done", body)
+            }
+            CompoundCommand::Case { word, items } => {
+                write!(f, "case {} in ", word)?;
+                for item in items {
+                    let patterns: Vec<String> = item.patterns.iter().map(Word::to_string).collect();
+                    write!(f, "{}) {};; ", patterns.join(" | "), item.body)?;
+                }
+                write!(f, "esac")
+            }
+            CompoundCommand::Group(body) => write!(f, "{{ {}; }}", body),
+            CompoundCommand::Subshell(body) => write!(f, "({})", body),
+            CompoundCommand::Arithmetic(expr) => write!(f, "(({}))", expr),
+            CompoundCommand::Conditional(expr) => write!(f, "[[ {} ]]", expr),
+        }
+    }
+}
+
+impl fmt::Display for Conditional {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conditional::Not(expr) => write!(f, "! {}", expr),
+            Conditional::And(left, right) => write!(f, "{} && {}", left, right),
+            Conditional::Or(left, right) => write!(f, "{} || {}", left, right),
+            Conditional::Unary(op, word) => write!(f, "{} {}", op, word),
+            Conditional::Binary(left, op, right) => write!(f, "{} {} {}", left, op, right),
+            Conditional::Word(word) => write!(f, "{}", word),
+        }
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        let commands: Vec<String> = self.commands.iter().map(Command::to_string).collect();
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            let op = match connector {
+                Connector::And => "&&",
+                Connector::Or => "||",
+            };
+            write!(f, " {} {}", op, pipeline)?;
+        }
+        if self.background {
+            write!(f, " &")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CommandList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                // A background item already ends with its `&`
+                let separator = if self.items[i - 1].background { " " } else { "; " };
+                write!(f, "{}", separator)?;
+            }
+            write!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a word such as `NAME=value` into an assignment. The name must be
+/// unquoted; the value keeps whatever quoting it had.
+fn assignment(word: &Word) -> Option<Assignment> {
+    let Some(WordPart::Literal(first)) = word.parts.first() else {
+        return None;
+    };
+    let (name, value) = first.split_once('=')?;
+    if !vars::is_valid_name(name) {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    if !value.is_empty() {
+        parts.push(WordPart::Literal(value.to_string()));
+    }
+    parts.extend(word.parts[1..].iter().cloned());
+    Some(Assignment {
+        name: name.to_string(),
+        value: Word { parts },
+    })
+}
+
+/// The error for finding `token` where it does not belong. Running out of
+/// tokens means the command is not finished yet.
+fn unexpected(token: Option<&Token>) -> ParseError {
+    let text = match token {
+        Some(Token::Word(word)) => word.text(),
+        Some(Token::Operator(op)) => op.as_str().to_string(),
+        Some(Token::IoNumber(fd)) => fd.to_string(),
+        Some(Token::Arithmetic(expr)) => format!("(({}))", expr),
+        Some(Token::HereDoc(_)) => "newline".to_string(),
+        None => {
+            return ParseError::Incomplete("syntax error: unexpected end of file".to_string())
+        }
+    };
+    ParseError::Syntax(format!("syntax error near unexpected token `{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> CommandList {
+        parse_input(input).unwrap().unwrap()
+    }
+
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(simple) => simple,
+            other => panic!("not a simple command: {:?}", other),
+        }
+    }
+
+    fn texts(words: &[Word]) -> Vec<String> {
+        words.iter().map(Word::text).collect()
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(parse_input("").unwrap(), None);
+        assert_eq!(parse_input("  \n\n # comment\n").unwrap(), None);
+    }
+
+    #[test]
+    fn pipelines() {
+        let list = parse("ls -l | grep 'a b' | wc");
+        let commands = &list.items[0].first.commands;
+        assert_eq!(commands.len(), 3);
+        assert_eq!(texts(&simple(&commands[1]).words), ["grep", "a b"]);
+    }
+
+    #[test]
+    fn redirections() {
+        let list = parse("cmd 'a b' 2>&1 >out");
+        let command = simple(&list.items[0].first.commands[0]);
+        assert_eq!(texts(&command.words), ["cmd", "a b"]);
+        let redirects: Vec<(Option<u32>, Operator, String)> = command
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.op, r.target.text()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (Some(2), Operator::GreatAnd, "1".to_string()),
+                (None, Operator::Great, "out".to_string()),
+            ]
+        );
+        // A redirection may come first, or be all there is
+        let list = parse("> file");
+        assert!(simple(&list.items[0].first.commands[0]).words.is_empty());
+        let list = parse("<in cat");
+        assert_eq!(
+            texts(&simple(&list.items[0].first.commands[0]).words),
+            ["cat"]
+        );
+    }
+
+    /// The command line as the parser understood it, written back out.
+    fn reparsed(input: &str) -> String {
+        parse(input).to_string()
+    }
+
+    fn incomplete(input: &str) -> bool {
+        matches!(parse_input(input), Err(ParseError::Incomplete(_)))
+    }
+
+    fn syntax_error(input: &str) -> bool {
+        matches!(parse_input(input), Err(ParseError::Syntax(_)))
+    }
+
+    #[test]
+    fn lists() {
+        let list = parse("a | b && c || d; e");
+        assert_eq!(list.items.len(), 2);
+        let first = &list.items[0];
+        assert_eq!(first.first.commands.len(), 2);
+        let connectors: Vec<Connector> = first.rest.iter().map(|(c, _)| *c).collect();
+        assert_eq!(connectors, [Connector::And, Connector::Or]);
+        assert_eq!(reparsed("a | b && c || d; e"), "a | b && c || d; e");
+        // An operator at the end of a line continues on the next one
+        assert_eq!(reparsed("a &&\n\nb |\nc"), "a && b | c");
+        assert_eq!(reparsed("a\nb\n"), "a; b");
+    }
+
+    #[test]
+    fn incomplete_lists() {
+        for input in ["echo 'abc", "a |", "a &&", "a ||"] {
+            assert!(incomplete(input), "{:?}: {:?}", input, parse_input(input));
+        }
+    }
+
+    #[test]
+    fn list_syntax_errors() {
+        for input in ["a; ;", "| a", "a && || b", "; a"] {
+            assert!(syntax_error(input), "{:?}: {:?}", input, parse_input(input));
+        }
+    }
+
+    #[test]
+    fn assignments() {
+        let list = parse("x=1 y=\"2 3\" cmd z=4");
+        let command = simple(&list.items[0].first.commands[0]);
+        let assignments: Vec<(&str, String)> = command
+            .assignments
+            .iter()
+            .map(|a| (a.name.as_str(), a.value.text()))
+            .collect();
+        assert_eq!(
+            assignments,
+            [("x", "1".to_string()), ("y", "2 3".to_string())]
+        );
+        // After the command name an assignment is just an argument
+        assert_eq!(texts(&command.words), ["cmd", "z=4"]);
+        // Without a command name the assignments are all there is
+        let list = parse("a=1");
+        let command = simple(&list.items[0].first.commands[0]);
+        assert_eq!(command.assignments.len(), 1);
+        assert!(command.words.is_empty());
+        // Not a valid name, so not an assignment
+        let list = parse("1a=1");
+        assert_eq!(simple(&list.items[0].first.commands[0]).words.len(), 1);
+    }
+
+    #[test]
+    fn background() {
+        let list = parse("a | b & c &");
+        assert_eq!(list.items.len(), 2);
+        assert!(list.items[0].background && list.items[1].background);
+        assert_eq!(reparsed("a&b"), "a & b");
+        assert!(syntax_error("& a"));
+    }
+
+    #[test]
+    fn reserved_words_only_where_a_command_starts() {
+        let list = parse("echo if then fi");
+        assert_eq!(
+            texts(&simple(&list.items[0].first.commands[0]).words),
+            ["echo", "if", "then", "fi"]
+        );
+        // Quoted, a reserved word is a command name
+        let list = parse("'if' a");
+        assert_eq!(simple(&list.items[0].first.commands[0]).words.len(), 2);
+    }
+
+    #[test]
+    fn control_flow() {
+        for input in [
+            "if a; then b; elif c; then d; else e; fi",
+            "while a; do b; done",
+            "for x in 1 2; do echo $x; done",
+            "for x; do :; done",
+            "! a | b",
+        ] {
+            assert_eq!(reparsed(input), input);
+        }
+        assert_eq!(reparsed("until a\ndo b\ndone"), "until a; do b; done");
+        assert_eq!(
+            reparsed("case $x in\n(a|b) one;;\n*) two\nesac"),
+            "case $x in a | b) one;; *) two;; esac"
+        );
+        assert!(parse("! a | b").items[0].first.negated);
+
+        let list = parse("if a\nthen\n  b\nfi");
+        match &list.items[0].first.commands[0] {
+            Command::Compound(
+                CompoundCommand::If {
+                    branches,
+                    otherwise,
+                },
+                _,
+            ) => {
+                assert_eq!(branches.len(), 1);
+                assert!(otherwise.is_none());
+            }
+            other => panic!("not an if: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn incomplete_control_flow() {
+        for input in [
+            "if true; then",
+            "if true; then a; else",
+            "while true",
+            "for x in a b",
+            "case x in",
+            "case x in a) b;;",
+        ] {
+            assert!(incomplete(input), "{:?}: {:?}", input, parse_input(input));
+        }
+    }
+
+    #[test]
+    fn control_flow_syntax_errors() {
+        for input in [
+            "fi",
+            "then a",
+            ";; a",
+            "if; then a; fi",
+            "case x in a) b;; esac esac",
+            "for 1x in a; do b; done",
+        ] {
+            assert!(syntax_error(input), "{:?}: {:?}", input, parse_input(input));
+        }
+    }
+
+    #[test]
+    fn functions() {
+        let list = parse("f() { echo hi; }");
+        match &list.items[0].first.commands[0] {
+            Command::Function(name, body) => {
+                assert_eq!(name, "f");
+                assert!(matches!(
+                    **body,
+                    Command::Compound(CompoundCommand::Group(_), _)
+                ));
+            }
+            other => panic!("not a function: {:?}", other),
+        }
+        assert_eq!(reparsed("f() { echo hi; }"), "f () { echo hi; }");
+        assert!(incomplete("f()"));
+        assert!(syntax_error("f() a"));
+    }
+
+    #[test]
+    fn arithmetic_command() {
+        assert_eq!(reparsed("((x++)) && echo"), "((x++)) && echo");
+        match &parse("((1 + 2))").items[0].first.commands[0] {
+            Command::Compound(CompoundCommand::Arithmetic(expr), _) => assert_eq!(expr, "1 + 2"),
+            other => panic!("not arithmetic: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn conditionals() {
+        let input = "[[ -f a && $b == c* || ! -z d ]]";
+        assert_eq!(reparsed(input), input);
+        assert_eq!(reparsed("[[ $x =~ ^(a|b)$ ]]"), "[[ $x =~ ^(a|b)$ ]]");
+        assert!(incomplete("[[ -f"));
+        assert!(syntax_error("[[ a -eq ]]"));
+    }
+
+    #[test]
+    fn here_documents() {
+        let list = parse("cat <<EOF | wc -l\nhello\nEOF\necho done");
+        assert_eq!(list.items.len(), 2);
+        let redirect = &simple(&list.items[0].first.commands[0]).redirects[0];
+        assert_eq!(redirect.op, Operator::DLess);
+        assert_eq!(redirect.target.text(), "EOF");
+        let here_doc = redirect.here_doc.as_ref().unwrap();
+        assert_eq!(here_doc.body, "hello\n");
+        assert!(here_doc.expand);
+        assert!(incomplete("cat <<EOF"));
+    }
+
+    #[test]
+    fn groups_and_subshells() {
+        assert_eq!(reparsed("{ a; b; } 2> err"), "{ a; b; } 2> err");
+        assert_eq!(reparsed("( cd /; ls ) > out"), "(cd /; ls) > out");
+        assert_eq!(reparsed("f ()\n(echo sub)"), "f () (echo sub)");
+        for input in ["{ a;", "( a"] {
+            assert!(incomplete(input), "{:?}: {:?}", input, parse_input(input));
+        }
+        for input in ["{ }", "( )", "a )"] {
+            assert!(syntax_error(input), "{:?}: {:?}", input, parse_input(input));
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// pattern.rs
+//
+// Shell pattern matching: `*` matches any run of characters, `?` matches one
+// character and `[...]` matches one character from a set. A backslash makes
+// the next character literal, which is how the expander marks characters that
+// were quoted on the command line.
+
+/// Whether `text` matches `pattern` as a whole.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&pattern, &text)
+}
+
+/// Whether `pattern` contains an unescaped `*`, `?` or `[`.
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Escapes every character that has a meaning in patterns.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Removes the backslashes added by `escape`.
+pub fn unescape(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the most recent `*`: (pattern index, text index)
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match match_bracket(pattern, p, text[t]) {
+                Some((true, next)) => Some(next),
+                Some((false, _)) => None,
+                // No closing bracket, so `[` is an ordinary character
+                None if text[t] == '[' => Some(p + 1),
+                None => None,
+            },
+            Some('\\') if p + 1 < pattern.len() => {
+                if pattern[p + 1] == text[t] {
+                    Some(p + 2)
+                } else {
+                    None
+                }
+            }
+            Some(&c) if c == text[t] => Some(p + 1),
+            _ => None,
+        };
+
+        match step {
+            Some(next) => {
+                p = next;
+                t += 1;
+            }
+            // Let the last `*` swallow one more character and try again
+            None => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // Only trailing stars may remain
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the bracket expression starting at `pattern[start]`.
+/// Returns whether it matched and the index just past the closing `]`, or
+/// `None` if the bracket is never closed.
+fn match_bracket(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = *pattern.get(i)?;
+        // A `]` right after the opening bracket is part of the set
+        if current == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        if current == '[' && pattern.get(i + 1) == Some(&':') {
+            let rest: String = pattern[i + 2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                let class = &rest[..end];
+                matched |= in_class(class, c);
+                i += 2 + class.chars().count() + 2;
+                continue;
+            }
+        }
+
+        let low = if current == '\\' {
+            i += 1;
+            *pattern.get(i)?
+        } else {
+            current
+        };
+        // A range such as `a-z`; a `-` before the closing bracket is literal
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&c| c != ']') {
+            let mut high = pattern[i + 2];
+            i += 2;
+            if high == '\\' {
+                i += 1;
+                high = *pattern.get(i)?;
+            }
+            matched |= low <= c && c <= high;
+        } else {
+            matched |= low == c;
+        }
+        i += 1;
+    }
+}
+
+fn in_class(class: &str, c: char) -> bool {
+    match class {
+        "alpha" => c.is_alphabetic(),
+        "digit" => c.is_ascii_digit(),
+        "alnum" => c.is_alphanumeric(),
+        "upper" => c.is_uppercase(),
+        "lower" => c.is_lowercase(),
+        "space" => c.is_whitespace(),
+        "blank" => c == ' ' || c == '\t',
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        "cntrl" => c.is_control(),
+        "print" => !c.is_control(),
+        "graph" => !c.is_control() && !c.is_whitespace(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(!matches("a*b", "aXbY"));
+        assert!(matches("?é?", "aéb"));
+        assert!(!matches("?", ""));
+        assert!(matches("**", "a/b"));
+    }
+
+    #[test]
+    fn brackets() {
+        assert!(matches("[abc]x", "bx"));
+        assert!(!matches("[abc]x", "dx"));
+        assert!(matches("[a-cx-z]", "y"));
+        assert!(!matches("[a-c]", "d"));
+        assert!(matches("[!a-c]", "d"));
+        assert!(matches("[^a-c]", "d"));
+        assert!(!matches("[!a-c]", "b"));
+        // `]` first and `-` last are members
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[!]]", "a"));
+        assert!(matches("[a-]", "-"));
+        // An unclosed bracket is an ordinary character
+        assert!(matches("[ab", "[ab"));
+        assert!(!matches("[ab", "a"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[[:digit:]][[:alpha:]]", "1é"));
+        assert!(matches("[[:upper:][:digit:]]", "Q"));
+        assert!(!matches("[[:upper:][:digit:]]", "q"));
+        assert!(matches("[![:space:]]", "x"));
+        assert!(!matches("[![:space:]]", "\t"));
+        assert!(matches("[[:xdigit:]]*", "f00"));
+        assert!(!matches("[[:bogus:]]", "a"));
+    }
+
+    #[test]
+    fn escaped_metacharacters() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("a\\?", "a?"));
+        assert!(!matches("a\\?", "ab"));
+        assert!(matches("[\\]]", "]"));
+        assert!(matches("[a\\-z]", "-"));
+        assert!(!matches("[a\\-z]", "m"));
+        assert!(!has_wildcards("a\\*b\\[c"));
+        assert!(has_wildcards("a\\*b?"));
+        let text = "a*b?[c]\\d";
+        assert!(matches(&escape(text), text));
+        assert_eq!(unescape(&escape(text)), text);
+    }
+}
@@ -0,0 +1,324 @@
+// prompt.rs
+//
+// The prompts shown at the terminal: `PS1` before each command, `PS2` before
+// each further line of a command that is not complete yet, and `RPS1` at the
+// right edge of the terminal next to `PS1`. Backslash escapes in them stand
+// for the user, host, working directory, status and duration of the last
+// command, time, number of jobs, git branch and colors; after those are
+// filled in, parameters and command substitutions are expanded as in double
+// quotes, so `$VAR` and `$(cmd)` work too.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Local;
+
+use crate::expand;
+use crate::state::Shell;
+
+/// The prompt when `PS1` is not set: the working directory and `$`.
+const DEFAULT_PS1: &str = "\\w \\$ ";
+const DEFAULT_PS2: &str = "> ";
+
+/// Color names for `\c{name}`, with their SGR codes.
+const COLORS: [(&str, &str); 13] = [
+    ("reset", "0"),
+    ("bold", "1"),
+    ("dim", "2"),
+    ("italic", "3"),
+    ("underline", "4"),
+    ("black", "30"),
+    ("red", "31"),
+    ("green", "32"),
+    ("yellow", "33"),
+    ("blue", "34"),
+    ("magenta", "35"),
+    ("cyan", "36"),
+    ("white", "37"),
+];
+
+/// The left and right prompts to show before reading a line: `PS1` and
+/// `RPS1` for a new command, or `PS2` when `continued`. `duration` is how
+/// long the last command took.
+pub fn prompts(shell: &mut Shell, continued: bool, duration: Duration) -> (String, String) {
+    if continued {
+        let ps2 = shell.vars.get("PS2").unwrap_or(DEFAULT_PS2).to_string();
+        return (render(shell, &ps2, duration), String::new());
+    }
+    let ps1 = shell.vars.get("PS1").unwrap_or(DEFAULT_PS1).to_string();
+    let rps1 = shell.vars.get("RPS1").unwrap_or_default().to_string();
+    (render(shell, &ps1, duration), render(shell, &rps1, duration))
+}
+
+/// Fills in the escapes in `template`, then expands it. An expansion that
+/// fails is reported and the template shown as it is.
+fn render(shell: &mut Shell, template: &str, duration: Duration) -> String {
+    let text = escapes(shell, template, duration);
+    // Showing the prompt leaves `$?` as the last command set it
+    let (status, substitution) = (shell.last_status, shell.substitution_status.take());
+    let expanded = expand::expand_here_doc(shell, &text);
+    shell.last_status = status;
+    shell.substitution_status = substitution;
+    expanded.unwrap_or_else(|e| {
+        eprintln!("prompt: {}", e);
+        template.to_string()
+    })
+}
+
+/// Replaces the backslash escapes in `template`. What they stand for is
+/// quoted, so that a `$` in a directory name is not expanded afterwards.
+fn escapes(shell: &Shell, template: &str, duration: Duration) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            out.push(c);
+            break;
+        };
+        let text = match escape {
+            'u' => user(shell),
+            'h' => host().split('.').next().unwrap_or_default().to_string(),
+            'H' => host(),
+            'w' => working_dir(shell),
+            'W' => match working_dir(shell).as_str() {
+                "/" => "/".to_string(),
+                dir => dir.rsplit('/').next().unwrap_or_default().to_string(),
+            },
+            'p' => short_working_dir(shell),
+            '?' => shell.last_status.to_string(),
+            'd' => format_duration(duration),
+            't' => Local::now().format("%H:%M:%S").to_string(),
+            'T' => Local::now().format("%I:%M:%S").to_string(),
+            '@' => Local::now().format("%I:%M %p").to_string(),
+            'A' => Local::now().format("%H:%M").to_string(),
+            'D' => match braced(&mut chars) {
+                Some(format) => strftime(&format),
+                None => "\\D".to_string(),
+            },
+            'j' => shell.jobs.ids().len().to_string(),
+            'g' => git_branch().unwrap_or_default(),
+            '$' => if users::get_effective_uid() == 0 { "#" } else { "$" }.to_string(),
+            'c' => {
+                let color = braced(&mut chars);
+                match color.as_deref().and_then(color_code) {
+                    Some(code) => format!("\x01\x1b[{}m\x02", code),
+                    None => format!("\\c{{{}}}", color.unwrap_or_default()),
+                }
+            }
+            'e' => "\x1b".to_string(),
+            '[' => "\x01".to_string(),
+            ']' => "\x02".to_string(),
+            'n' => "\n".to_string(),
+            '\\' => "\\".to_string(),
+            // An unknown escape stays as it is
+            c => {
+                out.push('\\');
+                out.push(c);
+                continue;
+            }
+        };
+        for c in text.chars() {
+            if matches!(c, '\\' | '$' | '`') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Reads the `{...}` argument of an escape such as `\D{%F}`.
+fn braced(chars: &mut std::str::Chars) -> Option<String> {
+    let rest = chars.as_str().strip_prefix('{')?;
+    let end = rest.find('}')?;
+    let argument = rest[..end].to_string();
+    *chars = rest[end + 1..].chars();
+    Some(argument)
+}
+
+/// The SGR code for a color name such as `red`, or a number from the 256
+/// color palette.
+fn color_code(name: &str) -> Option<String> {
+    if let Some((_, code)) = COLORS.iter().find(|(color, _)| *color == name) {
+        return Some(code.to_string());
+    }
+    let number: u8 = name.parse().ok()?;
+    Some(format!("38;5;{}", number))
+}
+
+/// The current time in a `strftime` format; an invalid format shows nothing.
+fn strftime(format: &str) -> String {
+    let mut out = String::new();
+    match write!(out, "{}", Local::now().format(format)) {
+        Ok(()) => out,
+        Err(_) => String::new(),
+    }
+}
+
+fn user(shell: &Shell) -> String {
+    match users::get_current_username() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => shell.vars.get("USER").unwrap_or_default().to_string(),
+    }
+}
+
+fn host() -> String {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer is writable for its whole length
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return String::new();
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+/// The working directory, with `~` for the home directory. A directory that
+/// was removed falls back to `$PWD`.
+fn working_dir(shell: &Shell) -> String {
+    let dir = match std::env::current_dir() {
+        Ok(dir) => dir.to_string_lossy().into_owned(),
+        Err(_) => shell.vars.get("PWD").unwrap_or(".").to_string(),
+    };
+    let home = shell.vars.get("HOME").unwrap_or_default().trim_end_matches('/');
+    match dir.strip_prefix(home) {
+        Some(rest) if !home.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+            format!("~{}", rest)
+        }
+        _ => dir,
+    }
+}
+
+/// The working directory with every directory but the last cut down to its
+/// first letter, as in `~/s/crate`.
+fn short_working_dir(shell: &Shell) -> String {
+    let dir = working_dir(shell);
+    let Some((parents, last)) = dir.rsplit_once('/') else {
+        return dir;
+    };
+    let mut out = String::new();
+    for parent in parents.split('/') {
+        // Hidden directories keep their dot
+        let len = if parent.starts_with('.') { 2 } else { 1 };
+        out.extend(parent.chars().take(len));
+        out.push('/');
+    }
+    out.push_str(last);
+    out
+}
+
+/// `0.25s`, `42.00s`, `3m07s` or `1h05m`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else if seconds < 3600 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60)
+    }
+}
+
+/// The branch checked out in the git repository around the working
+/// directory, or the start of the commit's hash when none is. Read from
+/// `.git/HEAD` rather than by running git, which would slow every prompt.
+fn git_branch() -> Option<String> {
+    let cwd = std::env::current_dir().ok()?;
+    let git_dir = cwd.ancestors().find_map(git_dir)?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(reference) => {
+            Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string())
+        }
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+/// The git directory of a repository rooted at `dir`: `.git` itself, or
+/// where a `.git` file points, as in a worktree or submodule.
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let git = dir.join(".git");
+    let metadata = fs::metadata(&git).ok()?;
+    if metadata.is_dir() {
+        return Some(git);
+    }
+    let link = fs::read_to_string(&git).ok()?;
+    let target = link.trim().strip_prefix("gitdir: ")?;
+    Some(dir.join(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(shell: &mut Shell, template: &str) -> String {
+        render(shell, template, Duration::from_millis(250))
+    }
+
+    #[test]
+    fn status_duration_and_jobs() {
+        let mut shell = Shell {
+            last_status: 3,
+            ..Shell::default()
+        };
+        assert_eq!(rendered(&mut shell, "[\\?] \\d \\j"), "[3] 0.25s 0");
+        // `$?` is the last command's, and stays so after the prompt
+        assert_eq!(rendered(&mut shell, "$?"), "3");
+        assert_eq!(shell.last_status, 3);
+    }
+
+    #[test]
+    fn colors_and_control_characters() {
+        let mut shell = Shell::default();
+        assert_eq!(rendered(&mut shell, "\\c{red}>"), "\x01\x1b[31m\x02>");
+        assert_eq!(rendered(&mut shell, "\\c{196}"), "\x01\x1b[38;5;196m\x02");
+        assert_eq!(rendered(&mut shell, "\\[\\e[1m\\]"), "\x01\x1b[1m\x02");
+        assert_eq!(rendered(&mut shell, "a\\nb\\\\"), "a\nb\\");
+    }
+
+    #[test]
+    fn unknown_escapes_stay() {
+        let mut shell = Shell::default();
+        assert_eq!(
+            rendered(&mut shell, "\\q \\D \\c{nope}"),
+            "\\q \\D \\c{nope}"
+        );
+        assert_eq!(rendered(&mut shell, "end\\"), "end\\");
+    }
+
+    #[test]
+    fn escapes_are_not_expanded_again() {
+        let mut shell = Shell::default();
+        shell.vars.set("x", "value");
+        assert_eq!(rendered(&mut shell, "\\c{$x} $x"), "\\c{$x} value");
+    }
+
+    #[test]
+    fn working_directory() {
+        let mut shell = Shell::default();
+        let cwd = std::env::current_dir()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        shell.vars.set("HOME", &cwd);
+        assert_eq!(rendered(&mut shell, "\\w"), "~");
+        shell.vars.set("HOME", "/nonexistent");
+        assert_eq!(rendered(&mut shell, "\\w"), cwd);
+        let last = cwd.rsplit('/').next().unwrap();
+        assert_eq!(rendered(&mut shell, "\\W"), last);
+        assert!(rendered(&mut shell, "\\p").ends_with(&format!("/{}", last)));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_millis(42_004)), "42.00s");
+        assert_eq!(format_duration(Duration::from_secs(187)), "3m07s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h05m");
+    }
+}
@@ -1,6 +1,12 @@
 use std::env;
+use std::io::Write;
 
-pub fn pwd() {
-    let current_dir = env::current_dir().unwrap();
-    println!("{}", current_dir.display());
+use crate::getopt::Spec;
+use crate::stream::Io;
+
+pub const SPEC: Spec = Spec::new("Print the working directory.", "", &[]);
+
+pub fn pwd(io: &mut Io) -> Result<(), String> {
+    let current_dir = env::current_dir().map_err(|e| format!("pwd: {}", e))?;
+    writeln!(io.stdout, "{}", current_dir.display()).map_err(|e| format!("pwd: {}", e))
 }
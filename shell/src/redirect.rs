@@ -0,0 +1,165 @@
+// redirect.rs
+//
+// Applies the redirections parsed after a command to its streams. This runs
+// once in the executor for every command, builtin or not, so `ls > out`,
+// `cat < in`, `make 2>&1` and `cat <<EOF` all behave the same way.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::expand;
+use crate::lexer::Operator;
+use crate::parser::Redirect;
+use crate::state::Shell;
+use crate::stream::{Io, Stream};
+
+/// Applies `redirects` to `io` from left to right, so `> out 2>&1` sends both
+/// streams to `out` while `2>&1 > out` leaves stderr where stdout was.
+pub fn apply(shell: &mut Shell, redirects: &[Redirect], io: &mut Io) -> Result<(), String> {
+    for redirect in redirects {
+        let fd = redirect.fd.unwrap_or(match redirect.op {
+            Operator::Less
+            | Operator::LessGreat
+            | Operator::LessAnd
+            | Operator::DLess
+            | Operator::DLessDash
+            | Operator::TLess => 0,
+            _ => 1,
+        });
+        if let Some(here_doc) = &redirect.here_doc {
+            let text = if here_doc.expand {
+                expand::expand_here_doc(shell, &here_doc.body)?
+            } else {
+                here_doc.body.clone()
+            };
+            set_stream(io, fd, here_doc_stream(&text)?)?;
+            continue;
+        }
+        let target = expand::expand_word(shell, &redirect.target)?;
+        let options = &shell.options;
+        let open_error = |e: io::Error| format!("{}: {}", target, e);
+
+        match redirect.op {
+            // `<<< word` feeds the word and a newline
+            Operator::TLess => set_stream(io, fd, here_doc_stream(&format!("{}\n", target))?)?,
+            Operator::Less => {
+                let file = File::open(&target).map_err(open_error)?;
+                set_stream(io, fd, Stream::File(file.into()))?;
+            }
+            Operator::LessGreat => {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(&target)
+                    .map_err(open_error)?;
+                set_stream(io, fd, Stream::File(file.into()))?;
+            }
+            Operator::Great | Operator::Clobber => {
+                let noclobber = options.noclobber && redirect.op == Operator::Great;
+                let file = create(&target, noclobber)?;
+                set_stream(io, fd, Stream::File(file.into()))?;
+            }
+            Operator::DGreat => {
+                let file = append(&target).map_err(open_error)?;
+                set_stream(io, fd, Stream::File(file.into()))?;
+            }
+            Operator::AndGreat | Operator::AndDGreat => {
+                let file = if redirect.op == Operator::AndGreat {
+                    create(&target, options.noclobber)?
+                } else {
+                    append(&target).map_err(open_error)?
+                };
+                let stream = Stream::File(file.into());
+                io.stdout = stream.clone();
+                io.stderr = stream;
+            }
+            Operator::GreatAnd | Operator::LessAnd => match target.parse::<u32>() {
+                Ok(source) => {
+                    let stream = get_stream(io, source)?;
+                    set_stream(io, fd, stream)?;
+                }
+                // `>&file` is an old spelling of `&>file`
+                Err(_) if redirect.op == Operator::GreatAnd && redirect.fd.is_none() => {
+                    let stream = Stream::File(create(&target, options.noclobber)?.into());
+                    io.stdout = stream.clone();
+                    io.stderr = stream;
+                }
+                Err(_) => return Err(format!("{}: ambiguous redirect", target)),
+            },
+            // The parser only builds redirections from redirection operators
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Holds the text of a here-document or here-string in a file that is
+/// deleted at once, so it can be read like any redirected file, however
+/// large, by builtins and external commands alike.
+fn here_doc_stream(text: &str) -> Result<Stream, String> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let error = |e: io::Error| format!("cannot create temp file for here-document: {}", e);
+    let path = env::temp_dir().join(format!(
+        "shell-here-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(error)?;
+    let _ = fs::remove_file(&path);
+    file.write_all(text.as_bytes()).map_err(error)?;
+    file.rewind().map_err(error)?;
+    Ok(Stream::File(file.into()))
+}
+
+/// Opens `path` for writing, truncating it unless `noclobber` protects it.
+fn create(path: &str, noclobber: bool) -> Result<File, String> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if noclobber {
+        match fs::metadata(path) {
+            // Only regular files are protected; devices such as /dev/null stay writable
+            Ok(metadata) if metadata.is_file() => {
+                return Err(format!("{}: cannot overwrite existing file", path));
+            }
+            Ok(_) => {}
+            Err(_) => {
+                options.create_new(true);
+            }
+        }
+    } else {
+        options.create(true).truncate(true);
+    }
+    options.open(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn append(path: &str) -> io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+fn get_stream(io: &Io, fd: u32) -> Result<Stream, String> {
+    match fd {
+        0 => Ok(io.stdin.clone()),
+        1 => Ok(io.stdout.clone()),
+        2 => Ok(io.stderr.clone()),
+        _ => Err(format!("{}: Bad file descriptor", fd)),
+    }
+}
+
+fn set_stream(io: &mut Io, fd: u32, stream: Stream) -> Result<(), String> {
+    match fd {
+        0 => io.stdin = stream,
+        1 => io.stdout = stream,
+        2 => io.stderr = stream,
+        _ => return Err(format!("{}: Bad file descriptor", fd)),
+    }
+    Ok(())
+}
@@ -1,37 +1,52 @@
-use std::fs::{remove_dir_all, remove_file};
+use std::fs::{self, remove_file};
+use std::io::{self, Write};
+use std::path::Path;
 
-pub fn handle_rm(args: Vec<&str>) -> Result<(), String> {
+use crate::getopt::{Args, Opt, Spec};
+use crate::signals;
+use crate::stream::Io;
+
+pub const SPEC: Spec = Spec::new(
+    "Remove files, and directories with -r.",
+    "file ...",
+    &[
+        Opt::flag('r', "recursive", "remove directories and everything in them"),
+        Opt::flag('R', "", "the same as -r"),
+        Opt::flag('f', "force", "ignore files that do not exist"),
+    ],
+);
+
+pub fn handle_rm(args: Args, io: &mut Io) -> Result<(), String> {
+    let force = args.has('f');
     // Check if no file or directory name is provided
-    if args.is_empty() {
+    if args.operands.is_empty() {
+        if force {
+            return Ok(());
+        }
         return Err("rm: missing operand".to_string());
     }
 
-    // Check if the -r flag is present
-    let recursive = args.contains(&"-r");
+    let recursive = args.has('r') || args.has('R');
 
     // Iterate over the provided file or directory names and remove each
-    for item in args {
-        // Skip the -r flag
-        if item == "-r" {
+    for item in args.operands {
+        if force && fs::symlink_metadata(item).is_err() {
             continue;
         }
-
-        if let Err(err) = remove_item(item, recursive) {
-            return Err(err);
-        }
+        remove_item(item, recursive, io)?;
     }
 
     Ok(())
 }
 
-fn remove_item(item: &str, recursive: bool) -> Result<(), String> {
+fn remove_item(item: &str, recursive: bool, io: &mut Io) -> Result<(), String> {
     let path = std::path::Path::new(item);
 
     if path.is_dir() && recursive {
         // Remove directory recursively
-        match remove_dir_all(path) {
+        match remove_tree(path) {
             Ok(()) => {
-                println!("Directory '{}' and its contents removed.", item);
+                let _ = writeln!(io.stdout, "Directory '{}' and its contents removed.", item);
                 Ok(())
             }
             Err(err) => Err(format!("rm: {}", err)),
@@ -40,10 +55,26 @@ fn remove_item(item: &str, recursive: bool) -> Result<(), String> {
         // Remove file or empty directory
         match remove_file(path) {
             Ok(()) => {
-                println!("File or directory '{}' removed.", item);
+                let _ = writeln!(io.stdout, "File or directory '{}' removed.", item);
                 Ok(())
             }
             Err(err) => Err(format!("rm: {}", err)),
         }
     }
 }
+
+/// Removes a directory and everything below it, one entry at a time so that
+/// Ctrl+C can stop it part way through.
+fn remove_tree(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        signals::check()?;
+        let entry = entry?;
+        // Symlinks to directories are removed, never followed
+        if entry.file_type()?.is_dir() {
+            remove_tree(&entry.path())?;
+        } else {
+            remove_file(entry.path())?;
+        }
+    }
+    fs::remove_dir(dir)
+}
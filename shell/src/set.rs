@@ -0,0 +1,97 @@
+use std::io::Write;
+
+use crate::state::{Options, Shell};
+use crate::stream::Io;
+
+/// Names accepted by `set -o`, paired with the flag that has the same effect
+/// if there is one.
+const OPTION_NAMES: [(&str, Option<char>); 4] = [
+    ("emacs", None),
+    ("noclobber", Some('C')),
+    ("noglob", Some('f')),
+    ("vi", None),
+];
+
+pub fn handle_set(args: Vec<&str>, shell: &mut Shell, io: &mut Io) -> Result<(), String> {
+    if args.is_empty() {
+        return print_variables(shell, io);
+    }
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // The first argument that is not an option starts the new positional
+        // parameters; `--` marks where they start, and may be followed by none
+        let enable = match arg.chars().next() {
+            _ if arg == "--" => {
+                shell.positional = args.map(str::to_string).collect();
+                return Ok(());
+            }
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                shell.positional = std::iter::once(arg).chain(args).map(str::to_string).collect();
+                return Ok(());
+            }
+        };
+        let flags = &arg[1..];
+        if flags == "o" {
+            match args.next() {
+                Some(name) => set_named(&mut shell.options, name, enable)?,
+                None => return print_options(&shell.options, io),
+            }
+            continue;
+        }
+        for flag in flags.chars() {
+            match OPTION_NAMES.iter().find(|(_, short)| *short == Some(flag)) {
+                Some((name, _)) => set_named(&mut shell.options, name, enable)?,
+                None => return Err(format!("set: -{}: invalid option", flag)),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_named(options: &mut Options, name: &str, enable: bool) -> Result<(), String> {
+    match name {
+        "noclobber" => options.noclobber = enable,
+        "noglob" => options.noglob = enable,
+        // The two keymaps exclude each other
+        "emacs" => options.vi = !enable,
+        "vi" => options.vi = enable,
+        _ => return Err(format!("set: {}: invalid option name", name)),
+    }
+    Ok(())
+}
+
+/// Prints every shell variable as an assignment that can be read back.
+fn print_variables(shell: &Shell, io: &mut Io) -> Result<(), String> {
+    for (name, var) in shell.vars.iter() {
+        writeln!(io.stdout, "{}={}", name, quote(&var.value)).map_err(|e| format!("set: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Single-quotes `value` unless it is made only of characters that are safe unquoted.
+fn quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./:,+@%=".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+fn print_options(options: &Options, io: &mut Io) -> Result<(), String> {
+    for (name, _) in OPTION_NAMES {
+        let enabled = match name {
+            "noclobber" => options.noclobber,
+            "noglob" => options.noglob,
+            "emacs" => !options.vi,
+            "vi" => options.vi,
+            _ => false,
+        };
+        let state = if enabled { "on" } else { "off" };
+        writeln!(io.stdout, "{:15} {}", name, state).map_err(|e| format!("set: {}", e))?;
+    }
+    Ok(())
+}
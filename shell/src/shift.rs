@@ -0,0 +1,25 @@
+// shift.rs
+//
+// `shift [n]` drops the first n positional parameters (one by default), so
+// `$2` becomes `$1` and so on. A script walks through its arguments this way.
+
+use crate::getopt::Spec;
+use crate::state::Shell;
+
+pub const SPEC: Spec = Spec::new("Drop the first n positional parameters, or one.", "[n]", &[])
+    .numbers();
+
+pub fn shift(args: Vec<&str>, shell: &mut Shell) -> Result<(), String> {
+    let count = match args.as_slice() {
+        [] => 1,
+        [n] => n
+            .parse::<usize>()
+            .map_err(|_| format!("shift: {}: numeric argument required", n))?,
+        _ => return Err("shift: too many arguments".to_string()),
+    };
+    if count > shell.positional.len() {
+        return Err(format!("shift: {}: shift count out of range", count));
+    }
+    shell.positional.drain(..count);
+    Ok(())
+}
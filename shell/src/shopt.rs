@@ -0,0 +1,56 @@
+use std::io::Write;
+
+use crate::getopt::{Args, Opt, Spec};
+use crate::state::Options;
+use crate::stream::Io;
+
+/// Options controlled by `shopt`, in the order they are listed.
+const SHOPT_NAMES: [&str; 3] = ["dotglob", "failglob", "nullglob"];
+
+pub const SPEC: Spec = Spec::new(
+    "Set, unset or show the globbing options, all of them by default.",
+    "[optname ...]",
+    &[
+        Opt::flag('s', "set", "turn the options on"),
+        Opt::flag('u', "unset", "turn the options off"),
+    ],
+);
+
+/// `shopt [-s|-u] [name]...`: sets, unsets or shows the globbing options.
+pub fn shopt(args: Args, options: &mut Options, io: &mut Io) -> Result<(), String> {
+    let enable = match (args.has('s'), args.has('u')) {
+        (true, true) => {
+            return Err("shopt: cannot set and unset shell options simultaneously".to_string())
+        }
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None,
+    };
+    let names = &args.operands;
+    for name in names {
+        if !SHOPT_NAMES.contains(name) {
+            return Err(format!("shopt: {}: invalid shell option name", name));
+        }
+    }
+
+    let names: Vec<&str> = if names.is_empty() {
+        SHOPT_NAMES.to_vec()
+    } else {
+        names.to_vec()
+    };
+    for name in names {
+        let option = match name {
+            "dotglob" => &mut options.dotglob,
+            "failglob" => &mut options.failglob,
+            _ => &mut options.nullglob,
+        };
+        match enable {
+            Some(enable) => *option = enable,
+            None => {
+                let state = if *option { "on" } else { "off" };
+                writeln!(io.stdout, "{:15} {}", name, state).map_err(|e| format!("shopt: {}", e))?;
+            }
+        }
+    }
+    Ok(())
+}
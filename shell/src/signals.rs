@@ -0,0 +1,57 @@
+// signals.rs
+//
+// Ctrl+C and Ctrl+\ handling for an interactive shell. While an external
+// command runs it owns the terminal, so the kernel sends it those signals and
+// the shell never sees them. While the shell itself is busy, in a builtin or at
+// the prompt, SIGINT only sets a flag: builtins check it at safe points and
+// stop early, and the prompt starts over on a fresh line. SIGQUIT is ignored.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Installs the shell's own SIGINT handler and ignores SIGQUIT.
+pub fn init() {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        // No SA_RESTART: a blocking read must return so the shell can notice
+        action.sa_flags = 0;
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+    }
+}
+
+/// Whether Ctrl+C was pressed since the flag was last cleared.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Sets the flag as if Ctrl+C had reached the shell, for when it killed the
+/// foreground command instead. Whatever the shell was running stops too.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Clears the flag, returning whether it was set.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Returns an error once Ctrl+C was pressed, for the loops of long-running
+/// builtins to bail out with `?`. The error is deliberately not of kind
+/// `Interrupted`, which the standard library would simply retry.
+pub fn check() -> io::Result<()> {
+    if interrupted() {
+        Err(io::Error::other("interrupted"))
+    } else {
+        Ok(())
+    }
+}
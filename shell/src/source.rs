@@ -0,0 +1,98 @@
+// source.rs
+//
+// Running the commands in a file in the shell itself, so that the variables,
+// functions and options they set stay: the `source` and `.` builtins, and the
+// startup files. A login shell (`-l`) first reads `~/.0shell_profile`; an
+// interactive one then reads `~/.0shellrc`, unless started with `--norc`.
+// Errors in these files are reported with the file name and line, and a
+// syntax error only stops the file it is in.
+
+use std::io;
+use std::path::Path;
+
+use crate::getopt::Spec;
+use crate::input::Input;
+use crate::state::{Flow, Shell};
+
+/// Read by login shells, in the home directory.
+pub const PROFILE: &str = ".0shell_profile";
+/// Read by interactive shells, in the home directory.
+pub const RC: &str = ".0shellrc";
+
+pub const SPEC: Spec = Spec::new(
+    "Run the commands in a file in this shell.",
+    "file [arg ...]",
+    &[],
+)
+.in_order();
+
+/// `source file [args...]` or `. file [args...]`. A file name without a `/`
+/// is looked for on `PATH`, then in the current directory. With `args`, they
+/// are the positional parameters while the file runs. Returns the status of
+/// the last command in the file, or the one given to `return`.
+pub fn source(command: &str, args: Vec<&str>, shell: &mut Shell) -> Result<i32, String> {
+    let Some((&file, args)) = args.split_first() else {
+        return Err(format!("{}: filename argument required", command));
+    };
+    let path = find(shell, file);
+    let mut input = Input::file(&path).map_err(|e| {
+        let message = match e.kind() {
+            io::ErrorKind::NotFound => "No such file or directory".to_string(),
+            _ => e.to_string(),
+        };
+        format!("{}: {}: {}", command, file, message)
+    })?;
+
+    let positional = if args.is_empty() {
+        None
+    } else {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        Some(std::mem::replace(&mut shell.positional, args))
+    };
+    let status = run_file(shell, &mut input);
+    if let Some(positional) = positional {
+        shell.positional = positional;
+    }
+    Ok(status)
+}
+
+/// Runs the startup file `name` in the home directory, if there is one.
+pub fn startup(shell: &mut Shell, name: &str) {
+    let Some(home) = shell.vars.get("HOME") else {
+        return;
+    };
+    let path = Path::new(home).join(name);
+    if !path.is_file() {
+        return;
+    }
+    let path = path.to_string_lossy().into_owned();
+    match Input::file(&path) {
+        Ok(mut input) => {
+            run_file(shell, &mut input);
+        }
+        Err(e) => eprintln!("shell: {}: {}", path, e),
+    }
+}
+
+fn run_file(shell: &mut Shell, input: &mut Input) -> i32 {
+    shell.source_depth += 1;
+    crate::run(shell, input);
+    shell.source_depth -= 1;
+    if shell.flow == Some(Flow::Return) {
+        shell.flow = None;
+    }
+    shell.last_status
+}
+
+/// Where `source file` reads from.
+fn find(shell: &Shell, file: &str) -> String {
+    if !file.contains('/') {
+        for dir in shell.vars.get("PATH").unwrap_or_default().split(':') {
+            let path = Path::new(if dir.is_empty() { "." } else { dir }).join(file);
+            if path.is_file() {
+                return path.to_string_lossy().into_owned();
+            }
+        }
+    }
+    file.to_string()
+}
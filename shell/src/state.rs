@@ -0,0 +1,128 @@
+// state.rs
+//
+// Everything the shell remembers between commands.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::history::History;
+use crate::jobs::Jobs;
+use crate::parser::Command;
+use crate::signals;
+use crate::terminal::Terminal;
+use crate::vars::Variables;
+
+/// Settings toggled with the `set` builtin.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// `set -C`: refuse to let `>` overwrite an existing file.
+    pub noclobber: bool,
+    /// `set -f`: turn off filename globbing.
+    pub noglob: bool,
+    /// `shopt -s nullglob`: a pattern matching nothing expands to nothing.
+    pub nullglob: bool,
+    /// `shopt -s failglob`: a pattern matching nothing is an error.
+    pub failglob: bool,
+    /// `shopt -s dotglob`: wildcards also match names starting with `.`.
+    pub dotglob: bool,
+    /// `set -o vi`: edit command lines with vi keys instead of emacs ones.
+    pub vi: bool,
+}
+
+/// A jump out of the normal order of commands, waiting to be carried out
+/// by the loops around the command that asked for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    /// `break n`: leave this many enclosing loops.
+    Break(usize),
+    /// `continue n`: leave n - 1 loops and start the next iteration of the nth.
+    Continue(usize),
+    /// `return`: leave the function or sourced file being run.
+    Return,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Shell {
+    pub options: Options,
+    /// Exit status of the most recent command, read back as `$?`.
+    pub last_status: i32,
+    /// Set when the shell is interactive and owns a terminal.
+    pub terminal: Option<Terminal>,
+    pub vars: Variables,
+    pub jobs: Jobs,
+    /// Process ID of the most recent background job, read back as `$!`.
+    pub last_background: Option<libc::pid_t>,
+    /// `$0`: the shell's own name, or the script it runs.
+    pub name: String,
+    /// `$1`, `$2` ...: the arguments given to the script.
+    pub positional: Vec<String>,
+    /// Set by `break` and `continue` until the loops have acted on it.
+    pub flow: Option<Flow>,
+    /// How many loops are running, so `break` knows whether it has one to leave.
+    pub loop_depth: usize,
+    /// Functions by name, each holding the compound command that is its body.
+    pub functions: BTreeMap<String, Arc<Command>>,
+    /// Aliases by name, with the text each stands for.
+    pub aliases: BTreeMap<String, String>,
+    /// Status of the last command substitution run while expanding the
+    /// current command, which becomes `$?` when it only assigns variables.
+    pub substitution_status: Option<i32>,
+    /// `$$`: the shell's process ID, which forked copies running a command
+    /// substitution or a background job keep.
+    pub pid: u32,
+    /// Command lines read at the prompt, kept only by an interactive shell.
+    pub history: History,
+    /// Where the command running was read, such as `build.sh: line 3: `, to
+    /// put before its errors. Empty at the prompt.
+    pub location: String,
+    /// How many files are being run by `source`, so `return` can leave one.
+    pub source_depth: usize,
+}
+
+impl Shell {
+    /// A shell reading commands from the terminal takes control of it; one
+    /// running a script leaves it alone.
+    pub fn new(interactive: bool) -> Shell {
+        let terminal = if interactive { Terminal::init() } else { None };
+        if terminal.is_some() {
+            signals::init();
+        }
+        let mut vars = Variables::from_env();
+        // `PWD` is where the shell really starts, whatever it inherited
+        if let Ok(dir) = std::env::current_dir() {
+            vars.set("PWD", &dir.to_string_lossy());
+            vars.export("PWD");
+        }
+        Shell {
+            terminal,
+            vars,
+            pid: std::process::id(),
+            ..Shell::default()
+        }
+    }
+
+    /// Looks up a parameter: a special one such as `$?` or a variable.
+    pub fn param(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "0" => Some(self.name.clone()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" => Some(self.positional.join(" ")),
+            // `$*` joins with the first character of IFS
+            "*" => {
+                let separator = match self.vars.get("IFS") {
+                    Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+                    None => " ".to_string(),
+                };
+                Some(self.positional.join(&separator))
+            }
+            _ if name.chars().all(|c| c.is_ascii_digit()) => {
+                let index: usize = name.parse().ok()?;
+                self.positional.get(index.checked_sub(1)?).cloned()
+            }
+            _ => self.vars.get(name).map(str::to_string),
+        }
+    }
+}
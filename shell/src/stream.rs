@@ -0,0 +1,146 @@
+// stream.rs
+//
+// Builtins used to print straight to the terminal, which made it impossible to
+// feed their output into another command. Every command now gets an `Io` with
+// its three standard streams, and a stream can be the shell's own descriptor or
+// any file, including one end of a pipe.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use crate::signals;
+
+/// Where a command reads its input from or writes its output to.
+#[derive(Clone, Debug)]
+pub enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(Arc<File>),
+}
+
+impl Stream {
+    pub fn from_fd(fd: impl Into<OwnedFd>) -> Stream {
+        Stream::File(Arc::new(File::from(fd.into())))
+    }
+
+    /// Duplicates the underlying descriptor so it can be handed to a child process.
+    pub fn to_stdio(&self) -> io::Result<Stdio> {
+        let fd = match self {
+            Stream::Stdin => io::stdin().as_fd().try_clone_to_owned()?,
+            Stream::Stdout => io::stdout().as_fd().try_clone_to_owned()?,
+            Stream::Stderr => io::stderr().as_fd().try_clone_to_owned()?,
+            Stream::File(file) => file.as_fd().try_clone_to_owned()?,
+        };
+        Ok(Stdio::from(fd))
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match self {
+            Stream::Stdin => io::stdin().read(buf),
+            Stream::File(file) => (&**file).read(buf),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stream is not readable",
+            )),
+        };
+        match result {
+            // A read cut short by Ctrl+C gives up instead of being retried
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => signals::check().and(Err(e)),
+            result => result,
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Stdout => io::stdout().write(buf),
+            Stream::Stderr => io::stderr().write(buf),
+            Stream::File(file) => (&**file).write(buf),
+            Stream::Stdin => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stream is not writable",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Stdout => io::stdout().flush(),
+            Stream::Stderr => io::stderr().flush(),
+            Stream::File(file) => (&**file).flush(),
+            Stream::Stdin => Ok(()),
+        }
+    }
+}
+
+/// The standard streams of a single command.
+#[derive(Clone, Debug)]
+pub struct Io {
+    pub stdin: Stream,
+    pub stdout: Stream,
+    pub stderr: Stream,
+}
+
+impl Io {
+    /// Streams connected to the shell's own stdin, stdout and stderr.
+    pub fn standard() -> Io {
+        Io {
+            stdin: Stream::Stdin,
+            stdout: Stream::Stdout,
+            stderr: Stream::Stderr,
+        }
+    }
+
+    /// Closes, in a forked copy of the shell, the descriptors the shell opened
+    /// for other commands, such as the pipes of a pipeline's other stages, so
+    /// that their readers still see EOF. Those are the close-on-exec ones;
+    /// the streams in `self` and descriptors inherited by the shell stay open.
+    pub fn close_others(&self) {
+        let keep: Vec<RawFd> = [&self.stdin, &self.stdout, &self.stderr]
+            .iter()
+            .filter_map(|stream| match stream {
+                Stream::File(file) => Some(file.as_raw_fd()),
+                _ => None,
+            })
+            .collect();
+        // Listed before closing anything, as the listing has a descriptor of
+        // its own. Without /proc every descriptor that may be open is tried
+        let fds: Vec<RawFd> = match std::fs::read_dir("/proc/self/fd") {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .collect(),
+            Err(_) => {
+                // SAFETY: sysconf only reads a limit
+                let max = unsafe { libc::sysconf(libc::_SC_OPEN_MAX) };
+                let max = if max > 0 { max.min(65536) as RawFd } else { 1024 };
+                (3..max).collect()
+            }
+        };
+        for fd in fds {
+            if fd <= 2 || keep.contains(&fd) {
+                continue;
+            }
+            // SAFETY: the descriptor is not used by anything in this process
+            // any more; one that is not open fails F_GETFD and is skipped
+            unsafe {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                if flags >= 0 && flags & libc::FD_CLOEXEC != 0 {
+                    libc::close(fd);
+                }
+            }
+        }
+    }
+}
+
+/// Creates a pipe and returns its `(read, write)` ends.
+pub fn pipe() -> io::Result<(Stream, Stream)> {
+    let (reader, writer) = io::pipe()?;
+    Ok((Stream::from_fd(reader), Stream::from_fd(writer)))
+}
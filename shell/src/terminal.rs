@@ -0,0 +1,97 @@
+// terminal.rs
+//
+// Terminal ownership for an interactive shell. External commands run in their
+// own process group, and the group that owns the terminal is the one that may
+// read from it and receives the signals typed at it. The shell hands the
+// terminal to a command while it runs and takes it back afterwards.
+
+use std::io;
+use std::os::fd::RawFd;
+
+/// The shell's controlling terminal and its own process group.
+#[derive(Clone, Copy, Debug)]
+pub struct Terminal {
+    fd: RawFd,
+    shell_pgid: libc::pid_t,
+}
+
+impl Terminal {
+    /// Takes control of the terminal on stdin. Returns `None` when stdin is
+    /// not a terminal, in which case commands simply share the shell's group.
+    pub fn init() -> Option<Terminal> {
+        // SAFETY: plain libc calls on descriptors and signal numbers
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return None;
+            }
+            // A background process touching the terminal would be stopped
+            // while the shell hands it around, so ignore those signals. Ctrl+Z
+            // is meant for the foreground job, never for the shell itself
+            libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::signal(libc::SIGTTIN, libc::SIG_IGN);
+            libc::signal(libc::SIGTSTP, libc::SIG_IGN);
+
+            // Keep a private copy of the terminal; stdin of a command may
+            // be redirected but this descriptor always points at the tty
+            let fd = libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10);
+            if fd < 0 {
+                return None;
+            }
+
+            // Become a process group leader, unless we already lead a session
+            let pid = libc::getpid();
+            if libc::getpgrp() != pid {
+                libc::setpgid(0, 0);
+            }
+            let shell_pgid = libc::getpgrp();
+            libc::tcsetpgrp(fd, shell_pgid);
+            Some(Terminal { fd, shell_pgid })
+        }
+    }
+
+    /// Makes `pgid` the foreground process group.
+    pub fn give_to(&self, pgid: libc::pid_t) {
+        // SAFETY: `fd` stays open for the lifetime of the shell
+        unsafe {
+            libc::tcsetpgrp(self.fd, pgid);
+        }
+    }
+
+    /// Makes the shell the foreground process group again.
+    pub fn reclaim(&self) {
+        self.give_to(self.shell_pgid);
+    }
+
+    /// Runs in a forked child just before `exec`: joins process group `pgid`
+    /// (or starts a new one when it is 0), takes the terminal, and restores
+    /// the signals the shell ignores. Only async-signal-safe calls are made
+    /// here.
+    pub fn prepare_child(&self, pgid: libc::pid_t) -> io::Result<()> {
+        // SAFETY: only async-signal-safe libc calls
+        unsafe {
+            // Ctrl+C must reach the command from the moment it owns the
+            // terminal, not the handler inherited from the shell
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+            libc::setpgid(0, pgid);
+            // Doing this in the child as well as the parent closes the
+            // window in which the child could read the tty too early
+            libc::tcsetpgrp(self.fd, libc::getpgrp());
+        }
+        reset_signals();
+        Ok(())
+    }
+}
+
+/// Restores the default action of the signals an interactive shell ignores
+/// or catches, for a child that is about to run a command. Async-signal-safe.
+pub fn reset_signals() {
+    // SAFETY: only async-signal-safe libc calls
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+        libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+        libc::signal(libc::SIGTTIN, libc::SIG_DFL);
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+    }
+}
@@ -0,0 +1,395 @@
+// test.rs
+//
+// Conditions for `if` and loops: the `test` and `[` builtins and the `[[ ]]`
+// command. They share the file, string and integer checks below. `test`
+// sees its operands after the usual word splitting and globbing, so they
+// need quoting; `[[ ]]` expands its words without either, and on top of that
+// matches `==` against a glob pattern and `=~` against a regular expression.
+
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+use regex::Regex;
+
+use crate::arith;
+use crate::expand;
+use crate::ls;
+use crate::parser::Conditional;
+use crate::pattern;
+use crate::state::Shell;
+
+/// Operators taking a single operand.
+const UNARY: [&str; 16] = [
+    "-e", "-f", "-d", "-L", "-h", "-r", "-w", "-x", "-s", "-p", "-S", "-b", "-c", "-t", "-z", "-n",
+];
+
+/// Operators comparing two operands. `test` also has `-a` and `-o`.
+const BINARY: [&str; 15] = [
+    "=", "==", "!=", "<", ">", "=~", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+pub fn is_unary(op: &str) -> bool {
+    UNARY.contains(&op)
+}
+
+pub fn is_binary(op: &str) -> bool {
+    BINARY.contains(&op)
+}
+
+/// `test expr` or `[ expr ]`. Returns 0 if the expression is true and 1 if
+/// it is false; errors are reported with status 2.
+pub fn test(command: &str, args: Vec<&str>) -> Result<i32, String> {
+    let args = if command == "[" {
+        match args.split_last() {
+            Some((&"]", args)) => args,
+            _ => return Err("[: missing `]'".to_string()),
+        }
+    } else {
+        &args[..]
+    };
+    let result = evaluate(args).map_err(|e| format!("{}: {}", command, e))?;
+    Ok(i32::from(!result))
+}
+
+/// Evaluates `test` arguments. Up to four arguments follow the POSIX rules,
+/// which decide by count so that `[ -f = -f ]` or `[ ! = x ]` compare
+/// strings; longer expressions are parsed with `!`, `-a`, `-o` and `( )`.
+fn evaluate(args: &[&str]) -> Result<bool, String> {
+    match *args {
+        [] => Ok(false),
+        [arg] => Ok(!arg.is_empty()),
+        ["!", arg] => Ok(arg.is_empty()),
+        [op, operand] if is_unary(op) => unary(op, operand),
+        [op, _] => Err(format!("{}: unary operator expected", op)),
+        [left, op, right] if is_binary(op) && op != "=~" => binary(left, op, right),
+        [left, "-a", right] => Ok(!left.is_empty() && !right.is_empty()),
+        [left, "-o", right] => Ok(!left.is_empty() || !right.is_empty()),
+        ["!", ..] if args.len() <= 4 => Ok(!evaluate(&args[1..])?),
+        ["(", arg, ")"] => Ok(!arg.is_empty()),
+        [_, op, _] => Err(format!("{}: binary operator expected", op)),
+        ["(", _, _, ")"] => evaluate(&args[1..3]),
+        _ => {
+            let mut parser = Parser { args, pos: 0 };
+            let result = parser.or()?;
+            match parser.args.get(parser.pos) {
+                Some(_) => Err("too many arguments".to_string()),
+                None => Ok(result),
+            }
+        }
+    }
+}
+
+/// Parses a long `test` expression. Every operand is evaluated, as the
+/// checks have no side effects.
+struct Parser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.pos + offset).copied()
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.peek(0) == Some("-o") {
+            self.pos += 1;
+            result |= self.and()?;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.peek(0) == Some("-a") {
+            self.pos += 1;
+            result &= self.not()?;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.peek(0) == Some("!") && self.peek(1).is_some() {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let Some(arg) = self.peek(0) else {
+            return Err("argument expected".to_string());
+        };
+        if let (Some(op), Some(right)) = (self.peek(1), self.peek(2)) {
+            if is_binary(op) && op != "=~" {
+                self.pos += 3;
+                return binary(arg, op, right);
+            }
+        }
+        if arg == "(" {
+            self.pos += 1;
+            let result = self.or()?;
+            if self.peek(0) != Some(")") {
+                return Err("`)' expected".to_string());
+            }
+            self.pos += 1;
+            return Ok(result);
+        }
+        if is_unary(arg) {
+            if let Some(operand) = self.peek(1) {
+                self.pos += 2;
+                return unary(arg, operand);
+            }
+        }
+        self.pos += 1;
+        Ok(!arg.is_empty())
+    }
+}
+
+/// Evaluates `[[ expr ]]`. `&&` and `||` only expand the right side when
+/// the left one does not decide the result.
+pub fn conditional(shell: &mut Shell, expr: &Conditional) -> Result<bool, String> {
+    match expr {
+        Conditional::Not(expr) => Ok(!conditional(shell, expr)?),
+        Conditional::And(left, right) => {
+            Ok(conditional(shell, left)? && conditional(shell, right)?)
+        }
+        Conditional::Or(left, right) => Ok(conditional(shell, left)? || conditional(shell, right)?),
+        Conditional::Unary(op, word) => {
+            let operand = expand::expand_word(shell, word)?;
+            unary(op, &operand)
+        }
+        Conditional::Binary(left, op, right) => {
+            let left = expand::expand_word(shell, left)?;
+            match op.as_str() {
+                "=" | "==" | "!=" => {
+                    let pattern = expand::expand_pattern(shell, right)?;
+                    Ok(pattern::matches(&pattern, &left) == (op != "!="))
+                }
+                "=~" => {
+                    let regex = expand::expand_regex(shell, right)?;
+                    let regex = Regex::new(&regex)
+                        .map_err(|_| format!("[[: {}: invalid regular expression", regex))?;
+                    Ok(regex.is_match(&left))
+                }
+                // Integer operands may be arithmetic expressions, as in `(( ))`
+                "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+                    let right = expand::expand_word(shell, right)?;
+                    let left = arith::evaluate(shell, &left)?;
+                    let right = arith::evaluate(shell, &right)?;
+                    Ok(compare_integers(left, op, right))
+                }
+                _ => {
+                    let right = expand::expand_word(shell, right)?;
+                    binary(&left, op, &right)
+                }
+            }
+        }
+        Conditional::Word(word) => Ok(!expand::expand_word(shell, word)?.is_empty()),
+    }
+}
+
+/// Checks a file or string with a unary operator such as `-f` or `-z`.
+fn unary(op: &str, operand: &str) -> Result<bool, String> {
+    let metadata = || fs::metadata(operand).ok();
+    Ok(match op {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-e" => metadata().is_some(),
+        "-f" => metadata().is_some_and(|m| m.is_file()),
+        "-d" => metadata().is_some_and(|m| m.is_dir()),
+        // The link itself, not what it points to
+        "-L" | "-h" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        "-r" => metadata().is_some_and(|m| ls::has_permission(&m, 'r')),
+        "-w" => metadata().is_some_and(|m| ls::has_permission(&m, 'w')),
+        "-x" => metadata().is_some_and(|m| ls::has_permission(&m, 'x')),
+        "-s" => metadata().is_some_and(|m| m.size() > 0),
+        "-p" => metadata().is_some_and(|m| m.file_type().is_fifo()),
+        "-S" => metadata().is_some_and(|m| m.file_type().is_socket()),
+        "-b" => metadata().is_some_and(|m| m.file_type().is_block_device()),
+        "-c" => metadata().is_some_and(|m| m.file_type().is_char_device()),
+        "-t" => {
+            let fd = integer(operand)?;
+            // SAFETY: isatty only inspects the descriptor
+            i32::try_from(fd).is_ok_and(|fd| unsafe { libc::isatty(fd) } == 1)
+        }
+        _ => return Err(format!("{}: unary operator expected", op)),
+    })
+}
+
+/// Compares two strings, integers or files with a binary operator.
+fn binary(left: &str, op: &str, right: &str) -> Result<bool, String> {
+    Ok(match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+            compare_integers(integer(left)?, op, integer(right)?)
+        }
+        // A file that exists is newer than one that does not
+        "-nt" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left > right,
+            (left, right) => left.is_some() && right.is_none(),
+        },
+        "-ot" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left < right,
+            (left, right) => left.is_none() && right.is_some(),
+        },
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => return Err(format!("{}: binary operator expected", op)),
+    })
+}
+
+fn compare_integers(left: i64, op: &str, right: i64) -> bool {
+    match op {
+        "-eq" => left == right,
+        "-ne" => left != right,
+        "-lt" => left < right,
+        "-le" => left <= right,
+        "-gt" => left > right,
+        _ => left >= right,
+    }
+}
+
+fn integer(text: &str) -> Result<i64, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{}: integer expression expected", text))
+}
+
+/// When a file was last modified, to the nanosecond, as `ls -l` reads it.
+fn modified(path: &str) -> Option<(i64, i64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.mtime(), metadata.mtime_nsec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, Command, CompoundCommand};
+
+    fn eval(args: &[&str]) -> bool {
+        evaluate(args).unwrap()
+    }
+
+    /// Runs the `[[ ]]` command `input`.
+    fn double_bracket(shell: &mut Shell, input: &str) -> Result<bool, String> {
+        let list = parser::parse_input(input).unwrap().unwrap();
+        match &list.items[0].first.commands[0] {
+            Command::Compound(CompoundCommand::Conditional(expr), _) => conditional(shell, expr),
+            other => panic!("not [[ ]]: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn argument_counts() {
+        // None, one: whether it is non-empty, whatever it looks like
+        assert!(!eval(&[]));
+        assert!(eval(&["-f"]));
+        assert!(eval(&["!"]));
+        assert!(!eval(&[""]));
+        // Two: negation or a unary operator
+        assert!(eval(&["!", ""]));
+        assert!(!eval(&["!", "-f"]));
+        assert!(eval(&["-n", "x"]));
+        assert!(eval(&["-z", ""]));
+        assert_eq!(
+            evaluate(&["x", "y"]).unwrap_err(),
+            "x: unary operator expected"
+        );
+        // Three: a binary operator wins over `!` and parentheses
+        assert!(eval(&["-f", "=", "-f"]));
+        assert!(!eval(&["!", "=", "x"]));
+        assert!(eval(&["(", "=", "("]));
+        assert!(eval(&["!", "-z", "x"]));
+        assert!(eval(&["(", "x", ")"]));
+        assert!(eval(&["x", "-a", "y"]));
+        assert!(!eval(&["x", "-a", ""]));
+        assert!(eval(&["", "-o", "y"]));
+        assert_eq!(
+            evaluate(&["a", "b", "c"]).unwrap_err(),
+            "b: binary operator expected"
+        );
+        // Four: `!` before three arguments, or parentheses around two
+        assert!(eval(&["!", "a", "=", "b"]));
+        assert!(!eval(&["!", "!", "=", "!"]));
+        assert!(eval(&["(", "!", "", ")"]));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(eval(&["x", "-o", "", "-a", ""]));
+        assert!(!eval(&["(", "x", "-o", "", ")", "-a", ""]));
+        assert!(eval(&["", "-a", "x", "-o", "y"]));
+        assert!(eval(&["!", "", "-a", "!", "", "-a", "x"]));
+        assert!(eval(&["1", "-eq", "2", "-o", "a", "=", "a"]));
+        assert_eq!(
+            evaluate(&["a", "-a", "b", "c", "d"]).unwrap_err(),
+            "too many arguments"
+        );
+        assert_eq!(
+            evaluate(&["(", "a", "-a", "b"]).unwrap_err(),
+            "`)' expected"
+        );
+    }
+
+    #[test]
+    fn integers_and_strings() {
+        assert!(eval(&[" 10", "-gt", "9"]));
+        assert!(eval(&["10", "<", "9"]));
+        assert!(eval(&["-1", "-le", "-1"]));
+        assert_eq!(
+            evaluate(&["a", "-eq", "1"]).unwrap_err(),
+            "a: integer expression expected"
+        );
+    }
+
+    #[test]
+    fn regular_expressions() {
+        let mut shell = Shell::default();
+        shell.vars.set("x", "a.c");
+        assert_eq!(double_bracket(&mut shell, "[[ $x =~ ^a.c$ ]]"), Ok(true));
+        assert_eq!(double_bracket(&mut shell, "[[ abc =~ ^a.c$ ]]"), Ok(true));
+        assert_eq!(double_bracket(&mut shell, "[[ x$x =~ b|c ]]"), Ok(true));
+        assert_eq!(double_bracket(&mut shell, "[[ ab =~ ^a(b|c)$ ]]"), Ok(true));
+        // Quoted, a character matches only itself
+        assert_eq!(
+            double_bracket(&mut shell, "[[ abc =~ \"a.c\" ]]"),
+            Ok(false)
+        );
+        assert_eq!(double_bracket(&mut shell, "[[ a.c =~ 'a.c' ]]"), Ok(true));
+        // An expansion is regex syntax
+        shell.vars.set("re", "^[0-9]+$");
+        assert_eq!(double_bracket(&mut shell, "[[ 42 =~ $re ]]"), Ok(true));
+        assert_eq!(double_bracket(&mut shell, "[[ 4a =~ $re ]]"), Ok(false));
+        shell.vars.set("re", "a[");
+        assert_eq!(
+            double_bracket(&mut shell, "[[ a =~ $re ]]"),
+            Err("[[: a[: invalid regular expression".to_string())
+        );
+    }
+
+    #[test]
+    fn double_bracket_patterns() {
+        let mut shell = Shell::default();
+        shell.vars.set("f", "main.rs");
+        assert_eq!(double_bracket(&mut shell, "[[ $f == *.rs ]]"), Ok(true));
+        assert_eq!(
+            double_bracket(&mut shell, "[[ $f == \"*.rs\" ]]"),
+            Ok(false)
+        );
+        assert_eq!(
+            double_bracket(&mut shell, "[[ $f != *.rs || 2 -gt 1+0 ]]"),
+            Ok(true)
+        );
+        assert_eq!(
+            double_bracket(&mut shell, "[[ ! -n $unset && -z '' ]]"),
+            Ok(true)
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use crate::getopt::{Args, Opt, Spec};
+use crate::state::Shell;
+use crate::vars;
+
+pub const SPEC: Spec = Spec::new(
+    "Remove variables or functions.",
+    "name ...",
+    &[
+        Opt::flag('f', "", "remove functions"),
+        Opt::flag('v', "", "remove variables"),
+    ],
+);
+
+/// `unset [-v|-f] name...`: removes variables, or functions with `-f`. Without
+/// a flag a name that is not a variable is looked up as a function instead.
+pub fn unset(args: Args, shell: &mut Shell) -> Result<(), String> {
+    let functions = match (args.has('f'), args.has('v')) {
+        (true, true) => {
+            return Err("unset: cannot simultaneously unset a function and a variable".to_string())
+        }
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None,
+    };
+    let mut result = Ok(());
+    for name in args.operands {
+        if !vars::is_valid_name(name) {
+            result = Err(format!("unset: `{}': not a valid identifier", name));
+            continue;
+        }
+        match functions {
+            Some(true) => {
+                shell.functions.remove(name);
+            }
+            Some(false) => {
+                shell.vars.unset(name);
+            }
+            None => {
+                if shell.vars.unset(name).is_none() {
+                    shell.functions.remove(name);
+                }
+            }
+        }
+    }
+    result
+}
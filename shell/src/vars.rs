@@ -0,0 +1,149 @@
+// vars.rs
+//
+// Shell variables. Every variable lives in the shell; the ones marked as
+// exported are also passed in the environment of external commands. The
+// shell starts with a copy of its own environment, all of it exported.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::process::Command;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Variables {
+    // Sorted so that `set`, `export` and `env` list variables in a stable order
+    map: BTreeMap<String, Variable>,
+    /// One frame per running function, holding what its `local` variables
+    /// shadowed so they can be put back when it returns.
+    scopes: Vec<Vec<(String, Option<Variable>)>>,
+}
+
+impl Variables {
+    /// Variables taken from the process environment, all marked for export.
+    pub fn from_env() -> Variables {
+        let mut vars = Variables::default();
+        for (name, value) in env::vars() {
+            vars.map.insert(name, Variable { value, exported: true });
+        }
+        vars
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.map.get(name).map(|var| var.value.as_str())
+    }
+
+    pub fn var(&self, name: &str) -> Option<&Variable> {
+        self.map.get(name)
+    }
+
+    /// Sets `name` to `value`, keeping its export flag if it already exists.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.map.get_mut(name) {
+            Some(var) => var.value = value.to_string(),
+            None => {
+                self.map.insert(
+                    name.to_string(),
+                    Variable {
+                        value: value.to_string(),
+                        exported: false,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Marks `name` for export, creating it empty if it does not exist yet.
+    pub fn export(&mut self, name: &str) {
+        self.map
+            .entry(name.to_string())
+            .or_insert(Variable {
+                value: String::new(),
+                exported: false,
+            })
+            .exported = true;
+    }
+
+    pub fn unset(&mut self, name: &str) -> Option<Variable> {
+        self.map.remove(name)
+    }
+
+    /// Puts back a variable saved from `unset`, or removes `name` if there was none.
+    pub fn restore(&mut self, name: &str, saved: Option<Variable>) {
+        match saved {
+            Some(var) => {
+                self.map.insert(name.to_string(), var);
+            }
+            None => {
+                self.map.remove(name);
+            }
+        }
+    }
+
+    /// Starts the scope of a function call.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Ends the innermost function call, restoring every variable it made local.
+    pub fn pop_scope(&mut self) {
+        if let Some(frame) = self.scopes.pop() {
+            for (name, saved) in frame.into_iter().rev() {
+                self.restore(&name, saved);
+            }
+        }
+    }
+
+    /// Number of function calls in progress.
+    pub fn scope_depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Makes `name` local to the innermost function call. It starts out unset,
+    /// and the caller's variable comes back when the function returns. Returns
+    /// false outside of a function.
+    pub fn make_local(&mut self, name: &str) -> bool {
+        let Some(frame) = self.scopes.last_mut() else {
+            return false;
+        };
+        // Declaring the same local again keeps it as it is
+        if !frame.iter().any(|(saved, _)| saved == name) {
+            let saved = self.map.remove(name);
+            frame.push((name.to_string(), saved));
+        }
+        true
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.map.iter()
+    }
+
+    /// The `NAME=value` pairs passed to external commands.
+    pub fn exported(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.map
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .map(|(name, var)| (name, &var.value))
+    }
+
+    /// Gives `command` exactly the exported variables as its environment.
+    pub fn apply_env(&self, command: &mut Command) {
+        command.env_clear().envs(self.exported());
+    }
+}
+
+/// Whether `name` can be used as a variable name: a letter or underscore
+/// followed by letters, digits and underscores.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}